@@ -19,12 +19,21 @@ build --no-default-features
 echo Build with only std feature
 build --no-default-features --features std
 
+echo Build with only markdown feature
+build --no-default-features --features markdown
+
+echo Build with only serde feature
+build --no-default-features --features serde
+
 echo Build with only swash feature
 build --no-default-features --features swash
 
 echo Build with only syntect feature
 build --no-default-features --features syntect
 
+echo Build with only tree-sitter feature
+build --no-default-features --features tree-sitter
+
 echo Build with only vi feature
 build --no-default-features --features vi
 
@@ -33,3 +42,21 @@ build --all-features
 
 echo Run tests
 cargo test
+
+echo Run tests that need system fonts
+cargo test --all-features -- --ignored
+
+echo Run tests with markdown feature
+cargo test --features markdown
+
+echo Run tests with serde feature
+cargo test --features serde
+
+echo Run tests with vi feature
+cargo test --features vi
+
+echo Run tests with syntect feature
+cargo test --features syntect
+
+echo Run tests with tree-sitter feature
+cargo test --features tree-sitter
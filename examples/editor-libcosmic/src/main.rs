@@ -89,7 +89,7 @@ pub struct Window {
     #[cfg(not(feature = "vi"))]
     editor: Mutex<SyntaxEditor<'static>>,
     #[cfg(feature = "vi")]
-    editor: Mutex<cosmic_text::ViEditor<'static>>,
+    editor: Mutex<cosmic_text::ViEditor<'static, SyntaxEditor<'static>>>,
 }
 
 #[allow(dead_code)]
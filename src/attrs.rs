@@ -10,8 +10,15 @@ use core::ops::Range;
 pub use fontdb::{Family, Stretch, Style, Weight};
 use rangemap::RangeMap;
 
+use crate::Synthesis;
+
 /// Text color
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct Color(pub u32);
 
 impl Color {
@@ -54,6 +61,11 @@ impl Color {
 
 /// An owned version of [`Family`]
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
 pub enum FamilyOwned {
     Name(String),
     Serif,
@@ -87,6 +99,58 @@ impl FamilyOwned {
     }
 }
 
+/// An object inside of text, like an image or a widget, that is drawn by the application
+///
+/// Each grapheme of text with an object, usually U+FFFC OBJECT REPLACEMENT CHARACTER, is laid out
+/// as one glyph with the size of the object. It wraps and moves with the text around it, and the
+/// cursor moves over it like any other grapheme. The glyph is found in [`crate::LayoutRun`] with
+/// [`crate::LayoutGlyph::object_opt`] set, and is not drawn by this library.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InlineObject {
+    /// Identifier of the object, chosen by the application
+    pub id: usize,
+    /// Width in pixels
+    pub width: f32,
+    /// Height above the baseline in pixels
+    pub ascent: f32,
+    /// Height below the baseline in pixels
+    pub descent: f32,
+}
+
+impl InlineObject {
+    /// Create a new inline object with an identifier and size
+    pub const fn new(id: usize, width: f32, ascent: f32, descent: f32) -> Self {
+        Self {
+            id,
+            width,
+            ascent,
+            descent,
+        }
+    }
+}
+
+// Sizes are compared by their bits, so that attributes with objects can be used as keys
+impl PartialEq for InlineObject {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+            && self.width.to_bits() == other.width.to_bits()
+            && self.ascent.to_bits() == other.ascent.to_bits()
+            && self.descent.to_bits() == other.descent.to_bits()
+    }
+}
+
+impl Eq for InlineObject {}
+
+impl core::hash::Hash for InlineObject {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+        self.width.to_bits().hash(state);
+        self.ascent.to_bits().hash(state);
+        self.descent.to_bits().hash(state);
+    }
+}
+
 /// Text attributes
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct Attrs<'a> {
@@ -97,6 +161,7 @@ pub struct Attrs<'a> {
     pub style: Style,
     pub weight: Weight,
     pub metadata: usize,
+    pub object_opt: Option<InlineObject>,
 }
 
 impl<'a> Attrs<'a> {
@@ -111,6 +176,7 @@ impl<'a> Attrs<'a> {
             style: Style::Normal,
             weight: Weight::NORMAL,
             metadata: 0,
+            object_opt: None,
         }
     }
 
@@ -150,13 +216,88 @@ impl<'a> Attrs<'a> {
         self
     }
 
-    /// Check if font matches
+    /// Set [InlineObject]
+    pub fn object(mut self, object: InlineObject) -> Self {
+        self.object_opt = Some(object);
+        self
+    }
+
+    /// Check if font matches exactly, see [`Self::match_distance`] for the nearest match
     pub fn matches(&self, face: &fontdb::FaceInfo) -> bool {
-        //TODO: smarter way of including emoji
-        face.post_script_name.contains("Emoji")
-            || (face.style == self.style
-                && face.weight == self.weight
-                && face.stretch == self.stretch)
+        face.style == self.style && face.weight == self.weight && face.stretch == self.stretch
+    }
+
+    /// Get how near a font face is to these attributes, where lower is nearer
+    ///
+    /// This follows the font matching algorithm of
+    /// [CSS Fonts Level 4](https://www.w3.org/TR/css-fonts-4/#font-style-matching). The stretch
+    /// is nearest first, then the style, and then the weight:
+    ///
+    /// - Narrower stretches are tried first for a normal or narrower stretch, and wider
+    ///   stretches are tried first for a wider stretch
+    /// - Italic falls back to oblique and then normal, oblique falls back to italic and then
+    ///   normal, and normal falls back to oblique and then italic
+    /// - For a weight from 400 to 500, heavier weights up to 500 are tried first, then lighter
+    ///   weights, then weights above 500. Lighter weights are tried first for a weight below
+    ///   400, and heavier weights are tried first for a weight above 500.
+    pub fn match_distance(&self, face: &fontdb::FaceInfo) -> (u16, u8, u16) {
+        let stretch = {
+            let (desired, stretch) = (self.stretch.to_number(), face.stretch.to_number());
+            let narrower_first = desired <= Stretch::Normal.to_number();
+            if stretch == desired || (stretch < desired) == narrower_first {
+                desired.abs_diff(stretch)
+            } else {
+                // Past the widest stretch in the preferred direction
+                10 + desired.abs_diff(stretch)
+            }
+        };
+
+        let style = match (self.style, face.style) {
+            (desired, style) if desired == style => 0,
+            (Style::Italic, Style::Oblique)
+            | (Style::Oblique, Style::Italic)
+            | (Style::Normal, Style::Oblique) => 1,
+            _ => 2,
+        };
+
+        let weight = {
+            let (desired, weight) = (self.weight.0, face.weight.0);
+            // Weights are at most 1000 apart, so each group is after the one before it
+            let (group, distance) = if (400..=500).contains(&desired) {
+                if weight >= desired && weight <= 500 {
+                    (0, weight - desired)
+                } else if weight < desired {
+                    (1, desired - weight)
+                } else {
+                    (2, weight - 500)
+                }
+            } else if desired < 400 {
+                if weight <= desired {
+                    (0, desired - weight)
+                } else {
+                    (1, weight - desired)
+                }
+            } else if weight >= desired {
+                (0, weight - desired)
+            } else {
+                (1, desired - weight)
+            };
+            group * 1001 + distance
+        };
+
+        (stretch, style, weight)
+    }
+
+    /// Get the styles that have to be synthesized to use a font face with these attributes
+    ///
+    /// Bold is synthesized for a weight of 600 or more with a face lighter than 600, and italic
+    /// is synthesized for an italic or oblique style with a normal face. Fonts with color
+    /// glyphs are not synthesized, which is checked by [`crate::Font::has_color_glyphs`].
+    pub fn synthesis(&self, face: &fontdb::FaceInfo) -> Synthesis {
+        Synthesis {
+            bold: self.weight.0 >= 600 && face.weight.0 < 600,
+            italic: self.style != Style::Normal && face.style == Style::Normal,
+        }
     }
 
     /// Check if this set of attributes can be shaped with another
@@ -165,19 +306,26 @@ impl<'a> Attrs<'a> {
             && self.stretch == other.stretch
             && self.style == other.style
             && self.weight == other.weight
+            && self.object_opt == other.object_opt
     }
 }
 
 /// An owned version of [`Attrs`]
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AttrsOwned {
     //TODO: should this be an option?
     pub color_opt: Option<Color>,
     pub family_owned: FamilyOwned,
+    #[cfg_attr(feature = "serde", serde(with = "serde_stretch"))]
     pub stretch: Stretch,
+    #[cfg_attr(feature = "serde", serde(with = "serde_style"))]
     pub style: Style,
+    #[cfg_attr(feature = "serde", serde(with = "serde_weight"))]
     pub weight: Weight,
     pub metadata: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub object_opt: Option<InlineObject>,
 }
 
 impl AttrsOwned {
@@ -189,6 +337,7 @@ impl AttrsOwned {
             style: attrs.style,
             weight: attrs.weight,
             metadata: attrs.metadata,
+            object_opt: attrs.object_opt,
         }
     }
 
@@ -200,13 +349,14 @@ impl AttrsOwned {
             style: self.style,
             weight: self.weight,
             metadata: self.metadata,
+            object_opt: self.object_opt,
         }
     }
 }
 
 /// List of text attributes to apply to a line
 //TODO: have this clean up the spans when changes are made
-#[derive(Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct AttrsList {
     defaults: AttrsOwned,
     spans: RangeMap<usize, AttrsOwned>,
@@ -291,3 +441,242 @@ impl AttrsList {
         new
     }
 }
+
+/// Serialized as the defaults and a list of `(range, attrs)` spans, ordered by range
+#[cfg(feature = "serde")]
+impl serde::Serialize for AttrsList {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(serde::Serialize)]
+        #[serde(rename = "AttrsList")]
+        struct AttrsListRef<'a> {
+            defaults: &'a AttrsOwned,
+            spans: Vec<(&'a Range<usize>, &'a AttrsOwned)>,
+        }
+
+        AttrsListRef {
+            defaults: &self.defaults,
+            spans: self.spans(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for AttrsList {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        #[serde(rename = "AttrsList")]
+        struct AttrsListOwned {
+            defaults: AttrsOwned,
+            spans: Vec<(Range<usize>, AttrsOwned)>,
+        }
+
+        let owned = AttrsListOwned::deserialize(deserializer)?;
+        let mut attrs_list = Self {
+            defaults: owned.defaults,
+            spans: RangeMap::new(),
+        };
+        for (range, attrs) in owned.spans {
+            // Empty ranges would panic when inserted, and are skipped like in add_span
+            if range.start < range.end {
+                attrs_list.spans.insert(range, attrs);
+            }
+        }
+        Ok(attrs_list)
+    }
+}
+
+/// CSS keywords of [`Stretch`], like `"semi-condensed"`, shared by serialization and markup
+const STRETCH_KEYWORDS: [(Stretch, &str); 9] = [
+    (Stretch::UltraCondensed, "ultra-condensed"),
+    (Stretch::ExtraCondensed, "extra-condensed"),
+    (Stretch::Condensed, "condensed"),
+    (Stretch::SemiCondensed, "semi-condensed"),
+    (Stretch::Normal, "normal"),
+    (Stretch::SemiExpanded, "semi-expanded"),
+    (Stretch::Expanded, "expanded"),
+    (Stretch::ExtraExpanded, "extra-expanded"),
+    (Stretch::UltraExpanded, "ultra-expanded"),
+];
+
+/// Get the CSS keyword of a [`Stretch`]
+pub(crate) fn stretch_keyword(stretch: Stretch) -> &'static str {
+    STRETCH_KEYWORDS
+        .iter()
+        .find(|(value, _)| *value == stretch)
+        .map_or("normal", |(_, keyword)| keyword)
+}
+
+/// Parse a [`Stretch`] from its CSS keyword
+pub(crate) fn parse_stretch(keyword: &str) -> Option<Stretch> {
+    STRETCH_KEYWORDS
+        .iter()
+        .find(|(_, value)| *value == keyword)
+        .map(|(stretch, _)| *stretch)
+}
+
+/// Serialize [`Stretch`] as its CSS keyword, like `"semi-condensed"`
+#[cfg(feature = "serde")]
+mod serde_stretch {
+    #[cfg(not(feature = "std"))]
+    use alloc::string::String;
+    use serde::{
+        de::{Error, Unexpected},
+        Deserialize, Deserializer, Serializer,
+    };
+
+    use super::{parse_stretch, stretch_keyword, Stretch};
+
+    pub fn serialize<S: Serializer>(stretch: &Stretch, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(stretch_keyword(*stretch))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Stretch, D::Error> {
+        let keyword = String::deserialize(deserializer)?;
+        parse_stretch(&keyword).ok_or_else(|| {
+            D::Error::invalid_value(Unexpected::Str(&keyword), &"a CSS font-stretch keyword")
+        })
+    }
+}
+
+/// Serialize [`Style`] as its CSS keyword, like `"italic"`
+#[cfg(feature = "serde")]
+mod serde_style {
+    #[cfg(not(feature = "std"))]
+    use alloc::string::String;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use super::Style;
+
+    const KEYWORDS: [&str; 3] = ["normal", "italic", "oblique"];
+
+    pub fn serialize<S: Serializer>(style: &Style, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match style {
+            Style::Normal => KEYWORDS[0],
+            Style::Italic => KEYWORDS[1],
+            Style::Oblique => KEYWORDS[2],
+        })
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Style, D::Error> {
+        let keyword = String::deserialize(deserializer)?;
+        match keyword.as_str() {
+            "normal" => Ok(Style::Normal),
+            "italic" => Ok(Style::Italic),
+            "oblique" => Ok(Style::Oblique),
+            _ => Err(D::Error::unknown_variant(&keyword, &KEYWORDS)),
+        }
+    }
+}
+
+/// Serialize [`Weight`] as its numeric value, like `700`
+#[cfg(feature = "serde")]
+mod serde_weight {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::Weight;
+
+    pub fn serialize<S: Serializer>(weight: &Weight, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u16(weight.0)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Weight, D::Error> {
+        u16::deserialize(deserializer).map(Weight)
+    }
+}
+
+/// Create a font face description without font data, for testing font matching
+#[cfg(test)]
+fn test_face(family: &str, stretch: Stretch, style: Style, weight: Weight) -> fontdb::FaceInfo {
+    fontdb::FaceInfo {
+        id: fontdb::ID::dummy(),
+        source: fontdb::Source::Binary(alloc::sync::Arc::new(Vec::<u8>::new())),
+        index: 0,
+        families: vec![(family.to_string(), fontdb::Language::English_UnitedStates)],
+        post_script_name: family.replace(' ', "-"),
+        style,
+        weight,
+        stretch,
+        monospaced: false,
+    }
+}
+
+#[test]
+fn test_match_distance() {
+    let family = "Test Sans";
+    let faces = [
+        test_face(family, Stretch::Normal, Style::Normal, Weight::NORMAL),
+        test_face(family, Stretch::Normal, Style::Normal, Weight::BOLD),
+        test_face(family, Stretch::Normal, Style::Normal, Weight::EXTRA_LIGHT),
+        test_face(family, Stretch::Normal, Style::Italic, Weight::NORMAL),
+        test_face(
+            family,
+            Stretch::SemiCondensed,
+            Style::Normal,
+            Weight::NORMAL,
+        ),
+    ];
+
+    let nearest = |attrs: Attrs| {
+        let face = faces
+            .iter()
+            .min_by_key(|face| attrs.match_distance(face))
+            .expect("no faces");
+        (face.stretch, face.style, face.weight)
+    };
+    let attrs = Attrs::new().family(Family::Name(family));
+    assert_eq!(
+        nearest(attrs.weight(Weight(450))),
+        (Stretch::Normal, Style::Normal, Weight::NORMAL)
+    );
+    assert_eq!(nearest(attrs.weight(Weight(600))).2, Weight::BOLD);
+    assert_eq!(nearest(attrs.weight(Weight(300))).2, Weight::EXTRA_LIGHT);
+    // Italic faces are used for oblique text
+    assert_eq!(nearest(attrs.style(Style::Oblique)).1, Style::Italic);
+    // The nearest narrower face is used, and there are no wider faces
+    assert_eq!(
+        nearest(attrs.stretch(Stretch::Condensed)).0,
+        Stretch::SemiCondensed
+    );
+    assert_eq!(
+        nearest(attrs.stretch(Stretch::SemiExpanded)).0,
+        Stretch::Normal
+    );
+
+    // Styles missing from the face are synthesized
+    let regular = &faces[0];
+    assert_eq!(attrs.synthesis(regular), Synthesis::new());
+    let synthesis = attrs
+        .weight(Weight::BOLD)
+        .style(Style::Oblique)
+        .synthesis(regular);
+    assert!(synthesis.bold && synthesis.italic);
+    assert_eq!(
+        attrs.weight(Weight::BOLD).synthesis(&faces[1]),
+        Synthesis::new()
+    );
+
+    // Matches have the nearest face of each family
+    let mut db = fontdb::Database::new();
+    for face in faces {
+        db.push_face_info(face);
+    }
+    db.push_face_info(test_face(
+        "Other",
+        Stretch::Normal,
+        Style::Normal,
+        Weight::BOLD,
+    ));
+    let mut font_system = crate::FontSystem::new_with_locale_and_db("en-US".to_string(), db);
+    let ids = font_system.get_font_matches(attrs.weight(Weight(450)));
+    let faces: Vec<&fontdb::FaceInfo> = ids
+        .iter()
+        .filter_map(|id| font_system.db().face(*id))
+        .collect();
+    assert_eq!(faces.len(), 2);
+    let face = faces
+        .iter()
+        .find(|face| face.families[0].0 == family)
+        .expect("no match");
+    assert_eq!(face.weight, Weight::NORMAL);
+}
@@ -1,22 +1,21 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 #[cfg(not(feature = "std"))]
-use alloc::{
-    string::{String, ToString},
-    vec::Vec,
-};
-use core::{cmp, fmt};
+use alloc::{format, string::String, vec::Vec};
+use core::{cell::Cell, cmp, fmt, ops::Range};
 use unicode_segmentation::UnicodeSegmentation;
 
 #[cfg(feature = "swash")]
 use crate::Color;
 use crate::{
-    Attrs, AttrsList, BorrowedWithFontSystem, BufferLine, FontSystem, LayoutGlyph, LayoutLine,
-    ShapeLine, Wrap,
+    parse_markup, to_markup, Attrs, AttrsList, BorrowedWithFontSystem, BufferLine, Exclusion,
+    FontSystem, LayoutGlyph, LayoutLine, LineBox, LineExtents, LineIter, MarkupError, ShapeLine,
+    Wrap,
 };
 
 /// Current cursor location
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Cursor {
     /// Text line the cursor is on
     pub line: usize,
@@ -45,6 +44,11 @@ impl Cursor {
 
 /// Whether to associate cursors placed at a boundary between runs with the run before or after it.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum Affinity {
     Before,
     After,
@@ -103,16 +107,24 @@ impl LayoutCursor {
 pub struct LayoutRun<'a> {
     /// The index of the original text line
     pub line_i: usize,
+    /// The index of this visual line within the original text line
+    pub layout_i: usize,
     /// The original text line
     pub text: &'a str,
     /// True if the original paragraph direction is RTL
     pub rtl: bool,
     /// The array of layout glyphs to draw
     pub glyphs: &'a [LayoutGlyph],
-    /// Y offset of line
+    /// Y offset of the baseline of line
     pub line_y: f32,
+    /// Y offset of the top of line
+    pub line_top: f32,
+    /// Height of line, see [`Metrics::layout_line_height`]
+    pub line_height: f32,
     /// width of line
     pub line_w: f32,
+    /// The hidden lines, if this is the placeholder of a [`Fold`]
+    pub fold_opt: Option<Range<usize>>,
 }
 
 impl<'a> LayoutRun<'a> {
@@ -171,64 +183,239 @@ impl<'a> LayoutRun<'a> {
     }
 }
 
+/// A range of hidden lines in a [`Buffer`], displayed as a single placeholder line
+pub struct Fold {
+    lines: Range<usize>,
+    text: String,
+    shape: ShapeLine,
+    layout: Vec<LayoutLine>,
+}
+
+impl Fold {
+    fn new(
+        font_system: &mut FontSystem,
+        lines: Range<usize>,
+        text: String,
+        attrs: Attrs,
+        font_size: f32,
+        width: f32,
+    ) -> Self {
+        let shape = ShapeLine::new(font_system, &text, &AttrsList::new(attrs));
+        let layout = shape.layout(font_size, width, Wrap::None, None);
+        Self {
+            lines,
+            text,
+            shape,
+            layout,
+        }
+    }
+
+    /// The range of hidden lines
+    pub fn lines(&self) -> Range<usize> {
+        self.lines.clone()
+    }
+
+    /// The placeholder text displayed instead of the hidden lines
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    fn glyphs(&self) -> &[LayoutGlyph] {
+        self.layout
+            .first()
+            .map_or(&[], |layout_line| &layout_line.glyphs)
+    }
+
+    fn width(&self) -> f32 {
+        self.layout.first().map_or(0.0, |layout_line| layout_line.w)
+    }
+
+    fn line_height(&self, metrics: &Metrics) -> (f32, f32) {
+        match self.layout.first() {
+            Some(layout_line) => metrics.layout_line_height(layout_line),
+            None => (metrics.line_height, metrics.font_size),
+        }
+    }
+}
+
+/// Where the position of a [`Hit`] is, relative to the visible text
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HitPosition {
+    /// On a glyph
+    Glyph,
+    /// On a visual line, before the start of its text, which is on the right if it is RTL
+    BeforeStart,
+    /// On a visual line, past the end of its text, or on an empty line
+    PastEnd,
+    /// On the placeholder of a [`Fold`]
+    Fold,
+    /// Above the first visible line
+    Above,
+    /// Below the last visible line
+    Below,
+}
+
+/// A glyph with its bounding box, see [`Buffer::hit_test`] and [`Buffer::glyphs_in_rect`]
+#[derive(Debug)]
+pub struct HitGlyph<'a> {
+    /// The glyph
+    pub glyph: &'a LayoutGlyph,
+    /// The index of the original text line of the glyph
+    pub line_i: usize,
+    /// The index of the visual line of the glyph within the original text line
+    pub layout_i: usize,
+    /// X offset of the bounding box, the same as the hitbox of the glyph
+    pub x: f32,
+    /// Y offset of the bounding box, the top of the visual line
+    pub y: f32,
+    /// Width of the bounding box
+    pub w: f32,
+    /// Height of the bounding box, the line height
+    pub h: f32,
+}
+
+impl<'a> HitGlyph<'a> {
+    fn new(glyph: &'a LayoutGlyph, run: &LayoutRun) -> Self {
+        Self {
+            glyph,
+            line_i: run.line_i,
+            layout_i: run.layout_i,
+            x: glyph.x,
+            y: run.line_top,
+            w: glyph.w,
+            h: run.line_height,
+        }
+    }
+}
+
+/// The result of hit detection, see [`Buffer::hit_test`]
+#[derive(Debug)]
+pub struct Hit<'a> {
+    /// The cursor at the position, the same as returned by [`Buffer::hit`]
+    pub cursor: Cursor,
+    /// The index of the visual line within the original text line of the cursor
+    pub layout_i: usize,
+    /// Where the position is, relative to the text
+    pub position: HitPosition,
+    /// The glyph at the position, if [`Self::position`] is [`HitPosition::Glyph`]
+    pub glyph_opt: Option<HitGlyph<'a>>,
+}
+
+impl<'a> Hit<'a> {
+    fn new(cursor: Cursor, run: &LayoutRun, position: HitPosition) -> Self {
+        Self {
+            cursor,
+            layout_i: run.layout_i,
+            position,
+            glyph_opt: None,
+        }
+    }
+
+    /// Get the [`Attrs::metadata`] of the glyph at the position, if there is one
+    pub fn metadata_opt(&self) -> Option<usize> {
+        self.glyph_opt
+            .as_ref()
+            .map(|hit_glyph| hit_glyph.glyph.metadata)
+    }
+}
+
 /// An iterator of visible text lines, see [`LayoutRun`]
+#[derive(Clone)]
 pub struct LayoutRunIter<'b> {
     buffer: &'b Buffer,
     line_i: usize,
     layout_i: usize,
-    remaining_len: usize,
-    line_y: f32,
+    fold_i: usize,
+    /// Number of remaining runs, counted when first needed by [`Iterator::size_hint`]
+    remaining_len_opt: Cell<Option<usize>>,
+    /// Bottom of the previous run
+    line_top: f32,
+    /// Space after the paragraph of the previous run, if it was its last visual line
+    space_after: f32,
     total_layout: i32,
 }
 
 impl<'b> LayoutRunIter<'b> {
     pub fn new(buffer: &'b Buffer) -> Self {
-        let total_layout_lines: usize = buffer
-            .lines
-            .iter()
-            .map(|line| {
-                line.layout_opt()
-                    .as_ref()
-                    .map(|layout| layout.len())
-                    .unwrap_or_default()
-            })
-            .sum();
-        let top_cropped_layout_lines =
-            total_layout_lines.saturating_sub(buffer.scroll.try_into().unwrap_or_default());
-        let maximum_lines = if buffer.metrics.line_height == 0.0 {
-            0
-        } else {
-            (buffer.height / buffer.metrics.line_height) as i32
-        };
-        let bottom_cropped_layout_lines =
-            if top_cropped_layout_lines > maximum_lines.try_into().unwrap_or_default() {
-                maximum_lines.try_into().unwrap_or_default()
-            } else {
-                top_cropped_layout_lines
-            };
-
         Self {
             buffer,
             line_i: 0,
             layout_i: 0,
-            remaining_len: bottom_cropped_layout_lines,
-            line_y: buffer.metrics.y_offset(),
+            fold_i: 0,
+            remaining_len_opt: Cell::new(None),
+            line_top: 0.0,
+            space_after: 0.0,
             total_layout: 0,
         }
     }
+
+    /// Count a run being returned, if the remaining runs were counted
+    fn counted(&self, run: LayoutRun<'b>) -> Option<LayoutRun<'b>> {
+        if let Some(remaining_len) = self.remaining_len_opt.get() {
+            self.remaining_len_opt.set(Some(remaining_len - 1));
+        }
+        Some(run)
+    }
 }
 
 impl<'b> Iterator for LayoutRunIter<'b> {
     type Item = LayoutRun<'b>;
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.remaining_len, Some(self.remaining_len))
+        // The number of runs that fit depends on the height of each of them, so they are counted
+        // by running a copy of the iterator, only when the length is needed
+        let remaining_len = match self.remaining_len_opt.get() {
+            Some(some) => some,
+            None => {
+                let remaining_len = self.clone().count();
+                self.remaining_len_opt.set(Some(remaining_len));
+                remaining_len
+            }
+        };
+        (remaining_len, Some(remaining_len))
     }
 
     fn next(&mut self) -> Option<Self::Item> {
         while let Some(line) = self.buffer.lines.get(self.line_i) {
+            if let Some(fold) = self.buffer.folds.get(self.fold_i) {
+                if fold.lines.start == self.line_i {
+                    // Hidden lines are replaced by one placeholder line
+                    self.fold_i += 1;
+                    self.line_i = fold.lines.end;
+                    self.layout_i = 0;
+
+                    let scrolled = self.total_layout < self.buffer.scroll;
+                    self.total_layout += 1;
+                    if scrolled {
+                        continue;
+                    }
+
+                    let line_top = self.line_top + self.space_after;
+                    let (line_height, baseline) = fold.line_height(&self.buffer.metrics);
+                    self.line_top = line_top + line_height;
+                    self.space_after = 0.0;
+                    if self.line_top > self.buffer.height {
+                        return None;
+                    }
+
+                    return self.counted(LayoutRun {
+                        line_i: fold.lines.start,
+                        layout_i: 0,
+                        text: fold.text(),
+                        rtl: false,
+                        glyphs: fold.glyphs(),
+                        line_y: line_top + baseline,
+                        line_top,
+                        line_height,
+                        line_w: fold.width(),
+                        fold_opt: Some(fold.lines()),
+                    });
+                }
+            }
+
             let shape = line.shape_opt().as_ref()?;
             let layout = line.layout_opt().as_ref()?;
+            let paragraph_attrs = line.paragraph_attrs();
             while let Some(layout_line) = layout.get(self.layout_i) {
                 self.layout_i += 1;
 
@@ -238,21 +425,31 @@ impl<'b> Iterator for LayoutRunIter<'b> {
                     continue;
                 }
 
-                self.line_y += self.buffer.metrics.line_height;
-                if self.line_y - self.buffer.metrics.y_offset() > self.buffer.height {
+                let mut line_top = self.line_top + self.space_after;
+                if self.layout_i == 1 {
+                    line_top += paragraph_attrs.space_before;
+                }
+                let (line_height, baseline) = self.buffer.metrics.layout_line_height(layout_line);
+                self.line_top = line_top + line_height;
+                self.space_after = 0.0;
+                if self.layout_i == layout.len() {
+                    self.space_after = paragraph_attrs.space_after;
+                }
+                if self.line_top > self.buffer.height {
                     return None;
                 }
 
-                return self.remaining_len.checked_sub(1).map(|num| {
-                    self.remaining_len = num;
-                    LayoutRun {
-                        line_i: self.line_i,
-                        text: line.text(),
-                        rtl: shape.rtl,
-                        glyphs: &layout_line.glyphs,
-                        line_y: self.line_y,
-                        line_w: layout_line.w,
-                    }
+                return self.counted(LayoutRun {
+                    line_i: self.line_i,
+                    layout_i: self.layout_i - 1,
+                    text: line.text(),
+                    rtl: shape.rtl,
+                    glyphs: &layout_line.glyphs,
+                    line_y: line_top + baseline,
+                    line_top,
+                    line_height,
+                    line_w: layout_line.w,
+                    fold_opt: None,
                 });
             }
             self.line_i += 1;
@@ -267,11 +464,20 @@ impl<'b> ExactSizeIterator for LayoutRunIter<'b> {}
 
 /// Metrics of text
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Metrics {
     /// Font size in pixels
     pub font_size: f32,
     /// Line height in pixels
+    ///
+    /// Lines with glyphs that are taller than this are made taller to fit them.
     pub line_height: f32,
+    /// Use the ascent, descent and line gap of the fonts of each visual line as its height, like
+    /// `line-height: normal` in CSS, instead of `line_height`
+    ///
+    /// Visual lines without glyphs still have a height of `line_height`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub normal_line_height: bool,
 }
 
 impl Metrics {
@@ -279,19 +485,85 @@ impl Metrics {
         Self {
             font_size,
             line_height,
+            normal_line_height: false,
         }
     }
 
+    /// Set [`Self::normal_line_height`]
+    pub fn normal_line_height(mut self, normal_line_height: bool) -> Self {
+        self.normal_line_height = normal_line_height;
+        self
+    }
+
     pub fn scale(self, scale: f32) -> Self {
         Self {
             font_size: self.font_size * scale,
             line_height: self.line_height * scale,
+            normal_line_height: self.normal_line_height,
         }
     }
 
-    fn y_offset(&self) -> f32 {
-        self.font_size - self.line_height
+    /// Get the height of a visual line, and the distance from its top to its baseline
+    ///
+    /// The glyphs are centered in a line that is taller than them, with the space split
+    /// between above and below like the half-leading of CSS. The baseline of a line without
+    /// glyphs is `font_size` from its top.
+    pub fn layout_line_height(&self, layout_line: &LayoutLine) -> (f32, f32) {
+        self.extents_line_height(layout_line.extents())
     }
+
+    /// Get the height and baseline of a visual line from the extents of its glyphs, like
+    /// [`Self::layout_line_height`], before the line is laid out
+    pub fn extents_line_height(&self, extents: LineExtents) -> (f32, f32) {
+        let ascent = extents.max_ascent;
+        let descent = extents.max_descent;
+        if ascent + descent <= 0.0 {
+            return (self.line_height, self.font_size);
+        }
+
+        let height = if self.normal_line_height {
+            ascent + descent + extents.max_line_gap
+        } else {
+            self.line_height.max(ascent + descent)
+        };
+        (height, (height - ascent - descent) / 2.0 + ascent)
+    }
+}
+
+/// The contents of a [`Buffer`] without its shaping and layout caches, with a stable serialized
+/// representation for saving and loading documents
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct BufferContents {
+    /// Version of the representation, see [`Self::VERSION`]
+    #[serde(deserialize_with = "deserialize_version")]
+    pub version: u32,
+    /// Metrics of the buffer
+    pub metrics: Metrics,
+    /// Wrapping mode of the buffer
+    pub wrap: Wrap,
+    /// Lines of the buffer, with their text, line ending, attributes, and alignment
+    pub lines: Vec<BufferLine>,
+}
+
+#[cfg(feature = "serde")]
+impl BufferContents {
+    /// Current version of the representation, contents with a newer version fail to deserialize
+    pub const VERSION: u32 = 1;
+}
+
+#[cfg(feature = "serde")]
+fn deserialize_version<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    use serde::{de::Error, Deserialize};
+
+    let version = u32::deserialize(deserializer)?;
+    if version > BufferContents::VERSION {
+        return Err(D::Error::custom(format_args!(
+            "unsupported buffer contents version {}",
+            version
+        )));
+    }
+    Ok(version)
 }
 
 impl fmt::Display for Metrics {
@@ -300,6 +572,78 @@ impl fmt::Display for Metrics {
     }
 }
 
+/// Lay out a line with the top of its paragraph at `y`, flowing around the exclusions if there
+/// are any
+fn layout_line_at<'a>(
+    line: &'a mut BufferLine,
+    font_system: &mut FontSystem,
+    metrics: Metrics,
+    width: f32,
+    wrap: Wrap,
+    exclusions: &[Exclusion],
+    y: f32,
+) -> &'a [LayoutLine] {
+    if exclusions.is_empty() {
+        line.layout(font_system, metrics.font_size, width, wrap)
+    } else {
+        line.layout_at(
+            font_system,
+            metrics,
+            wrap,
+            y + line.paragraph_attrs().space_before,
+            |line_y, line_height| LineBox::around(exclusions, width, line_y, line_height),
+        )
+    }
+}
+
+/// A paragraph or fold placeholder laid out by [`Buffer::layout_paragraph`]
+struct LaidOutParagraph {
+    /// Index of the line after the paragraph or the hidden lines
+    next_line_i: usize,
+    /// Number of visual lines
+    layout_lines: usize,
+    /// Height, including the spacing of the paragraph
+    height: f32,
+    /// True if the line had to be shaped
+    reshaped: bool,
+}
+
+/// An iterator over the heights of visual lines, see [`Buffer::layout_line_heights`]
+struct LayoutLineHeights<'a> {
+    buffer: &'a Buffer,
+    line_i: usize,
+    layout_i: usize,
+}
+
+impl<'a> Iterator for LayoutLineHeights<'a> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let buffer = self.buffer;
+        let line = buffer.lines.get(self.line_i)?;
+        if let Some(fold) = buffer.fold_starting_at(self.line_i) {
+            self.line_i = fold.lines.end;
+            self.layout_i = 0;
+            return Some(fold.line_height(&buffer.metrics).0);
+        }
+
+        let layout = line.layout_opt().as_ref()?;
+        let layout_line = layout.get(self.layout_i)?;
+        let paragraph_attrs = line.paragraph_attrs();
+        let mut height = buffer.metrics.layout_line_height(layout_line).0;
+        if self.layout_i == 0 {
+            height += paragraph_attrs.space_before;
+        }
+        self.layout_i += 1;
+        if self.layout_i == layout.len() {
+            height += paragraph_attrs.space_after;
+            self.line_i += 1;
+            self.layout_i = 0;
+        }
+        Some(height)
+    }
+}
+
 /// A buffer of text that is shaped and laid out
 pub struct Buffer {
     /// [BufferLine]s (or paragraphs) of text in the buffer
@@ -311,6 +655,10 @@ pub struct Buffer {
     /// True if a redraw is requires. Set to false after processing
     redraw: bool,
     wrap: Wrap,
+    tab_width: u16,
+    expand_tabs: bool,
+    folds: Vec<Fold>,
+    exclusions: Vec<Exclusion>,
 }
 
 impl Buffer {
@@ -330,6 +678,10 @@ impl Buffer {
             scroll: 0,
             redraw: false,
             wrap: Wrap::Word,
+            tab_width: 4,
+            expand_tabs: false,
+            folds: Vec::new(),
+            exclusions: Vec::new(),
         };
         buffer.set_text(font_system, "", Attrs::new());
         buffer
@@ -350,11 +702,35 @@ impl Buffer {
         #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
         let instant = std::time::Instant::now();
 
-        for line in &mut self.lines {
-            if line.shape_opt().is_some() {
+        let mut y = 0.0;
+        let mut line_i = 0;
+        while line_i < self.lines.len() {
+            if self.fold_starting_at(line_i).is_none() {
+                let line = &mut self.lines[line_i];
+                if line.shape_opt().is_none() {
+                    if !self.exclusions.is_empty() {
+                        // The lines after a line that is not shaped cannot be positioned, they
+                        // are laid out again when they are reached
+                        for line in self.lines[line_i..].iter_mut() {
+                            line.reset_layout();
+                        }
+                        break;
+                    }
+                    line_i += 1;
+                    continue;
+                }
                 line.reset_layout();
-                line.layout(font_system, self.metrics.font_size, self.width, self.wrap);
             }
+
+            let paragraph = self.layout_paragraph(font_system, line_i, y);
+            y += paragraph.height;
+            line_i = paragraph.next_line_i;
+        }
+
+        for fold in &mut self.folds {
+            fold.layout = fold
+                .shape
+                .layout(self.metrics.font_size, self.width, Wrap::None, None);
         }
 
         self.redraw = true;
@@ -363,6 +739,47 @@ impl Buffer {
         log::debug!("relayout: {:?}", instant.elapsed());
     }
 
+    /// Lay out the line at `line_i` with the top of its paragraph at `y`, or the placeholder of
+    /// the fold starting there
+    ///
+    /// This is shared by everything that walks the lines from the top, so they agree on the
+    /// position of each line.
+    fn layout_paragraph(
+        &mut self,
+        font_system: &mut FontSystem,
+        line_i: usize,
+        y: f32,
+    ) -> LaidOutParagraph {
+        if let Some(fold) = self.fold_starting_at(line_i) {
+            // Hidden lines are not shaped, the placeholder takes one layout line
+            return LaidOutParagraph {
+                next_line_i: fold.lines.end,
+                layout_lines: 1,
+                height: fold.line_height(&self.metrics).0,
+                reshaped: false,
+            };
+        }
+
+        let line = &mut self.lines[line_i];
+        let reshaped = line.shape_opt().is_none();
+        let paragraph_attrs = line.paragraph_attrs();
+        let layout = layout_line_at(
+            line,
+            font_system,
+            self.metrics,
+            self.width,
+            self.wrap,
+            &self.exclusions,
+            y,
+        );
+        LaidOutParagraph {
+            next_line_i: line_i + 1,
+            layout_lines: layout.len(),
+            height: paragraph_attrs.height(layout, &self.metrics),
+            reshaped,
+        }
+    }
+
     /// Pre-shape lines in the buffer, up to `lines`, return actual number of layout lines
     pub fn shape_until(&mut self, font_system: &mut FontSystem, lines: i32) -> i32 {
         #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
@@ -370,16 +787,20 @@ impl Buffer {
 
         let mut reshaped = 0;
         let mut total_layout = 0;
-        for line in &mut self.lines {
+        let mut y = 0.0;
+        let mut line_i = 0;
+        while line_i < self.lines.len() {
             if total_layout >= lines {
                 break;
             }
 
-            if line.shape_opt().is_none() {
+            let paragraph = self.layout_paragraph(font_system, line_i, y);
+            if paragraph.reshaped {
                 reshaped += 1;
             }
-            let layout = line.layout(font_system, self.metrics.font_size, self.width, self.wrap);
-            total_layout += layout.len() as i32;
+            total_layout += paragraph.layout_lines as i32;
+            y += paragraph.height;
+            line_i = paragraph.next_line_i;
         }
 
         if reshaped > 0 {
@@ -398,22 +819,27 @@ impl Buffer {
 
         let mut reshaped = 0;
         let mut layout_i = 0;
-        for (line_i, line) in self.lines.iter_mut().enumerate() {
-            if line_i > cursor.line {
+        let mut y = 0.0;
+        let hidden_start_opt = self.fold_at(cursor.line).map(|fold| fold.lines.start);
+        let mut line_i = 0;
+        while line_i <= cursor.line && line_i < self.lines.len() {
+            if hidden_start_opt == Some(line_i) {
+                // Cursor is hidden, scroll to the placeholder
                 break;
             }
 
-            if line.shape_opt().is_none() {
+            let paragraph = self.layout_paragraph(font_system, line_i, y);
+            if paragraph.reshaped {
                 reshaped += 1;
             }
-            let layout = line.layout(font_system, self.metrics.font_size, self.width, self.wrap);
             if line_i == cursor.line {
                 let layout_cursor = self.layout_cursor(&cursor);
                 layout_i += layout_cursor.layout as i32;
                 break;
-            } else {
-                layout_i += layout.len() as i32;
             }
+            layout_i += paragraph.layout_lines as i32;
+            y += paragraph.height;
+            line_i = paragraph.next_line_i;
         }
 
         if reshaped > 0 {
@@ -426,7 +852,23 @@ impl Buffer {
         if layout_i < self.scroll {
             self.scroll = layout_i;
         } else if layout_i >= self.scroll + lines {
-            self.scroll = layout_i - (lines - 1);
+            // Scroll as little as possible to fit the cursor line at the bottom, by dropping
+            // lines from the top of the lines up to it until they fit
+            let mut top_heights = self.layout_line_heights(0);
+            let mut height = 0.0;
+            let mut scroll = 0;
+            for (i, layout_height) in self
+                .layout_line_heights(0)
+                .take(layout_i as usize + 1)
+                .enumerate()
+            {
+                height += layout_height;
+                while height > self.height && scroll < i as i32 {
+                    height -= top_heights.next().unwrap_or_default();
+                    scroll += 1;
+                }
+            }
+            self.scroll = cmp::min(scroll, layout_i);
         }
 
         self.shape_until_scroll(font_system);
@@ -488,8 +930,65 @@ impl Buffer {
         font_system: &mut FontSystem,
         line_i: usize,
     ) -> Option<&[LayoutLine]> {
-        let line = self.lines.get_mut(line_i)?;
-        Some(line.layout(font_system, self.metrics.font_size, self.width, self.wrap))
+        if line_i >= self.lines.len() {
+            return None;
+        }
+
+        // The position of the line only matters with exclusions, and is found by laying out the
+        // lines before it
+        let mut y = 0.0;
+        if !self.exclusions.is_empty() {
+            let mut i = 0;
+            while i < line_i {
+                let paragraph = self.layout_paragraph(font_system, i, y);
+                if paragraph.next_line_i > line_i {
+                    // The line is hidden by a fold
+                    break;
+                }
+                y += paragraph.height;
+                i = paragraph.next_line_i;
+            }
+        }
+
+        Some(layout_line_at(
+            &mut self.lines[line_i],
+            font_system,
+            self.metrics,
+            self.width,
+            self.wrap,
+            &self.exclusions,
+            y,
+        ))
+    }
+
+    /// Get the x position of the start of the text of a run if it were not empty, after the margin
+    /// and indent of its paragraph
+    #[cfg(feature = "swash")]
+    pub(crate) fn run_start_x(&self, run: &LayoutRun) -> f32 {
+        let paragraph_attrs = match self.lines.get(run.line_i) {
+            Some(line) if run.fold_opt.is_none() => line.paragraph_attrs(),
+            _ => return 0.0,
+        };
+        paragraph_attrs
+            .line_box(LineBox::new(0.0, self.width), run.layout_i, run.rtl)
+            .x
+    }
+
+    /// Get the current exclusions
+    pub fn exclusions(&self) -> &[Exclusion] {
+        &self.exclusions
+    }
+
+    /// Set the rectangles that text flows around, in pixels from the top of the first line
+    ///
+    /// Each visual line is laid out in the widest part of the buffer width that is not covered
+    /// by an exclusion overlapping it vertically. Fold placeholders are not moved.
+    pub fn set_exclusions(&mut self, font_system: &mut FontSystem, exclusions: Vec<Exclusion>) {
+        if exclusions != self.exclusions {
+            self.exclusions = exclusions;
+            self.relayout(font_system);
+            self.shape_until_scroll(font_system);
+        }
     }
 
     /// Get the current [`Metrics`]
@@ -525,6 +1024,26 @@ impl Buffer {
         }
     }
 
+    /// Get the number of columns between tab stops
+    pub fn tab_width(&self) -> u16 {
+        self.tab_width
+    }
+
+    /// Set the number of columns between tab stops, used by editors for indentation
+    pub fn set_tab_width(&mut self, tab_width: u16) {
+        self.tab_width = cmp::max(tab_width, 1);
+    }
+
+    /// True if editors indent with spaces instead of tab characters
+    pub fn expand_tabs(&self) -> bool {
+        self.expand_tabs
+    }
+
+    /// Set whether editors indent with spaces instead of tab characters
+    pub fn set_expand_tabs(&mut self, expand_tabs: bool) {
+        self.expand_tabs = expand_tabs;
+    }
+
     /// Get the current buffer dimensions (width, height)
     pub fn size(&self) -> (f32, f32) {
         (self.width, self.height)
@@ -556,17 +1075,62 @@ impl Buffer {
         }
     }
 
-    /// Get the number of lines that can be viewed in the buffer
+    /// Get the number of lines that can be viewed in the buffer, from the current scroll
+    /// location
+    ///
+    /// This includes the space before and after paragraphs that are laid out, see
+    /// [`BufferLine::paragraph_attrs`].
     pub fn visible_lines(&self) -> i32 {
-        (self.height / self.metrics.line_height) as i32
+        let mut lines = 0;
+        let mut height = 0.0;
+        for layout_height in self.layout_line_heights(self.scroll.try_into().unwrap_or_default()) {
+            if height + layout_height > self.height {
+                return lines;
+            }
+            height += layout_height;
+            lines += 1;
+        }
+        // Lines that are not laid out yet are assumed to have no spacing
+        lines + ((self.height - height) / self.metrics.line_height) as i32
+    }
+
+    /// Iterate over the height of each visual line that is laid out, in the order of the layout
+    /// runs and starting at the visual line `start`, with the space before a paragraph added to
+    /// its first line and the space after it added to its last line
+    fn layout_line_heights(&self, start: usize) -> LayoutLineHeights {
+        // Skip whole paragraphs before the one containing the start
+        let mut line_i = 0;
+        let mut layout_i = start;
+        while let Some(line) = self.lines.get(line_i) {
+            let (layout_lines, next_line_i) = match self.fold_starting_at(line_i) {
+                Some(fold) => (1, fold.lines.end),
+                None => match line.layout_opt() {
+                    Some(layout) => (layout.len(), line_i + 1),
+                    None => break,
+                },
+            };
+            if layout_i < layout_lines {
+                break;
+            }
+            layout_i -= layout_lines;
+            line_i = next_line_i;
+        }
+        LayoutLineHeights {
+            buffer: self,
+            line_i,
+            layout_i,
+        }
     }
 
     /// Set text of buffer, using provided attributes for each line by default
+    ///
+    /// The line ending of each line is kept, see [`BufferLine::ending`]
     pub fn set_text(&mut self, font_system: &mut FontSystem, text: &str, attrs: Attrs) {
         self.lines.clear();
-        for line in text.lines() {
-            self.lines
-                .push(BufferLine::new(line.to_string(), AttrsList::new(attrs)));
+        for (range, ending) in LineIter::new(text) {
+            let mut line = BufferLine::new(&text[range], AttrsList::new(attrs));
+            line.set_ending(ending);
+            self.lines.push(line);
         }
         // Make sure there is always one line
         if self.lines.is_empty() {
@@ -575,10 +1139,101 @@ impl Buffer {
         }
 
         self.scroll = 0;
+        self.folds.clear();
 
         self.shape_until_scroll(font_system);
     }
 
+    /// Get the contents of the buffer, for serialization
+    #[cfg(feature = "serde")]
+    pub fn contents(&self) -> BufferContents {
+        BufferContents {
+            version: BufferContents::VERSION,
+            metrics: self.metrics,
+            wrap: self.wrap,
+            lines: self
+                .lines
+                .iter()
+                .map(|line| {
+                    let mut copy = BufferLine::new(line.text(), line.attrs_list().clone());
+                    copy.set_ending(line.ending());
+                    copy.set_align(line.align());
+                    copy.set_wrap(line.wrap());
+                    copy.set_paragraph_attrs(line.paragraph_attrs());
+                    copy
+                })
+                .collect(),
+        }
+    }
+
+    /// Replace the contents of the buffer, for deserialization
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `contents.metrics.line_height` is zero.
+    #[cfg(feature = "serde")]
+    pub fn set_contents(&mut self, font_system: &mut FontSystem, contents: BufferContents) {
+        assert_ne!(contents.metrics.line_height, 0.0, "line height cannot be 0");
+
+        self.metrics = contents.metrics;
+        self.wrap = contents.wrap;
+        self.lines = contents.lines;
+        // Make sure there is always one line
+        if self.lines.is_empty() {
+            self.lines
+                .push(BufferLine::new(String::new(), AttrsList::new(Attrs::new())));
+        }
+
+        self.scroll = 0;
+        self.folds.clear();
+        self.redraw = true;
+
+        self.shape_until_scroll(font_system);
+    }
+
+    /// Set text of buffer from markup, see [`parse_markup`]
+    ///
+    /// ## Errors
+    ///
+    /// Returns a [`MarkupError`] if the markup is invalid, leaving the buffer unchanged.
+    pub fn set_markup(
+        &mut self,
+        font_system: &mut FontSystem,
+        markup: &str,
+        attrs: Attrs,
+    ) -> Result<(), MarkupError> {
+        self.lines = parse_markup(markup, attrs)?;
+
+        self.scroll = 0;
+        self.folds.clear();
+
+        self.shape_until_scroll(font_system);
+        Ok(())
+    }
+
+    /// Set text of buffer from CommonMark, returning its links, see [`crate::parse_markdown`]
+    #[cfg(feature = "markdown")]
+    pub fn set_markdown(
+        &mut self,
+        font_system: &mut FontSystem,
+        markdown: &str,
+        attrs: Attrs,
+    ) -> Vec<crate::MarkdownLink> {
+        let (lines, links) = crate::parse_markdown(markdown, attrs, self.metrics.font_size);
+        self.lines = lines;
+
+        self.scroll = 0;
+        self.folds.clear();
+
+        self.shape_until_scroll(font_system);
+        links
+    }
+
+    /// Get the text of the buffer as markup, see [`to_markup`]
+    pub fn to_markup(&self) -> String {
+        to_markup(&self.lines)
+    }
+
     /// True if a redraw is needed
     pub fn redraw(&self) -> bool {
         self.redraw
@@ -594,42 +1249,173 @@ impl Buffer {
         LayoutRunIter::new(self)
     }
 
+    /// Hide a range of lines, displaying a placeholder line such as "⋯ 42 lines" instead
+    ///
+    /// Returns false if the range is empty, out of bounds, or overlaps an existing fold. Use
+    /// [`Self::fold_with_text`] to provide the placeholder text, for example to translate it.
+    pub fn fold(&mut self, font_system: &mut FontSystem, lines: Range<usize>) -> bool {
+        let count = lines.end.saturating_sub(lines.start);
+        let text = if count == 1 {
+            String::from("⋯ 1 line")
+        } else {
+            format!("⋯ {} lines", count)
+        };
+        self.fold_with_text(font_system, lines, text)
+    }
+
+    /// Hide a range of lines, displaying `text` as a placeholder line instead, see [`Self::fold`]
+    pub fn fold_with_text<T: Into<String>>(
+        &mut self,
+        font_system: &mut FontSystem,
+        lines: Range<usize>,
+        text: T,
+    ) -> bool {
+        if lines.start >= lines.end || lines.end > self.lines.len() {
+            return false;
+        }
+
+        let fold_i = self
+            .folds
+            .partition_point(|fold| fold.lines.end <= lines.start);
+        if let Some(fold) = self.folds.get(fold_i) {
+            if fold.lines.start < lines.end {
+                return false;
+            }
+        }
+
+        let attrs = self.lines[lines.start].attrs_list().defaults();
+        let fold = Fold::new(
+            font_system,
+            lines,
+            text.into(),
+            attrs,
+            self.metrics.font_size,
+            self.width,
+        );
+        self.folds.insert(fold_i, fold);
+        self.redraw = true;
+        true
+    }
+
+    /// Show the lines of the fold containing `line_i`, returns false if there was none
+    pub fn unfold(&mut self, line_i: usize) -> bool {
+        match self.fold_index(line_i) {
+            Some(fold_i) => {
+                self.folds.remove(fold_i);
+                self.redraw = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Show all hidden lines
+    pub fn unfold_all(&mut self) {
+        if !self.folds.is_empty() {
+            self.folds.clear();
+            self.redraw = true;
+        }
+    }
+
+    /// Get the current folds, sorted by line
+    pub fn folds(&self) -> &[Fold] {
+        &self.folds
+    }
+
+    /// Get the fold hiding `line_i`, if any
+    pub fn fold_at(&self, line_i: usize) -> Option<&Fold> {
+        self.fold_index(line_i).map(|fold_i| &self.folds[fold_i])
+    }
+
+    fn fold_starting_at(&self, line_i: usize) -> Option<&Fold> {
+        self.fold_at(line_i)
+            .filter(|fold| fold.lines.start == line_i)
+    }
+
+    fn fold_index(&self, line_i: usize) -> Option<usize> {
+        let fold_i = self.folds.partition_point(|fold| fold.lines.end <= line_i);
+        self.folds
+            .get(fold_i)
+            .filter(|fold| fold.lines.contains(&line_i))
+            .map(|_| fold_i)
+    }
+
+    /// Update folds after the lines `start_line..=old_end_line` were replaced by the lines
+    /// `start_line..=new_end_line`
+    ///
+    /// Folds after the edit are moved by the change in line count, folds touching the edited
+    /// lines are removed. [`Editor`](crate::Editor) calls this for every change it makes.
+    pub fn edit_folds(&mut self, start_line: usize, old_end_line: usize, new_end_line: usize) {
+        if self.folds.is_empty() {
+            return;
+        }
+
+        let len_before = self.folds.len();
+        self.folds
+            .retain(|fold| fold.lines.end <= start_line || fold.lines.start > old_end_line);
+        for fold in &mut self.folds {
+            if fold.lines.start > old_end_line {
+                fold.lines.start = fold.lines.start - old_end_line + new_end_line;
+                fold.lines.end = fold.lines.end - old_end_line + new_end_line;
+            }
+        }
+
+        if self.folds.len() != len_before || old_end_line != new_end_line {
+            self.redraw = true;
+        }
+    }
+
     /// Convert x, y position to Cursor (hit detection)
     pub fn hit(&self, x: f32, y: f32) -> Option<Cursor> {
+        self.hit_test(x, y).map(|hit| hit.cursor)
+    }
+
+    /// Convert x, y position to a [`Hit`] with the cursor, and the glyph at the position if
+    /// there is one (hit detection)
+    pub fn hit_test(&self, x: f32, y: f32) -> Option<Hit> {
         #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
         let instant = std::time::Instant::now();
 
-        let font_size = self.metrics.font_size;
-        let line_height = self.metrics.line_height;
-
-        let mut new_cursor_opt = None;
+        let mut hit_opt = None;
 
         let mut runs = self.layout_runs().peekable();
-        let mut first_run = true;
+        // Space between paragraphs belongs to the line below it
+        let mut prev_bottom_opt = None;
         while let Some(run) = runs.next() {
-            let line_y = run.line_y;
+            let line_top = run.line_top;
+            let line_bottom = run.line_top + run.line_height;
+            let first_run = prev_bottom_opt.is_none();
+            let hit_top = prev_bottom_opt.unwrap_or(line_top);
+            prev_bottom_opt = Some(line_bottom);
 
-            if first_run && y < line_y - font_size {
-                first_run = false;
+            if first_run && y < line_top {
                 let new_cursor = Cursor::new(run.line_i, 0);
-                new_cursor_opt = Some(new_cursor);
-            } else if y >= line_y - font_size && y < line_y - font_size + line_height {
+                hit_opt = Some(Hit::new(new_cursor, &run, HitPosition::Above));
+            } else if y >= hit_top && y < line_bottom {
+                if let Some(fold_lines) = &run.fold_opt {
+                    let new_cursor = Cursor::new(fold_lines.start, 0);
+                    hit_opt = Some(Hit::new(new_cursor, &run, HitPosition::Fold));
+                    break;
+                }
+
                 let mut new_cursor_glyph = run.glyphs.len();
                 let mut new_cursor_char = 0;
                 let mut new_cursor_affinity = Affinity::After;
+                let mut hit_glyph_opt = None;
 
                 let mut first_glyph = true;
 
                 'hit: for (glyph_i, glyph) in run.glyphs.iter().enumerate() {
                     if first_glyph {
                         first_glyph = false;
-                        if (run.rtl && x > glyph.x) || (!run.rtl && x < 0.0) {
+                        if (run.rtl && x > glyph.x) || (!run.rtl && x < glyph.x) {
                             new_cursor_glyph = 0;
                             new_cursor_char = 0;
                         }
                     }
                     if x >= glyph.x && x <= glyph.x + glyph.w {
                         new_cursor_glyph = glyph_i;
+                        hit_glyph_opt = Some(HitGlyph::new(glyph, &run));
 
                         let cluster = &run.text[glyph.start..glyph.end];
                         let total = cluster.grapheme_indices(true).count();
@@ -677,22 +1463,58 @@ impl Buffer {
                     }
                 }
 
-                new_cursor_opt = Some(new_cursor);
+                let position = if hit_glyph_opt.is_some() {
+                    HitPosition::Glyph
+                } else {
+                    // The start of the line is on the right if it is RTL
+                    let before_left = matches!(run.glyphs.first(), Some(glyph) if x < glyph.x);
+                    if before_left != run.rtl {
+                        HitPosition::BeforeStart
+                    } else {
+                        HitPosition::PastEnd
+                    }
+                };
+                let mut hit = Hit::new(new_cursor, &run, position);
+                hit.glyph_opt = hit_glyph_opt;
+                hit_opt = Some(hit);
 
                 break;
             } else if runs.peek().is_none() && y > run.line_y {
                 let mut new_cursor = Cursor::new(run.line_i, 0);
-                if let Some(glyph) = run.glyphs.last() {
+                if run.fold_opt.is_some() {
+                    // Placeholder glyphs do not map to the text of the line
+                } else if let Some(glyph) = run.glyphs.last() {
                     new_cursor = run.cursor_from_glyph_right(glyph);
                 }
-                new_cursor_opt = Some(new_cursor);
+                hit_opt = Some(Hit::new(new_cursor, &run, HitPosition::Below));
             }
         }
 
         #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
         log::trace!("click({}, {}): {:?}", x, y, instant.elapsed());
 
-        new_cursor_opt
+        hit_opt
+    }
+
+    /// Get the visible glyphs intersecting a rectangle, in the order of the layout runs
+    ///
+    /// Each glyph is as tall as its visual line, and the glyphs of fold placeholders are not
+    /// included.
+    pub fn glyphs_in_rect(&self, x: f32, y: f32, w: f32, h: f32) -> Vec<HitGlyph> {
+        let mut glyphs = Vec::new();
+        for run in self.layout_runs() {
+            let line_top = run.line_top;
+            let line_bottom = line_top + run.line_height;
+            if run.fold_opt.is_some() || line_bottom <= y || line_top >= y + h {
+                continue;
+            }
+            for glyph in run.glyphs.iter() {
+                if glyph.x < x + w && glyph.x + glyph.w > x {
+                    glyphs.push(HitGlyph::new(glyph, &run));
+                }
+            }
+        }
+        glyphs
     }
 
     /// Draw the buffer
@@ -708,6 +1530,10 @@ impl Buffer {
     {
         for run in self.layout_runs() {
             for glyph in run.glyphs.iter() {
+                if glyph.object_opt.is_some() {
+                    // Inline objects are drawn by the application
+                    continue;
+                }
                 let (cache_key, x_int, y_int) = (glyph.cache_key, glyph.x_int, glyph.y_int);
 
                 let glyph_color = match glyph.color_opt {
@@ -763,6 +1589,11 @@ impl<'a> BorrowedWithFontSystem<'a, Buffer> {
         self.inner.set_wrap(self.font_system, wrap);
     }
 
+    /// Set the rectangles that text flows around
+    pub fn set_exclusions(&mut self, exclusions: Vec<Exclusion>) {
+        self.inner.set_exclusions(self.font_system, exclusions);
+    }
+
     /// Set the current buffer dimensions
     pub fn set_size(&mut self, width: f32, height: f32) {
         self.inner.set_size(self.font_system, width, height);
@@ -773,6 +1604,28 @@ impl<'a> BorrowedWithFontSystem<'a, Buffer> {
         self.inner.set_text(self.font_system, text, attrs);
     }
 
+    /// Replace the contents of the buffer, for deserialization
+    #[cfg(feature = "serde")]
+    pub fn set_contents(&mut self, contents: BufferContents) {
+        self.inner.set_contents(self.font_system, contents);
+    }
+
+    /// Set text of buffer from markup, see [`parse_markup`]
+    pub fn set_markup(&mut self, markup: &str, attrs: Attrs) -> Result<(), MarkupError> {
+        self.inner.set_markup(self.font_system, markup, attrs)
+    }
+
+    /// Set text of buffer from CommonMark, returning its links, see [`crate::parse_markdown`]
+    #[cfg(feature = "markdown")]
+    pub fn set_markdown(&mut self, markdown: &str, attrs: Attrs) -> Vec<crate::MarkdownLink> {
+        self.inner.set_markdown(self.font_system, markdown, attrs)
+    }
+
+    /// Hide a range of lines, displaying a placeholder line such as "⋯ 42 lines" instead
+    pub fn fold(&mut self, lines: Range<usize>) -> bool {
+        self.inner.fold(self.font_system, lines)
+    }
+
     /// Draw the buffer
     #[cfg(feature = "swash")]
     pub fn draw<F>(&mut self, cache: &mut crate::SwashCache, color: Color, f: F)
@@ -782,3 +1635,354 @@ impl<'a> BorrowedWithFontSystem<'a, Buffer> {
         self.inner.draw(self.font_system, cache, color, f);
     }
 }
+
+#[cfg(feature = "serde")]
+#[test]
+#[ignore = "needs system fonts"]
+fn test_buffer_contents_serde() {
+    use crate::{Align, Color, Family, LineEnding, ParagraphAttrs, Style, Weight};
+
+    let mut font_system = FontSystem::new();
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(14.0, 20.0));
+    buffer.set_wrap(&mut font_system, Wrap::Glyph);
+    let attrs = Attrs::new().family(Family::Monospace);
+    buffer.set_text(&mut font_system, "Bold and red\r\nright\n", attrs);
+    let mut attrs_list = AttrsList::new(attrs);
+    attrs_list.add_span(0..4, attrs.weight(Weight::BOLD).style(Style::Italic));
+    attrs_list.add_span(9..12, attrs.color(Color::rgb(0xFF, 0, 0)));
+    buffer.lines[0].set_attrs_list(attrs_list);
+    buffer.lines[1].set_align(Some(Align::Right));
+    buffer.lines[1].set_wrap(Wrap::None);
+    buffer.lines[1].set_paragraph_attrs(ParagraphAttrs::new().hanging_indent(8.0));
+
+    let json = serde_json::to_string(&buffer.contents()).expect("failed to serialize");
+    assert!(json.starts_with(r#"{"version":1,"metrics":{"font_size":14.0,"line_height":20.0,"normal_line_height":false},"wrap":"glyph","lines":[{"text":"Bold and red","ending":"crlf","attrs_list":{"defaults":{"color_opt":null,"family_owned":"monospace","stretch":"normal","style":"normal","weight":400,"metadata":0,"object_opt":null},"spans":[[{"start":0,"end":4},"#));
+
+    let mut loaded = Buffer::new(&mut font_system, Metrics::new(1.0, 1.0));
+    loaded.set_contents(
+        &mut font_system,
+        serde_json::from_str(&json).expect("failed to deserialize"),
+    );
+    assert_eq!(loaded.metrics(), buffer.metrics());
+    assert_eq!(loaded.wrap(), Wrap::Glyph);
+    assert_eq!(loaded.lines.len(), buffer.lines.len());
+    for (loaded_line, line) in loaded.lines.iter().zip(buffer.lines.iter()) {
+        assert_eq!(loaded_line.text(), line.text());
+        assert_eq!(loaded_line.ending(), line.ending());
+        assert_eq!(loaded_line.attrs_list(), line.attrs_list());
+        assert_eq!(loaded_line.align(), line.align());
+        assert_eq!(loaded_line.wrap(), line.wrap());
+        assert_eq!(loaded_line.paragraph_attrs(), line.paragraph_attrs());
+    }
+    assert_eq!(loaded.lines[0].ending(), LineEnding::CrLf);
+    assert_eq!(loaded.lines[1].wrap(), Wrap::None);
+    assert_eq!(
+        serde_json::to_string(&loaded.contents()).expect("failed to serialize"),
+        json
+    );
+
+    let newer = json.replacen(r#""version":1"#, r#""version":2"#, 1);
+    assert!(serde_json::from_str::<BufferContents>(&newer).is_err());
+}
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_hit_test() {
+    let mut font_system = FontSystem::new();
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(14.0, 20.0));
+    buffer.set_size(&mut font_system, 1000.0, 100.0);
+    let attrs = Attrs::new();
+    buffer.set_text(&mut font_system, "plain link", attrs);
+    let mut attrs_list = AttrsList::new(attrs);
+    attrs_list.add_span(6..10, attrs.metadata(1));
+    buffer.lines[0].set_attrs_list(attrs_list);
+    buffer.shape_until_scroll(&mut font_system);
+
+    let run = buffer.layout_runs().next().expect("no layout runs");
+    let glyph = run
+        .glyphs
+        .iter()
+        .find(|glyph| glyph.start == 7)
+        .expect("no glyph");
+    let x = glyph.x + 1.0;
+    let y = run.line_y - 2.0;
+    let line_w = run.line_w;
+
+    let hit = buffer.hit_test(x, y).expect("no hit");
+    assert_eq!(hit.cursor, Cursor::new_with_affinity(0, 7, Affinity::After));
+    assert_eq!(hit.position, HitPosition::Glyph);
+    assert_eq!(hit.metadata_opt(), Some(1));
+    let hit_glyph = hit.glyph_opt.expect("no hit glyph");
+    assert_eq!((hit_glyph.line_i, hit_glyph.layout_i), (0, 0));
+    assert!(hit_glyph.y <= y && y < hit_glyph.y + hit_glyph.h);
+
+    let hit = buffer.hit_test(line_w + 10.0, y).expect("no hit");
+    assert_eq!(hit.cursor.index, 10);
+    assert_eq!(hit.position, HitPosition::PastEnd);
+    assert_eq!(hit.metadata_opt(), None);
+    assert_eq!(
+        buffer.hit_test(x, 90.0).map(|hit| hit.position),
+        Some(HitPosition::Below)
+    );
+
+    let glyphs = buffer.glyphs_in_rect(0.0, 0.0, x, 20.0);
+    assert_eq!(
+        glyphs.last().map(|hit_glyph| hit_glyph.glyph.start),
+        Some(7)
+    );
+    assert!(buffer.glyphs_in_rect(0.0, 30.0, 1000.0, 10.0).is_empty());
+}
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_folds() {
+    let mut font_system = FontSystem::new();
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(14.0, 20.0));
+    buffer.set_size(&mut font_system, 1000.0, 1000.0);
+    let text: Vec<String> = (0..10).map(|i| format!("line {}", i)).collect();
+    buffer.set_text(&mut font_system, &text.join("\n"), Attrs::new());
+    buffer.shape_until_scroll(&mut font_system);
+
+    assert!(buffer.fold(&mut font_system, 2..5));
+    assert!(!buffer.fold(&mut font_system, 4..6));
+    assert!(!buffer.fold(&mut font_system, 3..3));
+    assert!(!buffer.fold(&mut font_system, 9..11));
+    assert!(buffer.fold_with_text(&mut font_system, 7..8, "hidden"));
+    let folds: Vec<(Range<usize>, &str)> = buffer
+        .folds()
+        .iter()
+        .map(|fold| (fold.lines(), fold.text()))
+        .collect();
+    assert_eq!(folds, [(2..5, "⋯ 3 lines"), (7..8, "hidden")]);
+    assert_eq!(buffer.fold_at(3).map(Fold::lines), Some(2..5));
+    assert!(buffer.fold_at(5).is_none());
+
+    // Each fold is displayed as one placeholder run
+    let runs: Vec<(usize, Option<Range<usize>>, f32)> = buffer
+        .layout_runs()
+        .map(|run| (run.line_i, run.fold_opt, run.line_top))
+        .collect();
+    assert_eq!(
+        runs,
+        [
+            (0, None, 0.0),
+            (1, None, 20.0),
+            (2, Some(2..5), 40.0),
+            (5, None, 60.0),
+            (6, None, 80.0),
+            (7, Some(7..8), 100.0),
+            (8, None, 120.0),
+            (9, None, 140.0),
+        ]
+    );
+    let hit = buffer.hit_test(10.0, 50.0).expect("no hit");
+    assert_eq!((hit.cursor.line, hit.position), (2, HitPosition::Fold));
+
+    // Folds after an edit are moved, and folds touching it are removed
+    buffer.edit_folds(1, 1, 1);
+    assert_eq!(buffer.folds().len(), 2);
+    buffer.edit_folds(0, 0, 2);
+    assert_eq!(buffer.fold_at(4).map(Fold::lines), Some(4..7));
+    assert_eq!(buffer.fold_at(9).map(Fold::lines), Some(9..10));
+    buffer.edit_folds(5, 5, 5);
+    assert!(buffer.fold_at(4).is_none());
+    buffer.edit_folds(1, 3, 1);
+    assert_eq!(buffer.folds().len(), 1);
+    assert_eq!(buffer.fold_at(7).map(Fold::lines), Some(7..8));
+
+    assert!(buffer.unfold(7));
+    assert!(!buffer.unfold(7));
+    assert!(buffer.folds().is_empty());
+}
+
+#[test]
+fn test_line_box_around() {
+    assert_eq!(
+        LineBox::around(&[Exclusion::new(30.0, 0.0, 20.0, 20.0)], 100.0, 10.0, 20.0),
+        LineBox::new(50.0, 50.0)
+    );
+    assert_eq!(
+        LineBox::around(&[Exclusion::new(30.0, 0.0, 20.0, 20.0)], 100.0, 20.0, 20.0),
+        LineBox::new(0.0, 100.0)
+    );
+
+    // Overlapping exclusions cover the union of their ranges
+    let exclusions = [
+        Exclusion::new(60.0, 0.0, 30.0, 20.0),
+        Exclusion::new(20.0, 0.0, 50.0, 20.0),
+    ];
+    assert_eq!(
+        LineBox::around(&exclusions, 100.0, 0.0, 20.0),
+        LineBox::new(0.0, 20.0)
+    );
+
+    // A line that is covered entirely gets an empty box
+    assert_eq!(
+        LineBox::around(&[Exclusion::new(-10.0, 0.0, 120.0, 20.0)], 100.0, 0.0, 20.0),
+        LineBox::new(0.0, 0.0)
+    );
+}
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_exclusions() {
+    let mut font_system = FontSystem::new();
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(14.0, 20.0));
+    buffer.set_size(&mut font_system, 300.0, 200.0);
+    buffer.set_text(
+        &mut font_system,
+        "Text that flows around a rectangle covering the left half of the first two lines",
+        Attrs::new(),
+    );
+    let lines_before = buffer.layout_runs().len();
+    buffer.set_exclusions(
+        &mut font_system,
+        vec![Exclusion::new(0.0, 0.0, 150.0, 40.0)],
+    );
+
+    let runs: Vec<LayoutRun> = buffer.layout_runs().collect();
+    assert!(runs.len() > lines_before);
+    for run in runs.iter() {
+        let x_min = if run.layout_i < 2 { 150.0 } else { 0.0 };
+        for glyph in run.glyphs.iter() {
+            assert!(glyph.x >= x_min && glyph.x + glyph.w <= 300.0 + 0.5);
+        }
+    }
+    assert!(runs[2].glyphs[0].x < 150.0);
+
+    let first = &runs[0].glyphs[0];
+    let hit = buffer.hit(first.x - 10.0, runs[0].line_y - 2.0);
+    assert_eq!(hit, Some(Cursor::new_with_affinity(0, 0, Affinity::After)));
+
+    // Lines before one that is laid out directly are laid out to find its position
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(14.0, 20.0));
+    buffer.set_size(&mut font_system, 300.0, 20.0);
+    buffer.set_text(&mut font_system, "a\nb\nc\nd\ne", Attrs::new());
+    buffer.set_exclusions(
+        &mut font_system,
+        vec![Exclusion::new(0.0, 80.0, 150.0, 20.0)],
+    );
+    assert!(buffer.lines[3].layout_opt().is_none());
+    let layout = buffer
+        .line_layout(&mut font_system, 4)
+        .expect("line not found");
+    assert!(layout[0].glyphs[0].x >= 150.0);
+    let layout = buffer
+        .line_layout(&mut font_system, 3)
+        .expect("line not found");
+    assert!(layout[0].glyphs[0].x < 150.0);
+
+    // Lines taller than the line height are positioned with their real heights
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(14.0, 10.0));
+    buffer.set_size(&mut font_system, 300.0, 200.0);
+    buffer.set_text(
+        &mut font_system,
+        "Text that wraps onto a few lines, with only some of them next to the rectangle",
+        Attrs::new(),
+    );
+    let exclusion = Exclusion::new(0.0, 22.0, 150.0, 10.0);
+    buffer.set_exclusions(&mut font_system, vec![exclusion]);
+    let runs: Vec<LayoutRun> = buffer.layout_runs().collect();
+    assert_eq!(runs.len(), buffer.layout_runs().len());
+    assert!(runs.len() >= 3 && runs[1].line_height > 10.0);
+    for run in runs.iter() {
+        let overlaps = run.line_top < exclusion.y + exclusion.h
+            && run.line_top + run.line_height > exclusion.y;
+        assert_eq!(run.glyphs[0].x >= 150.0, overlaps);
+    }
+}
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_paragraph_attrs() {
+    use crate::ParagraphAttrs;
+
+    let mut font_system = FontSystem::new();
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(14.0, 20.0));
+    buffer.set_size(&mut font_system, 200.0, 100.0);
+    buffer.set_text(
+        &mut font_system,
+        "A paragraph long enough to wrap onto more than one line\nNext",
+        Attrs::new(),
+    );
+    let paragraph_attrs = ParagraphAttrs::new()
+        .first_line_indent(30.0)
+        .hanging_indent(10.0)
+        .margins(5.0, 5.0)
+        .spacing(8.0, 12.0);
+    buffer.lines[0].set_paragraph_attrs(paragraph_attrs);
+    buffer.shape_until_scroll(&mut font_system);
+
+    let runs: Vec<LayoutRun> = buffer.layout_runs().collect();
+    let next = runs
+        .iter()
+        .position(|run| run.line_i == 1)
+        .expect("no next line");
+    assert!(next >= 2);
+    assert_eq!(runs[0].glyphs[0].x, 35.0);
+    assert_eq!(runs[1].glyphs[0].x, 15.0);
+    for run in runs[..next].iter() {
+        let last = run.glyphs.last().expect("no glyphs");
+        assert!(last.x + last.w <= 195.0 + 0.5);
+    }
+    assert_eq!(runs[0].line_top, 8.0);
+    assert_eq!(
+        runs[next].line_top,
+        runs[next - 1].line_top + runs[next - 1].line_height + 12.0
+    );
+
+    // The space after the first paragraph belongs to the next line
+    let gap_y = runs[next - 1].line_top + runs[next - 1].line_height + 6.0;
+    let hit = buffer.hit(1000.0, gap_y).expect("no hit");
+    assert_eq!(hit.line, 1);
+
+    // Five lines would fit without spacing
+    assert!(buffer.visible_lines() < 5);
+    assert_eq!(buffer.layout_runs().len(), buffer.layout_runs().count());
+    let mut runs = buffer.layout_runs();
+    let len = runs.len();
+    runs.next();
+    assert_eq!(runs.len(), len - 1);
+
+    // Scrolling to a cursor fits the spacing of the lines before it
+    buffer.set_text(&mut font_system, "0\n1\n2\n3\n4\n5", Attrs::new());
+    for line in buffer.lines.iter_mut() {
+        line.set_paragraph_attrs(ParagraphAttrs::new().spacing(0.0, 5.0));
+    }
+    buffer.shape_until_cursor(&mut font_system, Cursor::new(5, 0));
+    assert_eq!(buffer.scroll(), 2);
+    assert_eq!(buffer.visible_lines(), 4);
+}
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_line_metrics() {
+    let mut font_system = FontSystem::new();
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(14.0, 20.0));
+    buffer.set_size(&mut font_system, 1000.0, 1000.0);
+    buffer.set_text(&mut font_system, "Hello\nworld", Attrs::new());
+
+    let runs: Vec<LayoutRun> = buffer.layout_runs().collect();
+    assert_eq!(runs.len(), 2);
+    let layout_line = &buffer.lines[0].layout_opt().as_ref().expect("no layout")[0];
+    let ascent = layout_line.max_ascent;
+    let descent = layout_line.max_descent;
+    let line_gap = layout_line.max_line_gap;
+    assert!(ascent > 0.0 && descent > 0.0);
+    assert_eq!(runs[0].line_top, 0.0);
+    assert_eq!(runs[0].line_height, 20.0);
+    assert_eq!(runs[1].line_top, 20.0);
+    for run in runs.iter() {
+        assert!(run.line_y - ascent >= run.line_top);
+        assert!(run.line_y + descent <= run.line_top + run.line_height);
+    }
+
+    // The line height is from the fonts in normal mode
+    buffer.set_metrics(
+        &mut font_system,
+        Metrics::new(14.0, 20.0).normal_line_height(true),
+    );
+    let run = buffer.layout_runs().next().expect("no layout runs");
+    assert!((run.line_height - (ascent + descent + line_gap)).abs() < 0.01);
+    assert!((run.line_y - (run.line_top + line_gap / 2.0 + ascent)).abs() < 0.01);
+}
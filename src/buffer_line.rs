@@ -1,17 +1,25 @@
 #[cfg(not(feature = "std"))]
 use alloc::{string::String, vec::Vec};
 
-use crate::{Align, AttrsList, FontSystem, LayoutLine, ShapeLine, Wrap};
+use crate::{
+    Align, AttrsList, FontSystem, LayoutLine, LineBox, LineEnding, Metrics, ParagraphAttrs,
+    ShapeLine, Wrap,
+};
 
 /// A line (or paragraph) of text that is shaped and laid out
 pub struct BufferLine {
     //TODO: make this not pub(crate)
     text: String,
+    ending: LineEnding,
     attrs_list: AttrsList,
     wrap: Wrap,
     align: Option<Align>,
+    paragraph_attrs: ParagraphAttrs,
     shape_opt: Option<ShapeLine>,
     layout_opt: Option<Vec<LayoutLine>>,
+    /// Vertical position of the cached layout, if it was laid out with [`Self::layout_at`]
+    layout_y_opt: Option<f32>,
+    metadata: Option<usize>,
 }
 
 impl BufferLine {
@@ -21,11 +29,15 @@ impl BufferLine {
     pub fn new<T: Into<String>>(text: T, attrs_list: AttrsList) -> Self {
         Self {
             text: text.into(),
+            ending: LineEnding::None,
             attrs_list,
             wrap: Wrap::Word,
             align: None,
+            paragraph_attrs: ParagraphAttrs::new(),
             shape_opt: None,
             layout_opt: None,
+            layout_y_opt: None,
+            metadata: None,
         }
     }
 
@@ -51,6 +63,18 @@ impl BufferLine {
         }
     }
 
+    /// Get line ending
+    pub fn ending(&self) -> LineEnding {
+        self.ending
+    }
+
+    /// Set line ending
+    ///
+    /// This does not reset shape or layout, as the line ending is not part of the text
+    pub fn set_ending(&mut self, ending: LineEnding) {
+        self.ending = ending;
+    }
+
     /// Consume this line, returning only its text contents as a String.
     pub fn into_text(self) -> String {
         self.text
@@ -114,12 +138,33 @@ impl BufferLine {
         }
     }
 
+    /// Get the indentation and spacing of the paragraph
+    pub fn paragraph_attrs(&self) -> ParagraphAttrs {
+        self.paragraph_attrs
+    }
+
+    /// Set the indentation and spacing of the paragraph
+    ///
+    /// Will reset layout if it differs from current paragraph attributes.
+    /// Returns true if the line was reset
+    pub fn set_paragraph_attrs(&mut self, paragraph_attrs: ParagraphAttrs) -> bool {
+        if paragraph_attrs != self.paragraph_attrs {
+            self.paragraph_attrs = paragraph_attrs;
+            self.reset_layout();
+            true
+        } else {
+            false
+        }
+    }
+
     /// Append line at end of this line
     ///
-    /// The wrap setting of the appended line will be lost
+    /// The wrap setting of the appended line will be lost, and the line ending of the appended
+    /// line will be used
     pub fn append(&mut self, other: Self) {
         let len = self.text.len();
         self.text.push_str(other.text());
+        self.ending = other.ending;
 
         if other.attrs_list.defaults() != self.attrs_list.defaults() {
             // If default formatting does not match, make a new span for it
@@ -137,21 +182,42 @@ impl BufferLine {
     }
 
     /// Split off new line at index
+    ///
+    /// Both lines will keep the line ending and paragraph attributes of this line
     pub fn split_off(&mut self, index: usize) -> Self {
         let text = self.text.split_off(index);
         let attrs_list = self.attrs_list.split_off(index);
         self.reset();
 
         let mut new = Self::new(text, attrs_list);
+        new.ending = self.ending;
         new.wrap = self.wrap;
+        new.paragraph_attrs = self.paragraph_attrs;
         new
     }
 
-    /// Reset shaping and layout information
+    /// Get highlighting metadata, which is cleared when the line is reset
+    ///
+    /// This is owned by the syntax highlighting editor wrapping the buffer, which uses it to
+    /// detect lines changed since they were last highlighted. `SyntaxEditor` stores the index
+    /// of the line's highlighting checkpoint, `TreeSitterEditor` only checks that it is set.
+    #[cfg(any(feature = "syntect", feature = "tree-sitter"))]
+    pub(crate) fn metadata(&self) -> Option<usize> {
+        self.metadata
+    }
+
+    /// Set highlighting metadata, see [`Self::metadata`]
+    #[cfg(any(feature = "syntect", feature = "tree-sitter"))]
+    pub(crate) fn set_metadata(&mut self, metadata: usize) {
+        self.metadata = Some(metadata);
+    }
+
+    /// Reset shaping, layout, and metadata
     //TODO: make this private
     pub fn reset(&mut self) {
         self.shape_opt = None;
         self.layout_opt = None;
+        self.metadata = None;
     }
 
     /// Reset only layout information
@@ -189,9 +255,53 @@ impl BufferLine {
         if self.layout_opt.is_none() {
             self.wrap = wrap;
             let align = self.align;
+            let paragraph_attrs = self.paragraph_attrs;
+            let shape = self.shape(font_system);
+            let layout = shape.layout_to_boxes(font_size, wrap, align, |layout_i, _| {
+                paragraph_attrs.line_box(LineBox::new(0.0, width), layout_i, shape.rtl)
+            });
+            self.layout_opt = Some(layout);
+            self.layout_y_opt = None;
+        }
+        self.layout_opt.as_ref().expect("layout not found")
+    }
+
+    /// Layout line at a vertical position `y`, with each visual line in the [`LineBox`] returned
+    /// by `line_box` for the top and height of that visual line, will cache results
+    ///
+    /// The first visual line is at `y`, which is below the space before the paragraph, and each
+    /// of the others is below the one above it, with the height from
+    /// [`Metrics::layout_line_height`]. As the height of a visual line is only known once its
+    /// glyphs are chosen, `line_box` gets the height of the line above it, or
+    /// [`Metrics::line_height`] for the first line. The margins and indents of the paragraph are
+    /// inside the box of each line. The cached layout is redone if it was done at another
+    /// position.
+    pub fn layout_at<F: FnMut(f32, f32) -> LineBox>(
+        &mut self,
+        font_system: &mut FontSystem,
+        metrics: Metrics,
+        wrap: Wrap,
+        y: f32,
+        mut line_box: F,
+    ) -> &[LayoutLine] {
+        if self.layout_opt.is_none() || self.layout_y_opt != Some(y) {
+            self.wrap = wrap;
+            let align = self.align;
+            let paragraph_attrs = self.paragraph_attrs;
             let shape = self.shape(font_system);
-            let layout = shape.layout(font_size, width, wrap, align);
+            let mut top = y;
+            let mut height = metrics.line_height;
+            let layout =
+                shape.layout_to_boxes(metrics.font_size, wrap, align, |layout_i, above_opt| {
+                    if let Some(above) = above_opt {
+                        height = metrics.extents_line_height(above).0;
+                        top += height;
+                    }
+                    let outer = line_box(top, height);
+                    paragraph_attrs.line_box(outer, layout_i, shape.rtl)
+                });
             self.layout_opt = Some(layout);
+            self.layout_y_opt = Some(y);
         }
         self.layout_opt.as_ref().expect("layout not found")
     }
@@ -201,3 +311,62 @@ impl BufferLine {
         &self.layout_opt
     }
 }
+
+/// Serialized as its text, line ending, attributes list, alignment, wrapping, and paragraph
+/// attributes, without the shaping and layout caches or metadata
+#[cfg(feature = "serde")]
+impl serde::Serialize for BufferLine {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(serde::Serialize)]
+        #[serde(rename = "BufferLine")]
+        struct BufferLineRef<'a> {
+            text: &'a str,
+            ending: LineEnding,
+            attrs_list: &'a AttrsList,
+            align: Option<Align>,
+            paragraph_attrs: ParagraphAttrs,
+            wrap: Wrap,
+        }
+
+        BufferLineRef {
+            text: &self.text,
+            ending: self.ending,
+            attrs_list: &self.attrs_list,
+            align: self.align,
+            paragraph_attrs: self.paragraph_attrs,
+            wrap: self.wrap,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for BufferLine {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        #[serde(rename = "BufferLine")]
+        struct BufferLineOwned {
+            text: String,
+            ending: LineEnding,
+            attrs_list: AttrsList,
+            #[serde(default)]
+            align: Option<Align>,
+            #[serde(default)]
+            paragraph_attrs: ParagraphAttrs,
+            #[serde(default = "default_wrap")]
+            wrap: Wrap,
+        }
+
+        fn default_wrap() -> Wrap {
+            Wrap::Word
+        }
+
+        let owned = BufferLineOwned::deserialize(deserializer)?;
+        let mut line = Self::new(owned.text, owned.attrs_list);
+        line.ending = owned.ending;
+        line.align = owned.align;
+        line.paragraph_attrs = owned.paragraph_attrs;
+        line.wrap = owned.wrap;
+        Ok(line)
+    }
+}
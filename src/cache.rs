@@ -13,6 +13,8 @@ pub struct CacheKey {
     pub x_bin: SubpixelBin,
     /// Binning of fractional Y offset
     pub y_bin: SubpixelBin,
+    /// Styles that are synthesized because the font does not have them
+    pub synthesis: Synthesis,
 }
 
 impl CacheKey {
@@ -31,6 +33,7 @@ impl CacheKey {
                 font_size_bits: font_size.to_bits(),
                 x_bin,
                 y_bin,
+                synthesis: Synthesis::new(),
             },
             x,
             y,
@@ -38,6 +41,36 @@ impl CacheKey {
     }
 }
 
+/// Styles that are synthesized when rasterizing a glyph, because the font that was chosen for it
+/// does not have the weight or style that was requested
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Synthesis {
+    /// Embolden the glyph outline
+    pub bold: bool,
+    /// Skew the glyph outline
+    pub italic: bool,
+}
+
+impl Synthesis {
+    /// Strength of synthetic emboldening in em units, which is also added to the advance of
+    /// emboldened glyphs
+    ///
+    /// The advance is widened when shaping, even if the glyphs are rasterized without
+    /// emboldening because of the `RenderOptions::synthetic_bold` option of the swash feature.
+    pub const EMBOLDEN_STRENGTH: f32 = 1.0 / 48.0;
+
+    /// Angle in degrees of the skew of synthetic italics, the default of a CSS oblique style
+    pub const SKEW_DEGREES: f32 = 14.0;
+
+    /// Create a new synthesis with no synthesized styles
+    pub const fn new() -> Self {
+        Self {
+            bold: false,
+            italic: false,
+        }
+    }
+}
+
 /// Binning of subpixel position for cache optimization
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum SubpixelBin {
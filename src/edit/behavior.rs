@@ -0,0 +1,113 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+#[cfg(not(feature = "std"))]
+use alloc::string::String;
+
+use crate::{Buffer, WordBoundaries};
+
+/// Optional editing behaviors of an editor, all disabled by default
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EditorBehavior {
+    /// Start new lines with the indentation of the line before them
+    pub auto_indent: bool,
+    /// Indent new lines one more level after an opening bracket, moving a matching closing
+    /// bracket after the cursor to its own line
+    pub bracket_indent: bool,
+    /// Insert the closing bracket or quote after an opening one, type over the closing ones
+    /// inserted this way, and remove both when deleting the opening one of such an empty pair
+    pub auto_close: bool,
+    /// Make [`Action::Home`](crate::Action::Home) move to the first non-whitespace character,
+    /// or to the start of the line if already there
+    pub smart_home: bool,
+    /// Rules used to find words when moving the cursor by word or double clicking
+    pub word_boundaries: WordBoundaries,
+}
+
+impl EditorBehavior {
+    /// Create a new [`EditorBehavior`], with all behaviors disabled
+    pub const fn new() -> Self {
+        Self {
+            auto_indent: false,
+            bracket_indent: false,
+            auto_close: false,
+            smart_home: false,
+            word_boundaries: WordBoundaries::Unicode,
+        }
+    }
+
+    /// Create a new [`EditorBehavior`], with all behaviors enabled as expected when editing code
+    pub const fn code() -> Self {
+        Self {
+            auto_indent: true,
+            bracket_indent: true,
+            auto_close: true,
+            smart_home: true,
+            word_boundaries: WordBoundaries::Subword,
+        }
+    }
+}
+
+impl Default for EditorBehavior {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+const QUOTES: [char; 3] = ['"', '\'', '`'];
+
+/// Get the closing bracket of an opening bracket
+pub(crate) fn closing_bracket(c: char) -> Option<char> {
+    BRACKETS
+        .iter()
+        .find(|(open, _)| *open == c)
+        .map(|(_, close)| *close)
+}
+
+/// Get the closing character of an opening bracket or quote
+pub(crate) fn closing_pair(c: char) -> Option<char> {
+    closing_bracket(c).or_else(|| QUOTES.contains(&c).then_some(c))
+}
+
+/// True if the character is a closing bracket or a quote
+pub(crate) fn is_closing(c: char) -> bool {
+    QUOTES.contains(&c) || BRACKETS.iter().any(|(_, close)| *close == c)
+}
+
+/// Get the length in bytes of the whitespace at the start of a line
+pub(crate) fn indent_len(text: &str) -> usize {
+    text.len() - text.trim_start().len()
+}
+
+/// Get the text inserted for one level of indentation, using the tab settings of the buffer
+pub(crate) fn indent_unit(buffer: &Buffer) -> String {
+    if buffer.expand_tabs() {
+        " ".repeat(buffer.tab_width().into())
+    } else {
+        String::from("\t")
+    }
+}
+
+/// Get the column of a byte index in a line, with tabs advancing to the next tab stop
+pub(crate) fn column(text: &str, index: usize, tab_width: u16) -> usize {
+    let tab_width = usize::from(tab_width);
+    text[..index].chars().fold(0, |column, c| {
+        if c == '\t' {
+            (column / tab_width + 1) * tab_width
+        } else {
+            column + 1
+        }
+    })
+}
+
+/// Get the length in bytes of one level of indentation at the start of a line
+pub(crate) fn outdent_len(text: &str, tab_width: u16) -> usize {
+    if text.starts_with('\t') {
+        return 1;
+    }
+    text.bytes()
+        .take(tab_width.into())
+        .take_while(|b| *b == b' ')
+        .count()
+}
@@ -0,0 +1,132 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+#[cfg(not(feature = "std"))]
+use alloc::{string::String, vec::Vec};
+
+use crate::{LineEnding, LineIter};
+
+/// Encoding of a text document
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TextEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+/// Format of a text document, used to save it the same way it was loaded
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DocumentFormat {
+    /// Text encoding
+    pub encoding: TextEncoding,
+    /// True if the document starts with a byte order mark
+    pub bom: bool,
+    /// Line ending to use between lines that do not have one, such as new lines
+    pub line_ending: LineEnding,
+}
+
+impl DocumentFormat {
+    /// Create a new document format, UTF-8 without a byte order mark and with `\n` line endings
+    pub const fn new() -> Self {
+        Self {
+            encoding: TextEncoding::Utf8,
+            bom: false,
+            line_ending: LineEnding::Lf,
+        }
+    }
+
+    /// Decode a document, detecting its format
+    ///
+    /// UTF-16 is only detected when the document starts with a byte order mark. The line ending
+    /// is the first one found in the document, or `\n` if there are none.
+    ///
+    /// Returns None if the document is not valid in the detected encoding
+    pub fn decode(data: &[u8]) -> Option<(String, Self)> {
+        let mut format = Self::new();
+
+        let text = if let Some(data) = data.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+            format.bom = true;
+            String::from_utf8(data.to_vec()).ok()?
+        } else if let Some(data) = data.strip_prefix(&[0xFF, 0xFE]) {
+            format.encoding = TextEncoding::Utf16Le;
+            format.bom = true;
+            decode_utf16(data, u16::from_le_bytes)?
+        } else if let Some(data) = data.strip_prefix(&[0xFE, 0xFF]) {
+            format.encoding = TextEncoding::Utf16Be;
+            format.bom = true;
+            decode_utf16(data, u16::from_be_bytes)?
+        } else {
+            String::from_utf8(data.to_vec()).ok()?
+        };
+
+        if let Some((_, ending)) =
+            LineIter::new(&text).find(|(_, ending)| *ending != LineEnding::None)
+        {
+            format.line_ending = ending;
+        }
+
+        Some((text, format))
+    }
+
+    /// Encode text in this format, adding a byte order mark if required
+    pub fn encode(&self, text: &str) -> Vec<u8> {
+        match self.encoding {
+            TextEncoding::Utf8 => {
+                let mut data = Vec::with_capacity(text.len() + 3);
+                if self.bom {
+                    data.extend_from_slice(&[0xEF, 0xBB, 0xBF]);
+                }
+                data.extend_from_slice(text.as_bytes());
+                data
+            }
+            TextEncoding::Utf16Le | TextEncoding::Utf16Be => {
+                let mut data = Vec::with_capacity(text.len() * 2 + 2);
+                let mut push = |unit: u16| {
+                    if self.encoding == TextEncoding::Utf16Le {
+                        data.extend_from_slice(&unit.to_le_bytes());
+                    } else {
+                        data.extend_from_slice(&unit.to_be_bytes());
+                    }
+                };
+                if self.bom {
+                    push(0xFEFF);
+                }
+                for unit in text.encode_utf16() {
+                    push(unit);
+                }
+                data
+            }
+        }
+    }
+}
+
+fn decode_utf16(data: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Option<String> {
+    let chunks = data.chunks_exact(2);
+    if !chunks.remainder().is_empty() {
+        return None;
+    }
+
+    let units = chunks.map(|chunk| from_bytes([chunk[0], chunk[1]]));
+    char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .ok()
+}
+
+#[test]
+fn test_document_format_round_trip() {
+    for data in [
+        &b"fn main() {\r\n}\r\n"[..],
+        &b"\xEF\xBB\xBFmixed\r\nendings\nno newline"[..],
+        &b"\xFF\xFEa\x00\n\x00"[..],
+        &b"\xFE\xFF\x00a\x00\r\x00\n"[..],
+        &b""[..],
+    ] {
+        let (text, format) = DocumentFormat::decode(data).expect("failed to decode");
+        assert_eq!(format.encode(&text), data);
+    }
+
+    let (_, format) = DocumentFormat::decode(b"a\r\nb\n").expect("failed to decode");
+    assert_eq!(format.line_ending, LineEnding::CrLf);
+
+    assert!(DocumentFormat::decode(b"\xFF\xFEa").is_none());
+    assert!(DocumentFormat::decode(b"\xC0").is_none());
+}
@@ -1,26 +1,64 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 #[cfg(not(feature = "std"))]
-use alloc::string::String;
+use alloc::{string::String, vec::Vec};
 use core::{
     cmp::{self, Ordering},
     iter::once,
 };
+#[cfg(feature = "std")]
+use std::{fs, io, path::Path};
 use unicode_segmentation::UnicodeSegmentation;
 
+use super::behavior::{
+    closing_bracket, closing_pair, column, indent_len, indent_unit, is_closing, outdent_len,
+};
+use super::style::Gutter;
 #[cfg(feature = "swash")]
 use crate::Color;
 use crate::{
-    Action, Affinity, AttrsList, Buffer, BufferLine, Cursor, Edit, FontSystem, LayoutCursor,
+    Action, Affinity, Attrs, AttrsList, BorrowedWithFontSystem, Buffer, BufferLine, Cursor,
+    DocumentFormat, Edit, EditorBehavior, EditorStyle, FontSystem, LayoutCursor, LineEnding,
 };
 
+/// A change to the text of an [`Editor`], see [`Editor::set_track_changes`]
+///
+/// The text between `start` and `end` was replaced with `text`. Positions refer to the text
+/// before the change, and lines in `text` are separated by `\n`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TextChange {
+    pub start: Cursor,
+    pub end: Cursor,
+    pub text: String,
+}
+
+/// Unit of the selection extended by dragging or shift clicking, set by the last click
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum SelectUnit {
+    Grapheme,
+    /// Word selected by a double click
+    Word(Cursor, Cursor),
+    /// Line selected by a triple click
+    Line(usize),
+}
+
 /// A wrapper of [`Buffer`] for easy editing
 pub struct Editor {
     buffer: Buffer,
     cursor: Cursor,
     cursor_x_opt: Option<i32>,
     select_opt: Option<Cursor>,
+    select_unit: SelectUnit,
     cursor_moved: bool,
+    changed: bool,
+    changes_opt: Option<Vec<TextChange>>,
+    format: DocumentFormat,
+    behavior: EditorBehavior,
+    style: EditorStyle,
+    gutter: Gutter,
+    /// Closing characters inserted by [`EditorBehavior::auto_close`] that can be typed over,
+    /// which are forgotten when the cursor leaves their line
+    auto_closed: Vec<Cursor>,
 }
 
 impl Editor {
@@ -31,8 +69,177 @@ impl Editor {
             cursor: Cursor::default(),
             cursor_x_opt: None,
             select_opt: None,
+            select_unit: SelectUnit::Grapheme,
             cursor_moved: false,
+            changed: false,
+            changes_opt: None,
+            format: DocumentFormat::new(),
+            behavior: EditorBehavior::new(),
+            style: EditorStyle::new(),
+            gutter: Gutter::new(),
+            auto_closed: Vec::new(),
+        }
+    }
+
+    /// Enable or disable tracking of the changes made through the editor
+    ///
+    /// This is used by editors that update incrementally, such as for syntax highlighting
+    pub fn set_track_changes(&mut self, track: bool) {
+        if track != self.changes_opt.is_some() {
+            self.changes_opt = if track { Some(Vec::new()) } else { None };
+        }
+    }
+
+    /// Take the changes made since the last call, in the order they were made
+    ///
+    /// This is empty unless changes are tracked, see [`Self::set_track_changes`]. Changes
+    /// made directly to the [`Buffer`] are not tracked.
+    pub fn take_changes(&mut self) -> Vec<TextChange> {
+        match &mut self.changes_opt {
+            Some(changes) => core::mem::take(changes),
+            None => Vec::new(),
+        }
+    }
+
+    fn record_change(&mut self, start: Cursor, end: Cursor, text: &str) {
+        let new_end_line = start.line + text.matches('\n').count();
+        self.buffer.edit_folds(start.line, end.line, new_end_line);
+
+        // Move auto closed characters after the change, and forget those it touches
+        if start.line == end.line && new_end_line == start.line {
+            self.auto_closed.retain_mut(|closer| {
+                if closer.line != start.line || start.index > closer.index {
+                    true
+                } else if end.index <= closer.index {
+                    closer.index = closer.index - (end.index - start.index) + text.len();
+                    true
+                } else {
+                    false
+                }
+            });
+        } else {
+            self.auto_closed.retain(|closer| closer.line < start.line);
+        }
+
+        if let Some(changes) = &mut self.changes_opt {
+            changes.push(TextChange {
+                start: Cursor::new(start.line, start.index),
+                end: Cursor::new(end.line, end.index),
+                text: text.into(),
+            });
+        }
+    }
+
+    /// Get the text between two cursors, with lines separated by `\n`
+    fn text_between(&self, start: Cursor, end: Cursor) -> String {
+        let mut text = String::new();
+        // Take the text from the first line
+        {
+            if start.line == end.line {
+                text.push_str(&self.buffer.lines[start.line].text()[start.index..end.index]);
+            } else {
+                text.push_str(&self.buffer.lines[start.line].text()[start.index..]);
+                text.push('\n');
+            }
+        }
+
+        // Take the text from all interior lines (if they exist)
+        for line_i in start.line + 1..end.line {
+            text.push_str(self.buffer.lines[line_i].text());
+            text.push('\n');
+        }
+
+        // Take the text from the last line
+        if end.line > start.line {
+            text.push_str(&self.buffer.lines[end.line].text()[..end.index]);
+        }
+
+        text
+    }
+
+    /// Get the [`DocumentFormat`] used when saving
+    pub fn format(&self) -> DocumentFormat {
+        self.format
+    }
+
+    /// Set the [`DocumentFormat`] used when saving
+    pub fn set_format(&mut self, format: DocumentFormat) {
+        self.format = format;
+    }
+
+    /// Load a document, detecting its [`DocumentFormat`] and keeping the line ending of each line
+    ///
+    /// Returns false if the document could not be decoded, see [`DocumentFormat::decode`]
+    pub fn load_bytes(&mut self, font_system: &mut FontSystem, data: &[u8], attrs: Attrs) -> bool {
+        let (text, format) = match DocumentFormat::decode(data) {
+            Some(some) => some,
+            None => return false,
+        };
+
+        self.buffer.set_text(font_system, &text, attrs);
+        self.format = format;
+        self.cursor = Cursor::default();
+        self.cursor_x_opt = None;
+        self.select_opt = None;
+        self.changed = false;
+        if let Some(changes) = &mut self.changes_opt {
+            // Positions of earlier changes no longer apply
+            changes.clear();
+        }
+        true
+    }
+
+    /// Save the document using its [`DocumentFormat`] and the line ending of each line
+    ///
+    /// Lines other than the last that have no line ending, such as lines added by the editor,
+    /// use [`DocumentFormat::line_ending`]. This does not clear the changed flag.
+    pub fn save_bytes(&self) -> Vec<u8> {
+        let mut text = String::new();
+        let last_line_i = self.buffer.lines.len().saturating_sub(1);
+        for (line_i, line) in self.buffer.lines.iter().enumerate() {
+            text.push_str(line.text());
+            let ending = match line.ending() {
+                LineEnding::None if line_i < last_line_i => self.format.line_ending,
+                ending => ending,
+            };
+            text.push_str(ending.as_str());
         }
+        self.format.encode(&text)
+    }
+
+    /// Load text from a file, see [`Self::load_bytes`]
+    ///
+    /// ## Errors
+    ///
+    /// Returns an [`io::Error`] if reading the file fails, or if it could not be decoded
+    #[cfg(feature = "std")]
+    pub fn load_text<P: AsRef<Path>>(
+        &mut self,
+        font_system: &mut FontSystem,
+        path: P,
+        attrs: Attrs,
+    ) -> io::Result<()> {
+        let data = fs::read(path)?;
+        if self.load_bytes(font_system, &data, attrs) {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "stream did not contain valid UTF-8 or UTF-16",
+            ))
+        }
+    }
+
+    /// Save text to a file and clear the changed flag, see [`Self::save_bytes`]
+    ///
+    /// ## Errors
+    ///
+    /// Returns an [`io::Error`] if writing the file fails
+    #[cfg(feature = "std")]
+    pub fn save_text<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        fs::write(path, self.save_bytes())?;
+        self.changed = false;
+        Ok(())
     }
 
     fn set_layout_cursor(&mut self, font_system: &mut FontSystem, cursor: LayoutCursor) {
@@ -68,6 +275,297 @@ impl Editor {
             self.buffer.set_redraw(true);
         }
     }
+
+    /// Get the line after the last line that syntax highlighting should cover, which is the end of
+    /// the view or the cursor line, whichever is later, plus `margin` lines
+    #[cfg(any(feature = "syntect", feature = "tree-sitter"))]
+    pub(crate) fn highlight_end(&self, margin: usize) -> usize {
+        let scroll_end = self.buffer.scroll() + self.buffer.visible_lines();
+        let mut total_layout = 0;
+        let mut view_end = 0;
+        for line in self.buffer.lines.iter() {
+            view_end += 1;
+            total_layout += line.layout_opt().as_ref().map_or(1, |layout| layout.len()) as i32;
+            if total_layout >= scroll_end {
+                break;
+            }
+        }
+        cmp::max(view_end, self.cursor.line + 1) + margin
+    }
+}
+
+impl Editor {
+    /// Get the start and end of the selection, if there is one
+    fn selection_bounds(&self) -> Option<(Cursor, Cursor)> {
+        let select = self.select_opt?;
+        Some(if select < self.cursor {
+            (select, self.cursor)
+        } else {
+            (self.cursor, select)
+        })
+    }
+
+    /// Insert text in a line, moving the cursor and selection after it if they are past `index`
+    fn insert_in_line(&mut self, line_i: usize, index: usize, text: &str) {
+        let mut cursor = self.cursor;
+        let mut select_opt = self.select_opt.take();
+
+        self.cursor = Cursor::new(line_i, index);
+        self.insert_string(text, None);
+
+        for cursor in once(&mut cursor).chain(select_opt.as_mut()) {
+            if cursor.line == line_i && cursor.index > index {
+                cursor.index += text.len();
+            }
+        }
+        self.cursor = cursor;
+        self.select_opt = select_opt;
+    }
+
+    /// Delete text in a line, moving the cursor and selection back if needed
+    fn delete_in_line(&mut self, line_i: usize, start: usize, end: usize) {
+        let mut cursor = self.cursor;
+        let mut select_opt = self.select_opt.take();
+
+        self.select_opt = Some(Cursor::new(line_i, start));
+        self.cursor = Cursor::new(line_i, end);
+        self.delete_selection();
+
+        for cursor in once(&mut cursor).chain(select_opt.as_mut()) {
+            if cursor.line == line_i && cursor.index > start {
+                cursor.index = cmp::max(start, cursor.index.saturating_sub(end - start));
+            }
+        }
+        self.cursor = cursor;
+        self.select_opt = select_opt;
+    }
+
+    /// Get the word at a cursor, as the cursors before and after it
+    fn word_bounds(&self, cursor: Cursor) -> (Cursor, Cursor) {
+        let text = self.buffer.lines[cursor.line].text();
+        let word = self.behavior.word_boundaries.word_at(text, cursor.index);
+        (
+            Cursor::new(cursor.line, word.start),
+            Cursor::new_with_affinity(cursor.line, word.end, Affinity::Before),
+        )
+    }
+
+    /// Get the cursor after a line, at the start of the next line if there is one so that the
+    /// line ending is included in selections
+    fn line_end(&self, line_i: usize) -> Cursor {
+        if line_i + 1 < self.buffer.lines.len() {
+            Cursor::new(line_i + 1, 0)
+        } else {
+            let index = self.buffer.lines[line_i].text().len();
+            Cursor::new_with_affinity(line_i, index, Affinity::Before)
+        }
+    }
+
+    /// Move the cursor to a position in editor coordinates, selecting the word there after a
+    /// double click and the line there after a triple click
+    fn click(&mut self, x: i32, y: i32, clicks: u8) {
+        if self.select_opt.take().is_some() {
+            self.buffer.set_redraw(true);
+        }
+        self.select_unit = SelectUnit::Grapheme;
+
+        let new_cursor = match self.buffer.hit((x - self.gutter.width()) as f32, y as f32) {
+            Some(some) => some,
+            None => return,
+        };
+
+        // Clicking a fold placeholder shows the hidden lines
+        self.buffer.unfold(new_cursor.line);
+
+        let cursor = match clicks {
+            1 => new_cursor,
+            2 => {
+                let (start, end) = self.word_bounds(new_cursor);
+                self.select_opt = Some(start);
+                self.select_unit = SelectUnit::Word(start, end);
+                end
+            }
+            _ => {
+                self.select_opt = Some(Cursor::new(new_cursor.line, 0));
+                self.select_unit = SelectUnit::Line(new_cursor.line);
+                self.line_end(new_cursor.line)
+            }
+        };
+
+        if self.select_opt.is_some() || cursor != self.cursor {
+            self.cursor = cursor;
+            self.buffer.set_redraw(true);
+        }
+    }
+
+    /// Extend the selection to a cursor, by the unit of the last click
+    fn extend_selection(&mut self, new_cursor: Cursor) {
+        let (select, cursor) = match self.select_unit {
+            SelectUnit::Grapheme => (self.select_opt.unwrap_or(self.cursor), new_cursor),
+            SelectUnit::Word(start, end) => {
+                let (word_start, word_end) = self.word_bounds(new_cursor);
+                if word_start < start {
+                    (end, word_start)
+                } else {
+                    (start, cmp::max(end, word_end))
+                }
+            }
+            SelectUnit::Line(line_i) => {
+                if new_cursor.line < line_i {
+                    (self.line_end(line_i), Cursor::new(new_cursor.line, 0))
+                } else {
+                    (Cursor::new(line_i, 0), self.line_end(new_cursor.line))
+                }
+            }
+        };
+
+        if self.select_opt != Some(select) || self.cursor != cursor {
+            self.select_opt = Some(select);
+            self.cursor = cursor;
+            self.buffer.set_redraw(true);
+        }
+    }
+
+    /// Get the lines changed by [`Action::Indent`] and [`Action::Unindent`]
+    fn indent_lines(&self) -> core::ops::Range<usize> {
+        match self.selection_bounds() {
+            Some((start, end)) => {
+                // A selection ending at the start of a line does not include that line
+                if end.line > start.line && end.index == 0 {
+                    start.line..end.line
+                } else {
+                    start.line..end.line + 1
+                }
+            }
+            None => self.cursor.line..self.cursor.line + 1,
+        }
+    }
+
+    /// Get the indentation of a new line created at the cursor, and the indentation of the
+    /// closing bracket moved to its own line, if any
+    fn enter_indent(&self) -> (String, Option<String>) {
+        let text = self.buffer.lines[self.cursor.line].text();
+        let (before, after) = text.split_at(self.cursor.index);
+        let line_indent = if self.behavior.auto_indent {
+            &before[..indent_len(before)]
+        } else {
+            ""
+        };
+
+        let mut indent = String::from(line_indent);
+        let mut closing_indent_opt = None;
+        if self.behavior.bracket_indent {
+            if let Some(closing) = before
+                .trim_end()
+                .chars()
+                .next_back()
+                .and_then(closing_bracket)
+            {
+                indent.push_str(&indent_unit(&self.buffer));
+                if after.trim_start().starts_with(closing) {
+                    closing_indent_opt = Some(String::from(line_indent));
+                }
+            }
+        }
+        (indent, closing_indent_opt)
+    }
+
+    /// Insert a character with [`EditorBehavior::auto_close`], returns false if it should be
+    /// inserted as usual
+    fn auto_close(&mut self, character: char) -> bool {
+        if self.select_opt.is_some() {
+            return false;
+        }
+
+        let text = self.buffer.lines[self.cursor.line].text();
+        let prev_opt = text[..self.cursor.index].chars().next_back();
+        let next_opt = text[self.cursor.index..].chars().next();
+
+        if next_opt == Some(character) {
+            if let Some(closer_i) = self.auto_closed_at(self.cursor) {
+                // Type over the closing character
+                self.auto_closed.remove(closer_i);
+                self.set_cursor(Cursor::new(
+                    self.cursor.line,
+                    self.cursor.index + character.len_utf8(),
+                ));
+                return true;
+            }
+        }
+
+        let closing = match closing_pair(character) {
+            Some(some) => some,
+            None => return false,
+        };
+        // Only close before whitespace or other closing characters, and do not close quotes
+        // used as apostrophes
+        let next_allowed = next_opt
+            .filter(|next| !next.is_whitespace() && !is_closing(*next))
+            .is_none();
+        let prev_allowed = closing != character
+            || prev_opt
+                .filter(|prev| prev.is_alphanumeric() || *prev == character)
+                .is_none();
+        if !(next_allowed && prev_allowed) {
+            return false;
+        }
+
+        let mut pair = String::new();
+        pair.push(character);
+        pair.push(closing);
+        self.insert_string(&pair, None);
+        self.cursor.index -= closing.len_utf8();
+        self.auto_closed
+            .push(Cursor::new(self.cursor.line, self.cursor.index));
+        true
+    }
+
+    /// Get the index in `auto_closed` of the closing character at a cursor, if it was inserted
+    /// by [`Self::auto_close`]
+    fn auto_closed_at(&self, cursor: Cursor) -> Option<usize> {
+        self.auto_closed
+            .iter()
+            .position(|closer| closer.line == cursor.line && closer.index == cursor.index)
+    }
+
+    /// Get the number of visual lines to move the cursor by to move it `px` pixels, up if
+    /// negative, using the height of each visible line and `line_height` past the view
+    fn vertical_lines(&self, px: i32) -> i32 {
+        let cursor = self.buffer.layout_cursor(&self.cursor);
+        let down = px > 0;
+
+        // Heights of the visible lines in the direction of the move
+        let mut heights = Vec::new();
+        let mut found = false;
+        for run in self.buffer.layout_runs() {
+            if run.fold_opt.is_none() && run.line_i == cursor.line && run.layout_i == cursor.layout
+            {
+                found = true;
+                if !down {
+                    break;
+                }
+            } else if found == down {
+                heights.push(run.line_height);
+            }
+        }
+        if !found {
+            heights.clear();
+        }
+        if !down {
+            heights.reverse();
+        }
+
+        let mut remaining = px.unsigned_abs() as f32;
+        let mut lines = 0;
+        for height in heights {
+            if height > remaining {
+                return lines * px.signum();
+            }
+            remaining -= height;
+            lines += 1;
+        }
+        (lines + (remaining / self.buffer.metrics().line_height) as i32) * px.signum()
+    }
 }
 
 impl Edit for Editor {
@@ -83,6 +581,15 @@ impl Edit for Editor {
         self.cursor
     }
 
+    fn set_cursor(&mut self, cursor: Cursor) {
+        if self.cursor != cursor {
+            self.cursor = cursor;
+            self.cursor_moved = true;
+            self.cursor_x_opt = None;
+            self.buffer.set_redraw(true);
+        }
+    }
+
     fn select_opt(&self) -> Option<Cursor> {
         self.select_opt
     }
@@ -94,7 +601,42 @@ impl Edit for Editor {
         }
     }
 
+    fn changed(&self) -> bool {
+        self.changed
+    }
+
+    fn set_changed(&mut self, changed: bool) {
+        self.changed = changed;
+    }
+
+    fn behavior(&self) -> EditorBehavior {
+        self.behavior
+    }
+
+    fn set_behavior(&mut self, behavior: EditorBehavior) {
+        self.behavior = behavior;
+    }
+
+    fn style(&self) -> EditorStyle {
+        self.style
+    }
+
+    fn set_style(&mut self, style: EditorStyle) {
+        if self.style != style {
+            self.style = style;
+            self.buffer.set_redraw(true);
+        }
+    }
+
+    fn gutter_width(&self) -> i32 {
+        self.gutter.width()
+    }
+
     fn shape_as_needed(&mut self, font_system: &mut FontSystem) {
+        if self.gutter.update(font_system, &self.buffer, &self.style) {
+            self.buffer.set_redraw(true);
+        }
+
         if self.cursor_moved {
             self.buffer.shape_until_cursor(font_system, self.cursor);
             self.cursor_moved = false;
@@ -120,31 +662,7 @@ impl Edit for Editor {
             }
         };
 
-        let mut selection = String::new();
-        // Take the selection from the first line
-        {
-            // Add selected part of line to string
-            if start.line == end.line {
-                selection.push_str(&self.buffer.lines[start.line].text()[start.index..end.index]);
-            } else {
-                selection.push_str(&self.buffer.lines[start.line].text()[start.index..]);
-                selection.push('\n');
-            }
-        }
-
-        // Take the selection from all interior lines (if they exist)
-        for line_i in start.line + 1..end.line {
-            selection.push_str(self.buffer.lines[line_i].text());
-            selection.push('\n');
-        }
-
-        // Take the selection from the last line
-        if end.line > start.line {
-            // Add selected part of line to string
-            selection.push_str(&self.buffer.lines[end.line].text()[..end.index]);
-        }
-
-        Some(selection)
+        Some(self.text_between(start, end))
     }
 
     fn delete_selection(&mut self) -> bool {
@@ -152,6 +670,7 @@ impl Edit for Editor {
             Some(some) => some,
             None => return false,
         };
+        self.changed = true;
 
         let (start, end) = match select.line.cmp(&self.cursor.line) {
             cmp::Ordering::Greater => (self.cursor, select),
@@ -167,6 +686,8 @@ impl Edit for Editor {
             }
         };
 
+        self.record_change(start, end, "");
+
         // Reset cursor to start of selection
         self.cursor = start;
 
@@ -220,6 +741,8 @@ impl Edit for Editor {
         if remaining_split_len == 0 {
             return;
         }
+        self.changed = true;
+        let start = self.cursor;
 
         let line: &mut BufferLine = &mut self.buffer.lines[self.cursor.line];
         let insert_line = self.cursor.line + 1;
@@ -240,23 +763,18 @@ impl Edit for Editor {
             let mut these_attrs = final_attrs.split_off(data_line.len());
             remaining_split_len -= data_line.len();
             core::mem::swap(&mut these_attrs, &mut final_attrs);
-            line.append(BufferLine::new(
-                data_line
-                    .strip_suffix(char::is_control)
-                    .unwrap_or(data_line),
-                these_attrs,
-            ));
+            let (text, ending) = LineEnding::strip(data_line);
+            let mut tmp = BufferLine::new(text, these_attrs);
+            tmp.set_ending(ending);
+            line.append(tmp);
         } else {
             panic!("str::lines() did not yield any elements");
         }
         if let Some(data_line) = lines_iter.next_back() {
             remaining_split_len -= data_line.len();
-            let mut tmp = BufferLine::new(
-                data_line
-                    .strip_suffix(char::is_control)
-                    .unwrap_or(data_line),
-                final_attrs.split_off(remaining_split_len),
-            );
+            let (text, _) = LineEnding::strip(data_line);
+            let mut tmp = BufferLine::new(text, final_attrs.split_off(remaining_split_len));
+            // The last inserted line takes the line ending of the text after insertion
             tmp.append(after);
             self.buffer.lines.insert(insert_line, tmp);
             self.cursor.line += 1;
@@ -265,12 +783,9 @@ impl Edit for Editor {
         }
         for data_line in lines_iter.rev() {
             remaining_split_len -= data_line.len();
-            let tmp = BufferLine::new(
-                data_line
-                    .strip_suffix(char::is_control)
-                    .unwrap_or(data_line),
-                final_attrs.split_off(remaining_split_len),
-            );
+            let (text, ending) = LineEnding::strip(data_line);
+            let mut tmp = BufferLine::new(text, final_attrs.split_off(remaining_split_len));
+            tmp.set_ending(ending);
             self.buffer.lines.insert(insert_line, tmp);
             self.cursor.line += 1;
         }
@@ -279,11 +794,26 @@ impl Edit for Editor {
 
         // Append the text after insertion
         self.cursor.index = self.buffer.lines[self.cursor.line].text().len() - after_len;
+
+        let text = self.text_between(start, self.cursor);
+        self.record_change(start, start, &text);
     }
 
     fn action(&mut self, font_system: &mut FontSystem, action: Action) {
         let old_cursor = self.cursor;
 
+        if !matches!(
+            action,
+            Action::DoubleClick { .. }
+                | Action::TripleClick { .. }
+                | Action::ShiftClick { .. }
+                | Action::Drag { .. }
+                | Action::Scroll { .. }
+        ) {
+            // Only mouse selection continues by the unit of the last click
+            self.select_unit = SelectUnit::Grapheme;
+        }
+
         match action {
             Action::Previous => {
                 let line = &mut self.buffer.lines[self.cursor.line];
@@ -408,9 +938,22 @@ impl Edit for Editor {
             }
             Action::Home => {
                 let mut cursor = self.buffer.layout_cursor(&self.cursor);
-                cursor.glyph = 0;
-                self.set_layout_cursor(font_system, cursor);
-                self.cursor_x_opt = None;
+                let text = self.buffer.lines[self.cursor.line].text();
+                if self.behavior.smart_home
+                    && cursor.layout == 0
+                    && self.cursor.index != indent_len(text)
+                {
+                    self.action(font_system, Action::SoftHome);
+                } else {
+                    cursor.glyph = 0;
+                    self.set_layout_cursor(font_system, cursor);
+                    self.cursor_x_opt = None;
+                }
+            }
+            Action::SoftHome => {
+                let line = self.cursor.line;
+                let index = indent_len(self.buffer.lines[line].text());
+                self.set_cursor(Cursor::new_with_affinity(line, index, Affinity::After));
             }
             Action::End => {
                 let mut cursor = self.buffer.layout_cursor(&self.cursor);
@@ -435,8 +978,7 @@ impl Edit for Editor {
                 self.action(font_system, Action::Vertical(self.buffer.size().1 as i32));
             }
             Action::Vertical(px) => {
-                // TODO more efficient
-                let lines = px / self.buffer.metrics().line_height as i32;
+                let lines = self.vertical_lines(px);
                 match lines.cmp(&0) {
                     Ordering::Less => {
                         for _ in 0..-lines {
@@ -462,7 +1004,7 @@ impl Edit for Editor {
                     log::debug!("Refusing to insert control character {:?}", character);
                 } else if character == '\n' {
                     self.action(font_system, Action::Enter);
-                } else {
+                } else if !(self.behavior.auto_close && self.auto_close(character)) {
                     let mut str_buf = [0u8; 8];
                     let str_ref = character.encode_utf8(&mut str_buf);
                     self.insert_string(str_ref, None);
@@ -471,14 +1013,46 @@ impl Edit for Editor {
             Action::Enter => {
                 self.delete_selection();
 
+                let (indent, closing_indent_opt) = self.enter_indent();
+
+                self.record_change(self.cursor, self.cursor, "\n");
                 let new_line = self.buffer.lines[self.cursor.line].split_off(self.cursor.index);
+                self.changed = true;
 
                 self.cursor.line += 1;
                 self.cursor.index = 0;
 
                 self.buffer.lines.insert(self.cursor.line, new_line);
+
+                self.insert_string(&indent, None);
+                if let Some(closing_indent) = closing_indent_opt {
+                    // Move the closing bracket to its own line
+                    let cursor = self.cursor;
+                    let mut text = String::from("\n");
+                    text.push_str(&closing_indent);
+                    self.insert_string(&text, None);
+                    self.cursor = cursor;
+                }
             }
             Action::Backspace => {
+                if self.behavior.auto_close && self.select_opt.is_none() {
+                    let text = self.buffer.lines[self.cursor.line].text();
+                    let prev_opt = text[..self.cursor.index].chars().next_back();
+                    let next_opt = text[self.cursor.index..].chars().next();
+                    if let (Some(prev), Some(next)) = (prev_opt, next_opt) {
+                        if closing_pair(prev) == Some(next)
+                            && self.auto_closed_at(self.cursor).is_some()
+                        {
+                            // Delete both characters of an empty pair
+                            self.select_opt = Some(Cursor::new(
+                                self.cursor.line,
+                                self.cursor.index - prev.len_utf8(),
+                            ));
+                            self.cursor.index += next.len_utf8();
+                        }
+                    }
+                }
+
                 if self.delete_selection() {
                     // Deleted selection
                 } else if self.cursor.index > 0 {
@@ -497,6 +1071,7 @@ impl Edit for Editor {
                         }
                     }
 
+                    let end = self.cursor;
                     self.cursor.index = prev_index;
 
                     // Remove character
@@ -504,7 +1079,10 @@ impl Edit for Editor {
 
                     // Add text after cursor
                     line.append(after);
+                    self.changed = true;
+                    self.record_change(self.cursor, end, "");
                 } else if self.cursor.line > 0 {
+                    let end = self.cursor;
                     let mut line_index = self.cursor.line;
                     let old_line = self.buffer.lines.remove(line_index);
                     line_index -= 1;
@@ -515,6 +1093,8 @@ impl Edit for Editor {
                     self.cursor.index = line.text().len();
 
                     line.append(old_line);
+                    self.changed = true;
+                    self.record_change(self.cursor, end, "");
                 }
             }
             Action::Delete => {
@@ -541,20 +1121,56 @@ impl Edit for Editor {
 
                         // Add text after deleted EGC
                         line.append(after);
+                        self.changed = true;
+                        self.record_change(
+                            self.cursor,
+                            Cursor::new(self.cursor.line, range.end),
+                            "",
+                        );
                     }
                 } else if self.cursor.line + 1 < self.buffer.lines.len() {
                     let old_line = self.buffer.lines.remove(self.cursor.line + 1);
                     self.buffer.lines[self.cursor.line].append(old_line);
+                    self.changed = true;
+                    self.record_change(self.cursor, Cursor::new(self.cursor.line + 1, 0), "");
                 }
             }
-            Action::Click { x, y } => {
-                self.select_opt = None;
-
-                if let Some(new_cursor) = self.buffer.hit(x as f32, y as f32) {
-                    if new_cursor != self.cursor {
-                        self.cursor = new_cursor;
-                        self.buffer.set_redraw(true);
+            Action::Indent => {
+                if self.select_opt.is_some() {
+                    let indent = indent_unit(&self.buffer);
+                    for line_i in self.indent_lines() {
+                        if !self.buffer.lines[line_i].text().is_empty() {
+                            self.insert_in_line(line_i, 0, &indent);
+                        }
                     }
+                } else if self.buffer.expand_tabs() {
+                    // Insert spaces up to the next tab stop
+                    let tab_width = self.buffer.tab_width();
+                    let text = self.buffer.lines[self.cursor.line].text();
+                    let column = column(text, self.cursor.index, tab_width);
+                    let spaces = usize::from(tab_width) - column % usize::from(tab_width);
+                    self.insert_string(&" ".repeat(spaces), None);
+                } else {
+                    self.insert_string("\t", None);
+                }
+            }
+            Action::Unindent => {
+                let tab_width = self.buffer.tab_width();
+                for line_i in self.indent_lines() {
+                    let len = outdent_len(self.buffer.lines[line_i].text(), tab_width);
+                    if len > 0 {
+                        self.delete_in_line(line_i, 0, len);
+                    }
+                }
+            }
+            Action::Click { x, y } => self.click(x, y, 1),
+            Action::DoubleClick { x, y } => self.click(x, y, 2),
+            Action::TripleClick { x, y } => self.click(x, y, 3),
+            Action::ShiftClick { x, y } => {
+                if let Some(new_cursor) =
+                    self.buffer.hit((x - self.gutter.width()) as f32, y as f32)
+                {
+                    self.extend_selection(new_cursor);
                 }
             }
             Action::Drag { x, y } => {
@@ -563,11 +1179,10 @@ impl Edit for Editor {
                     self.buffer.set_redraw(true);
                 }
 
-                if let Some(new_cursor) = self.buffer.hit(x as f32, y as f32) {
-                    if new_cursor != self.cursor {
-                        self.cursor = new_cursor;
-                        self.buffer.set_redraw(true);
-                    }
+                if let Some(new_cursor) =
+                    self.buffer.hit((x - self.gutter.width()) as f32, y as f32)
+                {
+                    self.extend_selection(new_cursor);
                 }
             }
             Action::Scroll { lines } => {
@@ -579,9 +1194,9 @@ impl Edit for Editor {
                 let line: &mut BufferLine = &mut self.buffer.lines[self.cursor.line];
                 if self.cursor.index > 0 {
                     let mut prev_index = 0;
-                    for (i, _) in line.text().unicode_word_indices() {
-                        if i < self.cursor.index {
-                            prev_index = i;
+                    for word in self.behavior.word_boundaries.words(line.text()) {
+                        if word.start < self.cursor.index {
+                            prev_index = word.start;
                         } else {
                             break;
                         }
@@ -599,10 +1214,9 @@ impl Edit for Editor {
             Action::NextWord => {
                 let line: &mut BufferLine = &mut self.buffer.lines[self.cursor.line];
                 if self.cursor.index < line.text().len() {
-                    for (i, word) in line.text().unicode_word_indices() {
-                        let i = i + word.len();
-                        if i > self.cursor.index {
-                            self.cursor.index = i;
+                    for word in self.behavior.word_boundaries.words(line.text()) {
+                        if word.end > self.cursor.index {
+                            self.cursor.index = word.end;
                             self.buffer.set_redraw(true);
                             break;
                         }
@@ -652,6 +1266,30 @@ impl Edit for Editor {
             }
         }
 
+        if let Some(fold_lines) = self
+            .buffer
+            .fold_at(self.cursor.line)
+            .map(|fold| fold.lines())
+        {
+            // Move the cursor past hidden lines, in the direction it was moving
+            let forward = self.cursor >= old_cursor || fold_lines.start == 0;
+            if forward && fold_lines.end < self.buffer.lines.len() {
+                self.cursor = Cursor::new(fold_lines.end, 0);
+            } else if fold_lines.start > 0 {
+                let line_i = fold_lines.start - 1;
+                self.cursor = Cursor::new(line_i, self.buffer.lines[line_i].text().len());
+            } else {
+                // Every line is hidden
+                self.buffer.unfold(self.cursor.line);
+            }
+            self.cursor_x_opt = None;
+            // Lay out the new cursor line, as hidden lines may be skipped past the shaped lines
+            self.buffer.line_layout(font_system, self.cursor.line);
+        }
+
+        let cursor_line = self.cursor.line;
+        self.auto_closed.retain(|closer| closer.line == cursor_line);
+
         if old_cursor != self.cursor {
             self.cursor_moved = true;
 
@@ -684,15 +1322,45 @@ impl Edit for Editor {
     ) where
         F: FnMut(i32, i32, u32, u32, Color),
     {
-        let font_size = self.buffer.metrics().font_size;
-        let line_height = self.buffer.metrics().line_height;
+        let selection_color = self.style.selection(color);
+
+        self.gutter.draw(
+            font_system,
+            cache,
+            &self.buffer,
+            self.cursor.line,
+            &self.style,
+            color,
+            &mut f,
+        );
+        // Offset the text by the gutter width
+        let gutter_width = self.gutter.width();
+        let mut f = |x, y, w, h, color| f(x + gutter_width, y, w, h, color);
 
         for run in self.buffer.layout_runs() {
             let line_i = run.line_i;
             let line_y = run.line_y;
+            let line_top = run.line_top;
+            let line_height = run.line_height;
+
+            // Highlight the line with the cursor
+            if let Some(line_highlight) = self.style.line_highlight_opt {
+                if line_i == self.cursor.line {
+                    f(
+                        0,
+                        line_top as i32,
+                        self.buffer.size().0 as u32,
+                        line_height as u32,
+                        line_highlight,
+                    );
+                }
+            }
+
+            // Fold placeholders have no cursor positions
+            let folded = run.fold_opt.is_some();
 
             let cursor_glyph_opt = |cursor: &Cursor| -> Option<(usize, f32)> {
-                if cursor.line == line_i {
+                if cursor.line == line_i && !folded {
                     for (glyph_i, glyph) in run.glyphs.iter().enumerate() {
                         if cursor.index == glyph.start {
                             return Some((glyph_i, 0.0));
@@ -727,7 +1395,7 @@ impl Edit for Editor {
                 None
             };
 
-            // Highlight selection (TODO: HIGHLIGHT COLOR!)
+            // Highlight selection
             if let Some(select) = self.select_opt {
                 let (start, end) = match select.line.cmp(&self.cursor.line) {
                     cmp::Ordering::Greater => (self.cursor, select),
@@ -743,7 +1411,7 @@ impl Edit for Editor {
                     }
                 };
 
-                if line_i >= start.line && line_i <= end.line {
+                if line_i >= start.line && line_i <= end.line && !folded {
                     let mut range_opt = None;
                     for glyph in run.glyphs.iter() {
                         // Guess x offset based on characters
@@ -767,10 +1435,10 @@ impl Edit for Editor {
                             } else if let Some((min, max)) = range_opt.take() {
                                 f(
                                     min,
-                                    (line_y - font_size) as i32,
+                                    line_top as i32,
                                     cmp::max(0, max - min) as u32,
                                     line_height as u32,
-                                    Color::rgba(color.r(), color.g(), color.b(), 0x33),
+                                    selection_color,
                                 );
                             }
                             c_x += c_w;
@@ -793,10 +1461,10 @@ impl Edit for Editor {
                         }
                         f(
                             min,
-                            (line_y - font_size) as i32,
+                            line_top as i32,
                             cmp::max(0, max - min) as u32,
                             line_height as u32,
-                            Color::rgba(color.r(), color.g(), color.b(), 0x33),
+                            selection_color,
                         );
                     }
                 }
@@ -824,15 +1492,25 @@ impl Edit for Editor {
                         }
                         None => {
                             // Start of empty line
-                            0
+                            self.buffer.run_start_x(&run) as i32
                         }
                     },
                 };
 
-                f(x, (line_y - font_size) as i32, 1, line_height as u32, color);
+                f(
+                    x,
+                    line_top as i32,
+                    self.style.caret_width,
+                    line_height as u32,
+                    self.style.caret(color),
+                );
             }
 
             for glyph in run.glyphs.iter() {
+                if glyph.object_opt.is_some() {
+                    // Inline objects are drawn by the application
+                    continue;
+                }
                 let (cache_key, x_int, y_int) = (glyph.cache_key, glyph.x_int, glyph.y_int);
 
                 let glyph_color = match glyph.color_opt {
@@ -847,3 +1525,385 @@ impl Edit for Editor {
         }
     }
 }
+
+impl<'a> BorrowedWithFontSystem<'a, Editor> {
+    /// Load a document, see [`Editor::load_bytes`]
+    pub fn load_bytes(&mut self, data: &[u8], attrs: Attrs) -> bool {
+        self.inner.load_bytes(self.font_system, data, attrs)
+    }
+
+    /// Load text from a file, see [`Editor::load_bytes`]
+    ///
+    /// ## Errors
+    ///
+    /// Returns an [`io::Error`] if reading the file fails, or if it could not be decoded
+    #[cfg(feature = "std")]
+    pub fn load_text<P: AsRef<Path>>(&mut self, path: P, attrs: Attrs) -> io::Result<()> {
+        self.inner.load_text(self.font_system, path, attrs)
+    }
+}
+
+#[cfg(test)]
+fn test_editor(font_system: &mut FontSystem, text: &str) -> Editor {
+    let mut buffer = Buffer::new(font_system, crate::Metrics::new(14.0, 20.0));
+    buffer.set_size(font_system, 400.0, 200.0);
+    buffer.set_text(font_system, text, Attrs::new());
+    let mut editor = Editor::new(buffer);
+    editor.shape_as_needed(font_system);
+    editor
+}
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_gutter_click() {
+    let mut font_system = FontSystem::new();
+    let mut editor = test_editor(&mut font_system, "hello world\nsecond line");
+    editor.set_style(EditorStyle {
+        line_numbers_opt: Some(super::LineNumbers::Absolute),
+        ..EditorStyle::new()
+    });
+    editor.shape_as_needed(&mut font_system);
+    let gutter_width = editor.gutter_width();
+    assert!(gutter_width > 0);
+
+    // Get the x of the start of a glyph, in editor coordinates
+    let glyph_x = |editor: &Editor, line_i: usize, glyph_i: usize| {
+        let run = editor
+            .buffer()
+            .layout_runs()
+            .find(|run| run.line_i == line_i)
+            .expect("run not found");
+        gutter_width + run.glyphs[glyph_i].x as i32 + 1
+    };
+
+    let x = glyph_x(&editor, 0, 6);
+    editor.action(&mut font_system, Action::Click { x, y: 5 });
+    assert_eq!(editor.cursor().index, 6);
+    assert_eq!(editor.select_opt(), None);
+
+    // Clicking in the gutter goes to the start of the line
+    editor.action(&mut font_system, Action::Click { x: 1, y: 25 });
+    assert_eq!((editor.cursor().line, editor.cursor().index), (1, 0));
+
+    editor.action(&mut font_system, Action::Click { x, y: 5 });
+    let x = glyph_x(&editor, 1, 3);
+    editor.action(&mut font_system, Action::Drag { x, y: 25 });
+    assert_eq!((editor.cursor().line, editor.cursor().index), (1, 3));
+    let select = editor.select_opt().expect("no selection");
+    assert_eq!((select.line, select.index), (0, 6));
+}
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_fold_cursor() {
+    let mut font_system = FontSystem::new();
+    let mut editor = test_editor(&mut font_system, "zero\none\ntwo\nthree\nfour\nfive");
+    assert!(editor.buffer_mut().fold(&mut font_system, 2..4));
+
+    // The cursor skips hidden lines in the direction it moves
+    editor.set_cursor(Cursor::new(1, 0));
+    editor.action(&mut font_system, Action::Down);
+    assert_eq!(editor.cursor().line, 4);
+    editor.action(&mut font_system, Action::Up);
+    assert_eq!((editor.cursor().line, editor.cursor().index), (1, 3));
+    editor.action(&mut font_system, Action::Right);
+    assert_eq!((editor.cursor().line, editor.cursor().index), (4, 0));
+
+    // Edits through the editor move folds after them
+    editor.set_cursor(Cursor::new(0, 0));
+    editor.insert_string("new\n", None);
+    assert_eq!(editor.buffer().folds().len(), 1);
+    assert_eq!(
+        editor.buffer().fold_at(3).map(|fold| fold.lines()),
+        Some(3..5)
+    );
+    assert_eq!(editor.buffer().lines[3].text(), "two");
+
+    // And remove folds touching them
+    editor.set_select_opt(Some(Cursor::new(2, 0)));
+    editor.set_cursor(Cursor::new(5, 0));
+    editor.insert_string("x", None);
+    assert!(editor.buffer().folds().is_empty());
+}
+
+#[cfg(test)]
+fn test_lines(editor: &Editor) -> Vec<&str> {
+    editor
+        .buffer()
+        .lines
+        .iter()
+        .map(|line| line.text())
+        .collect()
+}
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_auto_indent() {
+    let mut font_system = FontSystem::new();
+    let mut editor = test_editor(&mut font_system, "  a\n\tb {}");
+    editor.set_behavior(EditorBehavior {
+        auto_indent: true,
+        ..EditorBehavior::new()
+    });
+
+    editor.set_cursor(Cursor::new(0, 3));
+    editor.action(&mut font_system, Action::Enter);
+    assert_eq!(test_lines(&editor), ["  a", "  ", "\tb {}"]);
+    assert_eq!(editor.cursor(), Cursor::new(1, 2));
+
+    // Only the indentation before the cursor is used
+    editor.set_cursor(Cursor::new(0, 1));
+    editor.action(&mut font_system, Action::Enter);
+    assert_eq!(test_lines(&editor), [" ", "  a", "  ", "\tb {}"]);
+
+    // Brackets are not indented without bracket_indent
+    editor.set_cursor(Cursor::new(3, 4));
+    editor.action(&mut font_system, Action::Enter);
+    assert_eq!(&test_lines(&editor)[3..], ["\tb {", "\t}"]);
+}
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_bracket_indent() {
+    let mut font_system = FontSystem::new();
+    let mut editor = test_editor(&mut font_system, "  fn a() {}\n  if b {");
+    editor.buffer_mut().set_expand_tabs(true);
+    editor.set_behavior(EditorBehavior {
+        auto_indent: true,
+        bracket_indent: true,
+        ..EditorBehavior::new()
+    });
+
+    // A closing bracket after the cursor moves to its own line
+    editor.set_cursor(Cursor::new(0, 10));
+    editor.action(&mut font_system, Action::Enter);
+    assert_eq!(
+        test_lines(&editor),
+        ["  fn a() {", "      ", "  }", "  if b {"]
+    );
+    assert_eq!(editor.cursor(), Cursor::new(1, 6));
+
+    editor.set_cursor(Cursor::new(3, 8));
+    editor.action(&mut font_system, Action::Enter);
+    assert_eq!(&test_lines(&editor)[3..], ["  if b {", "      "]);
+
+    // Tabs are used unless tabs are expanded
+    editor.buffer_mut().set_expand_tabs(false);
+    editor.set_cursor(Cursor::new(3, 8));
+    editor.action(&mut font_system, Action::Enter);
+    assert_eq!(test_lines(&editor)[4], "  \t");
+}
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_auto_close() {
+    let mut font_system = FontSystem::new();
+    let mut editor = test_editor(&mut font_system, "\nx)\n[]");
+    editor.set_behavior(EditorBehavior::code());
+
+    // Inserted closing characters are typed over, even after typing inside the pair
+    for c in "f((a".chars() {
+        editor.action(&mut font_system, Action::Insert(c));
+    }
+    assert_eq!(test_lines(&editor)[0], "f((a))");
+    assert_eq!(editor.cursor(), Cursor::new(0, 4));
+    editor.buffer_mut().set_redraw(false);
+    editor.action(&mut font_system, Action::Insert(')'));
+    assert!(editor.buffer().redraw());
+    editor.action(&mut font_system, Action::Insert(')'));
+    assert_eq!(test_lines(&editor)[0], "f((a))");
+    assert_eq!(editor.cursor(), Cursor::new(0, 6));
+    editor.action(&mut font_system, Action::Insert(')'));
+    assert_eq!(test_lines(&editor)[0], "f((a)))");
+
+    // Closing characters that were not inserted are not typed over
+    editor.set_cursor(Cursor::new(1, 1));
+    editor.action(&mut font_system, Action::Insert(')'));
+    assert_eq!(test_lines(&editor)[1], "x))");
+
+    // Closing characters are forgotten when the cursor leaves their line
+    editor.set_cursor(Cursor::new(1, 3));
+    editor.action(&mut font_system, Action::Insert('"'));
+    assert_eq!(test_lines(&editor)[1], "x))\"\"");
+    editor.shape_as_needed(&mut font_system);
+    editor.action(&mut font_system, Action::Up);
+    editor.set_cursor(Cursor::new(1, 4));
+    editor.action(&mut font_system, Action::Insert('"'));
+    assert_eq!(test_lines(&editor)[1], "x))\"\"\"");
+
+    // Deleting the opening character of an inserted pair deletes both
+    editor.set_cursor(Cursor::new(2, 2));
+    editor.action(&mut font_system, Action::Insert('{'));
+    assert_eq!(test_lines(&editor)[2], "[]{}");
+    editor.action(&mut font_system, Action::Backspace);
+    assert_eq!(test_lines(&editor)[2], "[]");
+    editor.set_cursor(Cursor::new(2, 1));
+    editor.action(&mut font_system, Action::Backspace);
+    assert_eq!(test_lines(&editor)[2], "]");
+}
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_smart_home() {
+    let mut font_system = FontSystem::new();
+    let mut editor = test_editor(&mut font_system, "    abc");
+    editor.set_cursor(Cursor::new(0, 6));
+    editor.action(&mut font_system, Action::Home);
+    assert_eq!(editor.cursor().index, 0);
+
+    editor.set_behavior(EditorBehavior {
+        smart_home: true,
+        ..EditorBehavior::new()
+    });
+    editor.set_cursor(Cursor::new(0, 6));
+    editor.buffer_mut().set_redraw(false);
+    editor.action(&mut font_system, Action::Home);
+    assert_eq!(editor.cursor().index, 4);
+    assert!(editor.buffer().redraw());
+    editor.action(&mut font_system, Action::Home);
+    assert_eq!(editor.cursor().index, 0);
+    editor.action(&mut font_system, Action::Home);
+    assert_eq!(editor.cursor().index, 4);
+    editor.set_cursor(Cursor::new(0, 2));
+    editor.action(&mut font_system, Action::Home);
+    assert_eq!(editor.cursor().index, 4);
+}
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_indent() {
+    let mut font_system = FontSystem::new();
+    let mut editor = test_editor(&mut font_system, "a\n\n  b\nc");
+    editor.buffer_mut().set_expand_tabs(true);
+
+    // Lines of the selection are indented, except empty lines and a line where it ends at the
+    // start
+    editor.set_select_opt(Some(Cursor::new(0, 1)));
+    editor.set_cursor(Cursor::new(3, 0));
+    editor.action(&mut font_system, Action::Indent);
+    assert_eq!(test_lines(&editor), ["    a", "", "      b", "c"]);
+    assert_eq!(editor.select_opt(), Some(Cursor::new(0, 5)));
+    assert_eq!(editor.cursor(), Cursor::new(3, 0));
+
+    editor.action(&mut font_system, Action::Unindent);
+    editor.action(&mut font_system, Action::Unindent);
+    assert_eq!(test_lines(&editor), ["a", "", "b", "c"]);
+    assert_eq!(editor.select_opt(), Some(Cursor::new(0, 1)));
+
+    // Without a selection, spaces are inserted up to the next tab stop
+    editor.set_select_opt(None);
+    editor.set_cursor(Cursor::new(3, 1));
+    editor.action(&mut font_system, Action::Indent);
+    assert_eq!(test_lines(&editor)[3], "c   ");
+    editor.buffer_mut().set_expand_tabs(false);
+    editor.action(&mut font_system, Action::Indent);
+    assert_eq!(test_lines(&editor)[3], "c   \t");
+
+    // Unindenting removes one level from the line of the cursor
+    editor.set_cursor(Cursor::new(3, 0));
+    editor.action(&mut font_system, Action::Indent);
+    editor.action(&mut font_system, Action::Unindent);
+    assert_eq!(test_lines(&editor)[3], "c   \t");
+}
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_extend_selection() {
+    let mut font_system = FontSystem::new();
+    let mut editor = test_editor(&mut font_system, "one two three\nfour five\nsix");
+
+    // Get the editor coordinates of the glyph starting at a cursor
+    let at = |editor: &Editor, line_i: usize, index: usize| {
+        let run = editor
+            .buffer()
+            .layout_runs()
+            .find(|run| run.line_i == line_i)
+            .expect("run not found");
+        let glyph = run
+            .glyphs
+            .iter()
+            .find(|glyph| glyph.start == index)
+            .expect("glyph not found");
+        (glyph.x as i32 + 1, run.line_top as i32 + 1)
+    };
+    let selection = |editor: &Editor| {
+        let select = editor.select_opt().expect("no selection");
+        let cursor = editor.cursor();
+        ((select.line, select.index), (cursor.line, cursor.index))
+    };
+
+    // By character, from the cursor
+    let (x, y) = at(&editor, 0, 4);
+    editor.action(&mut font_system, Action::Click { x, y });
+    let (x, y) = at(&editor, 1, 2);
+    editor.action(&mut font_system, Action::ShiftClick { x, y });
+    assert_eq!(selection(&editor), ((0, 4), (1, 2)));
+    let (x, y) = at(&editor, 0, 1);
+    editor.action(&mut font_system, Action::ShiftClick { x, y });
+    assert_eq!(selection(&editor), ((0, 4), (0, 1)));
+    let (x, y) = at(&editor, 0, 2);
+    editor.action(&mut font_system, Action::Drag { x, y });
+    assert_eq!(selection(&editor), ((0, 4), (0, 2)));
+
+    // By word, keeping the word that was double clicked
+    let (x, y) = at(&editor, 0, 5);
+    editor.action(&mut font_system, Action::DoubleClick { x, y });
+    assert_eq!(selection(&editor), ((0, 4), (0, 7)));
+    let (x, y) = at(&editor, 0, 9);
+    editor.action(&mut font_system, Action::Drag { x, y });
+    assert_eq!(selection(&editor), ((0, 4), (0, 13)));
+    let (x, y) = at(&editor, 0, 1);
+    editor.action(&mut font_system, Action::Drag { x, y });
+    assert_eq!(selection(&editor), ((0, 7), (0, 0)));
+    let (x, y) = at(&editor, 1, 7);
+    editor.action(&mut font_system, Action::ShiftClick { x, y });
+    assert_eq!(selection(&editor), ((0, 4), (1, 9)));
+
+    // By line, including the line ending
+    let (x, y) = at(&editor, 1, 2);
+    editor.action(&mut font_system, Action::TripleClick { x, y });
+    assert_eq!(selection(&editor), ((1, 0), (2, 0)));
+    let (x, y) = at(&editor, 2, 1);
+    editor.action(&mut font_system, Action::Drag { x, y });
+    assert_eq!(selection(&editor), ((1, 0), (2, 3)));
+    let (x, y) = at(&editor, 0, 2);
+    editor.action(&mut font_system, Action::Drag { x, y });
+    assert_eq!(selection(&editor), ((2, 0), (0, 0)));
+
+    // Other actions stop extending by word or line
+    editor.action(&mut font_system, Action::Escape);
+    let (x, y) = at(&editor, 1, 7);
+    editor.action(&mut font_system, Action::ShiftClick { x, y });
+    assert_eq!(selection(&editor), ((0, 0), (1, 7)));
+}
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_vertical() {
+    let mut font_system = FontSystem::new();
+    let mut editor = test_editor(&mut font_system, "0\n1\n2\n3\n4\n5\n6\n7\n8\n9");
+    // Lines are made taller than `line_height` to fit their glyphs
+    editor
+        .buffer_mut()
+        .set_metrics(&mut font_system, crate::Metrics::new(14.0, 5.0));
+    editor.shape_as_needed(&mut font_system);
+    let run = editor
+        .buffer()
+        .layout_runs()
+        .next()
+        .expect("no layout runs");
+    let line_height = run.line_height;
+    assert!(line_height > 10.0);
+
+    // The cursor moves by the real height of each line
+    editor.action(
+        &mut font_system,
+        Action::Vertical((3.5 * line_height) as i32),
+    );
+    assert_eq!(editor.cursor().line, 3);
+    editor.action(
+        &mut font_system,
+        Action::Vertical(-(2.5 * line_height) as i32),
+    );
+    assert_eq!(editor.cursor().line, 1);
+}
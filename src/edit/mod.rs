@@ -3,21 +3,38 @@ use alloc::string::String;
 
 #[cfg(feature = "swash")]
 use crate::Color;
-use crate::{AttrsList, BorrowedWithFontSystem, Buffer, Cursor, FontSystem};
+use crate::{AttrsList, BorrowedWithFontSystem, Buffer, Cursor, FontSystem, RichText};
+
+pub use self::behavior::*;
+mod behavior;
+
+pub use self::document::*;
+mod document;
 
 pub use self::editor::*;
 mod editor;
 
+pub use self::style::*;
+mod style;
+
 #[cfg(feature = "syntect")]
 pub use self::syntect::*;
 #[cfg(feature = "syntect")]
 mod syntect;
 
+#[cfg(feature = "tree-sitter")]
+pub use self::tree_sitter::*;
+#[cfg(feature = "tree-sitter")]
+mod tree_sitter;
+
 #[cfg(feature = "vi")]
 pub use self::vi::*;
 #[cfg(feature = "vi")]
 mod vi;
 
+pub use self::word::*;
+mod word;
+
 /// An action to perform on an [`Editor`]
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Action {
@@ -35,6 +52,8 @@ pub enum Action {
     Down,
     /// Move cursor to start of line
     Home,
+    /// Move cursor to the first non-whitespace character of the line
+    SoftHome,
     /// Move cursor to end of line
     End,
     /// Move cursor to start of paragraph
@@ -57,9 +76,21 @@ pub enum Action {
     Backspace,
     /// Delete text in front of cursor
     Delete,
+    /// Indent the lines of the selection, or insert indentation at the cursor
+    Indent,
+    /// Remove one level of indentation from the lines of the selection, or the line of the cursor
+    Unindent,
     /// Mouse click at specified position
     Click { x: i32, y: i32 },
-    /// Mouse drag to specified position
+    /// Mouse double click at specified position, selecting the word there
+    DoubleClick { x: i32, y: i32 },
+    /// Mouse triple click at specified position, selecting the line there
+    TripleClick { x: i32, y: i32 },
+    /// Mouse click at specified position with shift held, extending the selection from its
+    /// start, or from the cursor if there is none
+    ShiftClick { x: i32, y: i32 },
+    /// Mouse drag to specified position, extending the selection by words or lines after a
+    /// double or triple click
     Drag { x: i32, y: i32 },
     /// Scroll specified number of lines
     Scroll { lines: i32 },
@@ -102,18 +133,87 @@ pub trait Edit {
     /// Get the current cursor position
     fn cursor(&self) -> Cursor;
 
+    /// Set the current cursor position
+    fn set_cursor(&mut self, cursor: Cursor);
+
     /// Get the current selection position
     fn select_opt(&self) -> Option<Cursor>;
 
     /// Set the current selection position
     fn set_select_opt(&mut self, select_opt: Option<Cursor>);
 
+    /// True if the text was changed since it was loaded or saved
+    ///
+    /// Only changes made through the editor are tracked, not changes made directly to the
+    /// [`Buffer`]. The default implementation does not track changes and returns false.
+    fn changed(&self) -> bool {
+        false
+    }
+
+    /// Set the changed flag, for example after saving the text through other means
+    ///
+    /// The default implementation does nothing
+    fn set_changed(&mut self, changed: bool) {
+        let _ = changed;
+    }
+
+    /// Get the [`EditorBehavior`] used when editing
+    ///
+    /// The default implementation returns [`EditorBehavior::default`]
+    fn behavior(&self) -> EditorBehavior {
+        EditorBehavior::default()
+    }
+
+    /// Set the [`EditorBehavior`] used when editing
+    ///
+    /// The default implementation does nothing
+    fn set_behavior(&mut self, behavior: EditorBehavior) {
+        let _ = behavior;
+    }
+
+    /// Get the [`EditorStyle`] used when drawing
+    ///
+    /// The default implementation returns [`EditorStyle::default`]
+    fn style(&self) -> EditorStyle {
+        EditorStyle::default()
+    }
+
+    /// Set the [`EditorStyle`] used when drawing
+    ///
+    /// The default implementation does nothing
+    fn set_style(&mut self, style: EditorStyle) {
+        let _ = style;
+    }
+
+    /// Get the width of the line number gutter in pixels, as of the last call to
+    /// [`Self::shape_as_needed`]
+    ///
+    /// The gutter is drawn before the text, so the [`Buffer`] width should be the editor width
+    /// minus the gutter width. Mouse actions use editor coordinates, including the gutter.
+    ///
+    /// The default implementation has no gutter and returns 0
+    fn gutter_width(&self) -> i32 {
+        0
+    }
+
     /// Shape lines until scroll, after adjusting scroll if the cursor moved
     fn shape_as_needed(&mut self, font_system: &mut FontSystem);
 
     /// Copy selection
     fn copy_selection(&mut self) -> Option<String>;
 
+    /// Copy selection, with the attributes of its text
+    fn copy_selection_rich(&self) -> Option<RichText> {
+        let select = self.select_opt()?;
+        let cursor = self.cursor();
+        let (start, end) = if select < cursor {
+            (select, cursor)
+        } else {
+            (cursor, select)
+        };
+        Some(RichText::from_buffer(self.buffer(), start, end))
+    }
+
     /// Delete selection, adjusting cursor and returning true if there was a selection
     // Also used by backspace, delete, insert, and enter when there is a selection
     fn delete_selection(&mut self) -> bool;
@@ -122,6 +222,15 @@ pub trait Edit {
     /// attributes, or with the previous character's attributes if None is given.
     fn insert_string(&mut self, data: &str, attrs_list: Option<AttrsList>);
 
+    /// Insert text with attributes at the current cursor or replacing the current selection
+    fn insert_rich_text(&mut self, rich_text: &RichText) {
+        self.delete_selection();
+        let defaults = self.buffer().lines[self.cursor().line]
+            .attrs_list()
+            .defaults();
+        self.insert_string(&rich_text.text(), Some(rich_text.attrs_list(defaults)));
+    }
+
     /// Perform an [Action] on the editor
     fn action(&mut self, font_system: &mut FontSystem, action: Action);
 
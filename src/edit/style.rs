@@ -0,0 +1,243 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+#[cfg(feature = "swash")]
+use core::cmp;
+
+use crate::{Attrs, AttrsList, AttrsOwned, Buffer, Color, FontSystem, LayoutLine, ShapeLine, Wrap};
+
+/// Line numbers shown in the gutter of an editor
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LineNumbers {
+    /// Line numbers starting at 1
+    Absolute,
+    /// Distance from the line with the cursor, which shows its absolute line number
+    Relative,
+}
+
+/// Colors and sizes used when drawing an editor, in addition to the text color
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EditorStyle {
+    /// Fill color of the selection, or None to use the text color with low opacity
+    pub selection_opt: Option<Color>,
+    /// Color of the caret, or None to use the text color
+    pub caret_opt: Option<Color>,
+    /// Width of the caret in pixels
+    pub caret_width: u32,
+    /// Fill color of the line with the cursor, or None to not highlight it
+    pub line_highlight_opt: Option<Color>,
+    /// Line numbers to show in a gutter before the text, which also marks folded lines, or None
+    /// to not show a gutter
+    pub line_numbers_opt: Option<LineNumbers>,
+    /// Background color of the line number gutter, or None to use the editor background
+    pub gutter_opt: Option<Color>,
+    /// Color of line numbers in the gutter, or None to use the text color
+    pub gutter_foreground_opt: Option<Color>,
+}
+
+impl EditorStyle {
+    /// Create a new [`EditorStyle`], which uses the text color for everything
+    pub const fn new() -> Self {
+        Self {
+            selection_opt: None,
+            caret_opt: None,
+            caret_width: 1,
+            line_highlight_opt: None,
+            line_numbers_opt: None,
+            gutter_opt: None,
+            gutter_foreground_opt: None,
+        }
+    }
+
+    /// Get the selection color, based on the text color if not set
+    pub fn selection(&self, color: Color) -> Color {
+        self.selection_opt
+            .unwrap_or_else(|| Color::rgba(color.r(), color.g(), color.b(), 0x33))
+    }
+
+    /// Get the caret color, which is the text color if not set
+    pub fn caret(&self, color: Color) -> Color {
+        self.caret_opt.unwrap_or(color)
+    }
+}
+
+impl Default for EditorStyle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The line number gutter of an editor, with the digits used for line numbers shaped once for
+/// each font size and attributes
+pub(crate) struct Gutter {
+    width: i32,
+    /// Font size and attributes of `digits`
+    key_opt: Option<(f32, AttrsOwned)>,
+    /// Layout of each digit from 0 to 9
+    digits: Vec<LayoutLine>,
+}
+
+impl Gutter {
+    pub(crate) const fn new() -> Self {
+        Self {
+            width: 0,
+            key_opt: None,
+            digits: Vec::new(),
+        }
+    }
+
+    /// Get the width of the gutter in pixels, as of the last call to [`Self::update`]
+    pub(crate) fn width(&self) -> i32 {
+        self.width
+    }
+
+    /// Update the gutter for a buffer, returning true if its width changed. The width is zero if
+    /// there are no line numbers.
+    pub(crate) fn update(
+        &mut self,
+        font_system: &mut FontSystem,
+        buffer: &Buffer,
+        style: &EditorStyle,
+    ) -> bool {
+        let width = if style.line_numbers_opt.is_some() {
+            // Reserve space for at least two digits, so that it only changes with large buffers
+            let mut digits = 2;
+            let mut lines = buffer.lines.len() / 100;
+            while lines > 0 {
+                digits += 1;
+                lines /= 10;
+            }
+
+            let font_size = buffer.metrics().font_size;
+            let digit_width = self.shape_digits(font_system, font_size, gutter_attrs(buffer));
+
+            // Half of the font size is used as padding on each side
+            libm::ceilf(digits as f32 * digit_width + font_size) as i32
+        } else {
+            0
+        };
+
+        if width != self.width {
+            self.width = width;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Shape the digits if the font size or attributes changed, returning the widest digit width
+    fn shape_digits(&mut self, font_system: &mut FontSystem, font_size: f32, attrs: Attrs) -> f32 {
+        let matches = self.key_opt.as_ref().is_some_and(|(key_size, key_attrs)| {
+            *key_size == font_size && key_attrs.as_attrs() == attrs
+        });
+        if !matches {
+            let attrs_list = AttrsList::new(attrs);
+            self.digits = ('0'..='9')
+                .filter_map(|digit| {
+                    let mut text = [0; 4];
+                    ShapeLine::new(font_system, digit.encode_utf8(&mut text), &attrs_list)
+                        .layout(font_size, f32::MAX, Wrap::None, None)
+                        .into_iter()
+                        .next()
+                })
+                .collect();
+            self.key_opt = Some((font_size, AttrsOwned::new(attrs)));
+        }
+
+        self.digits
+            .iter()
+            .map(|layout_line| layout_line.w)
+            .reduce(f32::max)
+            .unwrap_or(font_size / 2.0)
+    }
+
+    /// Draw the gutter, with line numbers on the first visual line of each line and a marker on
+    /// the placeholder of each fold
+    #[cfg(feature = "swash")]
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn draw<F>(
+        &self,
+        font_system: &mut FontSystem,
+        cache: &mut crate::SwashCache,
+        buffer: &Buffer,
+        cursor_line: usize,
+        style: &EditorStyle,
+        color: Color,
+        f: &mut F,
+    ) where
+        F: FnMut(i32, i32, u32, u32, Color),
+    {
+        let line_numbers = match style.line_numbers_opt {
+            Some(some) => some,
+            None => return,
+        };
+
+        if let Some(gutter) = style.gutter_opt {
+            f(0, 0, self.width as u32, buffer.size().1 as u32, gutter);
+        }
+
+        let font_size = buffer.metrics().font_size;
+        let number_color = style.gutter_foreground_opt.unwrap_or(color);
+        for run in buffer.layout_runs() {
+            if run.fold_opt.is_some() {
+                // A triangle pointing right, in the padding before the numbers
+                let size = cmp::max((font_size / 2.0) as i32, 2);
+                let x = (font_size / 8.0) as i32;
+                let y = (run.line_top + (run.line_height - size as f32) / 2.0) as i32;
+                for row in 0..size {
+                    let w = cmp::min(row, size - 1 - row) + 1;
+                    f(x, y + row, w as u32, 1, number_color);
+                }
+                continue;
+            }
+
+            if run.layout_i != 0 {
+                continue;
+            }
+
+            let mut number = match line_numbers {
+                LineNumbers::Absolute => run.line_i + 1,
+                LineNumbers::Relative => {
+                    if run.line_i == cursor_line {
+                        run.line_i + 1
+                    } else {
+                        run.line_i.abs_diff(cursor_line)
+                    }
+                }
+            };
+
+            // Draw digits from the right, before the padding
+            let mut x = self.width as f32 - font_size / 2.0;
+            loop {
+                if let Some(layout_line) = self.digits.get(number % 10) {
+                    x -= layout_line.w;
+                    for glyph in layout_line.glyphs.iter() {
+                        let (cache_key, x_int, y_int) = (glyph.cache_key, glyph.x_int, glyph.y_int);
+                        cache.with_pixels(font_system, cache_key, number_color, |px, py, color| {
+                            f(
+                                x as i32 + x_int + px,
+                                run.line_y as i32 + y_int + py,
+                                1,
+                                1,
+                                color,
+                            );
+                        });
+                    }
+                }
+                number /= 10;
+                if number == 0 {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Get the attributes of line numbers, which use the default attributes of the first line
+fn gutter_attrs(buffer: &Buffer) -> Attrs {
+    buffer
+        .lines
+        .first()
+        .map_or(Attrs::new(), |line| line.attrs_list().defaults())
+}
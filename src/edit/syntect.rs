@@ -1,17 +1,22 @@
 #[cfg(not(feature = "std"))]
-use alloc::{string::String, vec::Vec};
+use alloc::{string::String, vec, vec::Vec};
+use core::{cmp, mem, ops::Range};
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+use std::time::{Duration, Instant};
 #[cfg(feature = "std")]
-use std::{fs, io, path::Path};
+use std::{io, path::Path};
 use syntect::highlighting::{
     FontStyle, HighlightState, Highlighter, RangedHighlightIterator, Theme, ThemeSet,
 };
 use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
 
 use crate::{
-    Action, AttrsList, BorrowedWithFontSystem, Buffer, Color, Cursor, Edit, Editor, FontSystem,
-    Style, Weight, Wrap,
+    Action, AttrsList, BorrowedWithFontSystem, Buffer, Color, Cursor, Edit, Editor, EditorBehavior,
+    EditorStyle, FontSystem, RichText, Style, Weight,
 };
 
+use super::behavior::{column, indent_len};
+
 pub struct SyntaxSystem {
     pub syntax_set: SyntaxSet,
     pub theme_set: ThemeSet,
@@ -28,14 +33,110 @@ impl SyntaxSystem {
     }
 }
 
+fn syntect_color(color: syntect::highlighting::Color) -> Color {
+    Color::rgba(color.r, color.g, color.b, color.a)
+}
+
+/// Get the [`EditorStyle`] from the settings of a theme
+fn theme_style(theme: &Theme) -> EditorStyle {
+    let settings = &theme.settings;
+    EditorStyle {
+        selection_opt: settings.selection.map(syntect_color),
+        caret_opt: settings.caret.map(syntect_color),
+        line_highlight_opt: settings.line_highlight.map(syntect_color),
+        gutter_opt: settings.gutter.map(syntect_color),
+        gutter_foreground_opt: settings.gutter_foreground.map(syntect_color),
+        ..EditorStyle::new()
+    }
+}
+
+/// Method used by [`SyntaxEditor::fold_ranges`] to find blocks of lines that can be folded
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FoldMethod {
+    /// Lines indented further than the line before them
+    Indent,
+    /// Lines starting inside syntax scopes opened by the line before them, such as blocks and
+    /// multi-line comments
+    Scope,
+}
+
+/// Get the indentation width of a line, with tabs advancing to the next tab stop, or None if
+/// the line is blank
+fn indent_level(text: &str, tab_width: u16) -> Option<usize> {
+    let indent_len = indent_len(text);
+    if indent_len < text.len() {
+        Some(column(text, indent_len, tab_width))
+    } else {
+        None
+    }
+}
+
+/// Get the ranges of lines nested deeper than the line before them, given the nesting level of
+/// each line, or None for blank lines. Blank lines at the end of a block are not included.
+fn nested_ranges(levels: &[Option<usize>]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    // Lines that may start a block, with their levels increasing
+    let mut starts: Vec<(usize, usize)> = Vec::new();
+    let mut last_line_i = 0;
+    for (line_i, level_opt) in levels.iter().enumerate() {
+        let level = match level_opt {
+            Some(some) => *some,
+            None => continue,
+        };
+        while let Some(&(start_i, start_level)) = starts.last() {
+            if start_level < level {
+                break;
+            }
+            starts.pop();
+            if last_line_i > start_i {
+                ranges.push(start_i + 1..last_line_i + 1);
+            }
+        }
+        starts.push((line_i, level));
+        last_line_i = line_i;
+    }
+    for (start_i, _) in starts {
+        if last_line_i > start_i {
+            ranges.push(start_i + 1..last_line_i + 1);
+        }
+    }
+    ranges.sort_by_key(|range| range.start);
+    ranges
+}
+
+/// Syntax state at the start of a line
+type SyntaxState = (ParseState, HighlightState);
+
+/// Highlighting done before an edit, which can be resumed if the syntax state after the edit
+/// matches one of its checkpoints
+struct SyntaxResume {
+    /// Checkpoints after the edit, with line numbers adjusted for added or removed lines
+    checkpoints: Vec<(usize, SyntaxState)>,
+    /// Number of highlighted lines, adjusted for added or removed lines
+    highlighted: usize,
+    /// Syntax state at the start of the first line that was not highlighted
+    state: SyntaxState,
+}
+
 /// A wrapper of [`Editor`] with syntax highlighting provided by [`SyntaxSystem`]
+///
+/// Only the lines up to the end of the view, plus a margin, are highlighted when shaping. The
+/// rest of the buffer can be highlighted when idle using [`Self::highlight_ahead`].
 pub struct SyntaxEditor<'a> {
     editor: Editor,
     syntax_system: &'a SyntaxSystem,
     syntax: &'a SyntaxReference,
     theme: &'a Theme,
     highlighter: Highlighter<'a>,
-    syntax_cache: Vec<(ParseState, HighlightState)>,
+    /// Syntax state at the start of every `checkpoint_interval` lines, sorted by line
+    syntax_cache: Vec<(usize, SyntaxState)>,
+    /// Syntax state at the start of the first line that was not highlighted
+    syntax_state: SyntaxState,
+    highlighted: usize,
+    highlighted_lines_len: usize,
+    resume_opt: Option<SyntaxResume>,
+    checkpoint_interval: usize,
+    highlight_margin: usize,
 }
 
 impl<'a> SyntaxEditor<'a> {
@@ -45,10 +146,16 @@ impl<'a> SyntaxEditor<'a> {
     ///
     /// Returns None if theme not found
     pub fn new(buffer: Buffer, syntax_system: &'a SyntaxSystem, theme_name: &str) -> Option<Self> {
-        let editor = Editor::new(buffer);
+        let mut editor = Editor::new(buffer);
+        editor.set_track_changes(true);
         let syntax = syntax_system.syntax_set.find_syntax_plain_text();
         let theme = syntax_system.theme_set.themes.get(theme_name)?;
+        editor.set_style(theme_style(theme));
         let highlighter = Highlighter::new(theme);
+        let syntax_state = (
+            ParseState::new(syntax),
+            HighlightState::new(&highlighter, ScopeStack::new()),
+        );
 
         Some(Self {
             editor,
@@ -56,15 +163,23 @@ impl<'a> SyntaxEditor<'a> {
             syntax,
             theme,
             highlighter,
-            syntax_cache: Vec::new(),
+            syntax_cache: vec![(0, syntax_state.clone())],
+            syntax_state,
+            highlighted: 0,
+            highlighted_lines_len: 0,
+            resume_opt: None,
+            checkpoint_interval: 64,
+            highlight_margin: 128,
         })
     }
 
     /// Load text from a file, and also set syntax to the best option
     ///
+    /// The format and line endings of the file are kept, see [`Editor::load_text`]
+    ///
     /// ## Errors
     ///
-    /// Returns an [`io::Error`] if reading the file fails
+    /// Returns an [`io::Error`] if reading the file fails, or if it could not be decoded
     #[cfg(feature = "std")]
     pub fn load_text<P: AsRef<Path>>(
         &mut self,
@@ -74,8 +189,7 @@ impl<'a> SyntaxEditor<'a> {
     ) -> io::Result<()> {
         let path = path.as_ref();
 
-        let text = fs::read_to_string(path)?;
-        self.editor.buffer_mut().set_text(font_system, &text, attrs);
+        self.editor.load_text(font_system, path, attrs)?;
 
         //TODO: re-use text
         self.syntax = match self.syntax_system.syntax_set.find_syntax_for_file(path) {
@@ -90,16 +204,98 @@ impl<'a> SyntaxEditor<'a> {
             }
         };
 
-        // Clear syntax cache
-        self.syntax_cache.clear();
+        self.reset_highlighting();
 
         Ok(())
     }
 
+    /// Save text to a file in the format it was loaded with, and clear the changed flag
+    ///
+    /// ## Errors
+    ///
+    /// Returns an [`io::Error`] if writing the file fails
+    #[cfg(feature = "std")]
+    pub fn save_text<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        self.editor.save_text(path)
+    }
+
+    /// Set the number of lines past the end of the view, or the cursor, that are highlighted by
+    /// [`Edit::shape_as_needed`]
+    pub fn set_highlight_margin(&mut self, lines: usize) {
+        self.highlight_margin = lines;
+    }
+
+    /// Set the number of lines between saved syntax states
+    ///
+    /// After an edit, highlighting restarts from the last saved state before it. Smaller intervals
+    /// make this faster, at the cost of memory.
+    pub fn set_checkpoint_interval(&mut self, lines: usize) {
+        self.checkpoint_interval = cmp::max(lines, 1);
+    }
+
+    /// Get highlighting progress as of the last highlighting pass, as the number of highlighted
+    /// lines and the total number of lines
+    pub fn highlight_progress(&self) -> (usize, usize) {
+        (self.highlighted, self.highlighted_lines_len)
+    }
+
+    /// Highlight lines that are not yet highlighted, such as those past the end of the view,
+    /// until `budget` has elapsed. This is intended to be called when the application is idle.
+    ///
+    /// Lines are not shaped, so this does not require a redraw.
+    ///
+    /// Returns true if there are more lines to highlight
+    #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+    pub fn highlight_ahead(&mut self, budget: Duration) -> bool {
+        let now = Instant::now();
+        let highlighted = self.highlight_until(usize::MAX, || now.elapsed() >= budget);
+        if highlighted > 0 {
+            log::debug!(
+                "Syntax highlighted {} lines ahead in {:?}",
+                highlighted,
+                now.elapsed()
+            );
+        }
+        self.highlighted < self.highlighted_lines_len
+    }
+
+    /// Get the ranges of lines that can be folded, sorted by their first line
+    ///
+    /// Each range is hidden below the line before it, so nested blocks produce overlapping
+    /// ranges. Pass one of them to [`Buffer::fold`] to fold it.
+    pub fn fold_ranges(&self, method: FoldMethod) -> Vec<Range<usize>> {
+        let lines = &self.editor.buffer().lines;
+        let tab_width = self.editor.buffer().tab_width();
+        let levels: Vec<Option<usize>> = match method {
+            FoldMethod::Indent => lines
+                .iter()
+                .map(|line| indent_level(line.text(), tab_width))
+                .collect(),
+            FoldMethod::Scope => {
+                let mut parse_state = ParseState::new(self.syntax);
+                let mut scope_stack = ScopeStack::new();
+                let mut levels = Vec::with_capacity(lines.len());
+                for line in lines.iter() {
+                    let text = line.text();
+                    levels.push(indent_level(text, tab_width).map(|_| scope_stack.len()));
+
+                    let ops = parse_state
+                        .parse_line(text, &self.syntax_system.syntax_set)
+                        .expect("failed to parse syntax");
+                    for (_, op) in ops.iter() {
+                        scope_stack.apply(op).expect("failed to apply scope");
+                    }
+                }
+                levels
+            }
+        };
+        nested_ranges(&levels)
+    }
+
     /// Get the default background color
     pub fn background_color(&self) -> Color {
         if let Some(background) = self.theme.settings.background {
-            Color::rgba(background.r, background.g, background.b, background.a)
+            syntect_color(background)
         } else {
             Color::rgb(0, 0, 0)
         }
@@ -108,7 +304,7 @@ impl<'a> SyntaxEditor<'a> {
     /// Get the default foreground (text) color
     pub fn foreground_color(&self) -> Color {
         if let Some(foreground) = self.theme.settings.foreground {
-            Color::rgba(foreground.r, foreground.g, foreground.b, foreground.a)
+            syntect_color(foreground)
         } else {
             Color::rgb(0xFF, 0xFF, 0xFF)
         }
@@ -128,6 +324,10 @@ impl<'a> Edit for SyntaxEditor<'a> {
         self.editor.cursor()
     }
 
+    fn set_cursor(&mut self, cursor: Cursor) {
+        self.editor.set_cursor(cursor);
+    }
+
     fn select_opt(&self) -> Option<Cursor> {
         self.editor.select_opt()
     }
@@ -136,88 +336,45 @@ impl<'a> Edit for SyntaxEditor<'a> {
         self.editor.set_select_opt(select_opt);
     }
 
-    fn shape_as_needed(&mut self, font_system: &mut FontSystem) {
-        #[cfg(feature = "std")]
-        let now = std::time::Instant::now();
+    fn changed(&self) -> bool {
+        self.editor.changed()
+    }
 
-        let buffer = self.editor.buffer_mut();
+    fn set_changed(&mut self, changed: bool) {
+        self.editor.set_changed(changed);
+    }
 
-        let mut highlighted = 0;
-        for line_i in 0..buffer.lines.len() {
-            let line = &mut buffer.lines[line_i];
-            if !line.is_reset() && line_i < self.syntax_cache.len() {
-                continue;
-            }
-            highlighted += 1;
+    fn behavior(&self) -> EditorBehavior {
+        self.editor.behavior()
+    }
 
-            let (mut parse_state, mut highlight_state) =
-                if line_i > 0 && line_i <= self.syntax_cache.len() {
-                    self.syntax_cache[line_i - 1].clone()
-                } else {
-                    (
-                        ParseState::new(self.syntax),
-                        HighlightState::new(&self.highlighter, ScopeStack::new()),
-                    )
-                };
+    fn set_behavior(&mut self, behavior: EditorBehavior) {
+        self.editor.set_behavior(behavior);
+    }
 
-            let ops = parse_state
-                .parse_line(line.text(), &self.syntax_system.syntax_set)
-                .expect("failed to parse syntax");
-            let ranges = RangedHighlightIterator::new(
-                &mut highlight_state,
-                &ops,
-                line.text(),
-                &self.highlighter,
-            );
+    fn style(&self) -> EditorStyle {
+        self.editor.style()
+    }
 
-            let attrs = line.attrs_list().defaults();
-            let mut attrs_list = AttrsList::new(attrs);
-            for (style, _, range) in ranges {
-                attrs_list.add_span(
-                    range,
-                    attrs
-                        .color(Color::rgba(
-                            style.foreground.r,
-                            style.foreground.g,
-                            style.foreground.b,
-                            style.foreground.a,
-                        ))
-                        //TODO: background
-                        .style(if style.font_style.contains(FontStyle::ITALIC) {
-                            Style::Italic
-                        } else {
-                            Style::Normal
-                        })
-                        .weight(if style.font_style.contains(FontStyle::BOLD) {
-                            Weight::BOLD
-                        } else {
-                            Weight::NORMAL
-                        }), //TODO: underline
-                );
-            }
+    fn set_style(&mut self, style: EditorStyle) {
+        self.editor.set_style(style);
+    }
 
-            // Update line attributes. This operation only resets if the line changes
-            line.set_attrs_list(attrs_list);
-            line.set_wrap(Wrap::Word);
+    fn gutter_width(&self) -> i32 {
+        self.editor.gutter_width()
+    }
 
-            //TODO: efficiently do syntax highlighting without having to shape whole buffer
-            buffer.line_shape(font_system, line_i);
+    fn shape_as_needed(&mut self, font_system: &mut FontSystem) {
+        #[cfg(feature = "std")]
+        let now = std::time::Instant::now();
 
-            let cache_item = (parse_state.clone(), highlight_state.clone());
-            if line_i < self.syntax_cache.len() {
-                if self.syntax_cache[line_i] != cache_item {
-                    self.syntax_cache[line_i] = cache_item;
-                    if line_i + 1 < buffer.lines.len() {
-                        buffer.lines[line_i + 1].reset();
-                    }
-                }
-            } else {
-                self.syntax_cache.push(cache_item);
-            }
-        }
+        // Highlight up to the end of the view and the cursor, plus a margin
+        let end = self.editor.highlight_end(self.highlight_margin);
+
+        let highlighted = self.highlight_until(end, || false);
 
         if highlighted > 0 {
-            buffer.set_redraw(true);
+            self.editor.buffer_mut().set_redraw(true);
             #[cfg(feature = "std")]
             log::debug!(
                 "Syntax highlighted {} lines in {:?}",
@@ -233,6 +390,10 @@ impl<'a> Edit for SyntaxEditor<'a> {
         self.editor.copy_selection()
     }
 
+    fn copy_selection_rich(&self) -> Option<RichText> {
+        self.editor.copy_selection_rich()
+    }
+
     fn delete_selection(&mut self) -> bool {
         self.editor.delete_selection()
     }
@@ -241,6 +402,10 @@ impl<'a> Edit for SyntaxEditor<'a> {
         self.editor.insert_string(data, attrs_list);
     }
 
+    fn insert_rich_text(&mut self, rich_text: &RichText) {
+        self.editor.insert_rich_text(rich_text);
+    }
+
     fn action(&mut self, font_system: &mut FontSystem, action: Action) {
         self.editor.action(font_system, action);
     }
@@ -257,12 +422,214 @@ impl<'a> Edit for SyntaxEditor<'a> {
         F: FnMut(i32, i32, u32, u32, Color),
     {
         let size = self.buffer().size();
-        f(0, 0, size.0 as u32, size.1 as u32, self.background_color());
+        f(
+            0,
+            0,
+            size.0 as u32 + self.gutter_width() as u32,
+            size.1 as u32,
+            self.background_color(),
+        );
         self.editor
             .draw(font_system, cache, self.foreground_color(), f);
     }
 }
 
+impl<'a> SyntaxEditor<'a> {
+    /// Discard all highlighting, for example when the syntax changes
+    fn reset_highlighting(&mut self) {
+        self.syntax_state = (
+            ParseState::new(self.syntax),
+            HighlightState::new(&self.highlighter, ScopeStack::new()),
+        );
+        self.syntax_cache = vec![(0, self.syntax_state.clone())];
+        self.highlighted = 0;
+        self.resume_opt = None;
+        // Positions of earlier changes no longer apply
+        self.editor.take_changes();
+    }
+
+    /// Restart highlighting from the last checkpoint before any lines changed since the last
+    /// highlighting pass. Changes made through the editor are found using
+    /// [`Editor::take_changes`]. Changes made directly to the buffer are detected by
+    /// [`crate::BufferLine::metadata`] being cleared, up to the end of the view plus the margin.
+    fn invalidate_changed_lines(&mut self) {
+        let lines_len = self.editor.buffer().lines.len();
+        let delta = lines_len as isize - self.highlighted_lines_len as isize;
+        self.highlighted_lines_len = lines_len;
+
+        // First and last changed lines, as numbered after the changes
+        let mut changed_opt: Option<(usize, usize)> = None;
+        for change in self.editor.take_changes() {
+            let start = change.start.line;
+            let old_end = change.end.line;
+            let new_end = start + change.text.matches('\n').count();
+            changed_opt = Some(match changed_opt {
+                Some((first, last)) => (
+                    cmp::min(first, start),
+                    if last > old_end {
+                        last - old_end + new_end
+                    } else {
+                        new_end
+                    },
+                ),
+                None => (start, new_end),
+            });
+        }
+
+        // Added lines may extend past the highlighted lines
+        let scan_end = cmp::min(
+            self.editor.highlight_end(self.highlight_margin),
+            self.highlighted + cmp::max(delta, 0) as usize,
+        );
+        let lines = &self.editor.buffer().lines;
+        for (line_i, line) in lines.iter().enumerate().take(scan_end) {
+            if line.metadata().is_none() {
+                changed_opt = match changed_opt {
+                    Some((first, last)) => Some((cmp::min(first, line_i), cmp::max(last, line_i))),
+                    None => Some((line_i, line_i)),
+                };
+            }
+        }
+
+        let (first_changed, last_changed) = match changed_opt {
+            Some(some) => some,
+            None => {
+                if delta != 0 {
+                    // Lines were added or removed without resetting any lines, start over
+                    self.reset_highlighting();
+                }
+                return;
+            }
+        };
+
+        if first_changed >= self.highlighted {
+            // Only lines that were not highlighted changed, but the highlighting done before an
+            // earlier edit may include them
+            self.resume_opt = None;
+            return;
+        }
+
+        // Keep checkpoints before the change, and keep checkpoints after the change for resuming
+        let unchanged_start = (last_changed + 1) as isize - delta;
+        let mut resume_checkpoints = Vec::new();
+        for (line_i, state) in mem::take(&mut self.syntax_cache) {
+            if line_i <= first_changed {
+                self.syntax_cache.push((line_i, state));
+            } else if line_i as isize >= unchanged_start {
+                resume_checkpoints.push(((line_i as isize + delta) as usize, state));
+            }
+        }
+
+        let (line_i, state) = self
+            .syntax_cache
+            .last()
+            .cloned()
+            .expect("syntax cache missing first line");
+        let resume_highlighted = self.highlighted as isize + delta;
+        let resume_state = mem::replace(&mut self.syntax_state, state);
+        self.highlighted = line_i;
+        self.resume_opt =
+            if resume_highlighted > last_changed as isize && !resume_checkpoints.is_empty() {
+                Some(SyntaxResume {
+                    checkpoints: resume_checkpoints,
+                    highlighted: resume_highlighted as usize,
+                    state: resume_state,
+                })
+            } else {
+                None
+            };
+    }
+
+    /// Highlight lines until `end`, or until `stop` returns true
+    ///
+    /// Returns the number of lines highlighted
+    fn highlight_until<F: FnMut() -> bool>(&mut self, end: usize, mut stop: F) -> usize {
+        self.invalidate_changed_lines();
+
+        let buffer = self.editor.buffer_mut();
+        let wrap = buffer.wrap();
+        let end = cmp::min(end, buffer.lines.len());
+
+        let mut highlighted = 0;
+        while self.highlighted < end && !stop() {
+            let line_i = self.highlighted;
+
+            // Skip to the highlighting done before an edit if the syntax state matches
+            if let Some(resume) = &mut self.resume_opt {
+                resume
+                    .checkpoints
+                    .retain(|(resume_i, _)| *resume_i >= line_i);
+                match resume.checkpoints.first() {
+                    Some((resume_i, state)) if *resume_i == line_i => {
+                        if *state == self.syntax_state {
+                            let resume = self.resume_opt.take().expect("resume missing");
+                            self.syntax_cache.extend(resume.checkpoints);
+                            self.syntax_state = resume.state;
+                            self.highlighted = resume.highlighted;
+                            continue;
+                        }
+                    }
+                    Some(_) => {}
+                    None => self.resume_opt = None,
+                }
+            }
+
+            let checkpoint_i = self
+                .syntax_cache
+                .last()
+                .map_or(0, |(checkpoint_i, _)| *checkpoint_i);
+            if line_i >= checkpoint_i + self.checkpoint_interval {
+                self.syntax_cache.push((line_i, self.syntax_state.clone()));
+            }
+
+            let line = &mut buffer.lines[line_i];
+            let (parse_state, highlight_state) = &mut self.syntax_state;
+            let ops = parse_state
+                .parse_line(line.text(), &self.syntax_system.syntax_set)
+                .expect("failed to parse syntax");
+            let ranges =
+                RangedHighlightIterator::new(highlight_state, &ops, line.text(), &self.highlighter);
+
+            let attrs = line.attrs_list().defaults();
+            let mut attrs_list = AttrsList::new(attrs);
+            for (style, _, range) in ranges {
+                attrs_list.add_span(
+                    range,
+                    attrs
+                        .color(Color::rgba(
+                            style.foreground.r,
+                            style.foreground.g,
+                            style.foreground.b,
+                            style.foreground.a,
+                        ))
+                        //TODO: background
+                        .style(if style.font_style.contains(FontStyle::ITALIC) {
+                            Style::Italic
+                        } else {
+                            Style::Normal
+                        })
+                        .weight(if style.font_style.contains(FontStyle::BOLD) {
+                            Weight::BOLD
+                        } else {
+                            Weight::NORMAL
+                        }), //TODO: underline
+                );
+            }
+
+            // Update line attributes. This operation only resets if the line changes
+            line.set_attrs_list(attrs_list);
+            line.set_wrap(wrap);
+            // Mark the line as highlighted, with the checkpoint it was highlighted from
+            line.set_metadata(self.syntax_cache.len() - 1);
+
+            self.highlighted += 1;
+            highlighted += 1;
+        }
+
+        highlighted
+    }
+}
+
 impl<'a, 'b> BorrowedWithFontSystem<'b, SyntaxEditor<'a>> {
     /// Load text from a file, and also set syntax to the best option
     ///
@@ -274,3 +641,128 @@ impl<'a, 'b> BorrowedWithFontSystem<'b, SyntaxEditor<'a>> {
         self.inner.load_text(self.font_system, path, attrs)
     }
 }
+
+#[test]
+fn test_nested_ranges() {
+    let text = "fn main() {\n    if x {\n        y();\n    }\n\n    z();\n}\n\nfn other() {}";
+    let levels: Vec<_> = text.split('\n').map(|line| indent_level(line, 4)).collect();
+    assert_eq!(nested_ranges(&levels), vec![1..6, 2..3]);
+
+    // Tabs advance to the next tab stop
+    let text = "a\n\tb\n        c";
+    let levels: Vec<_> = text.split('\n').map(|line| indent_level(line, 4)).collect();
+    assert_eq!(levels, [Some(0), Some(4), Some(8)]);
+    assert_eq!(nested_ranges(&levels), vec![1..3, 2..3]);
+    let levels: Vec<_> = text.split('\n').map(|line| indent_level(line, 8)).collect();
+    assert_eq!(nested_ranges(&levels), vec![1..3]);
+}
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_highlight_checkpoints() {
+    use crate::{Attrs, Metrics};
+
+    let mut font_system = FontSystem::new();
+    let syntax_system = SyntaxSystem::new();
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(14.0, 20.0));
+    let text: Vec<String> = (0..20).map(|i| format!("let x{} = {};", i, i)).collect();
+    buffer.set_text(&mut font_system, &text.join("\n"), Attrs::new());
+    let mut editor =
+        SyntaxEditor::new(buffer, &syntax_system, "base16-eighties.dark").expect("theme not found");
+    editor.syntax = syntax_system
+        .syntax_set
+        .find_syntax_by_extension("rs")
+        .expect("rust syntax not found");
+    editor.reset_highlighting();
+    editor.set_checkpoint_interval(4);
+
+    // Highlighting stops when asked to, and continues from there
+    let mut calls = 0;
+    let highlighted = editor.highlight_until(usize::MAX, || {
+        calls += 1;
+        calls > 6
+    });
+    assert_eq!(highlighted, 6);
+    assert_eq!(editor.highlight_progress(), (6, 20));
+    #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+    assert!(!editor.highlight_ahead(Duration::from_secs(60)));
+    assert_eq!(editor.highlight_until(usize::MAX, || false), 0);
+    assert_eq!(editor.highlight_progress(), (20, 20));
+    let checkpoints: Vec<usize> = editor.syntax_cache.iter().map(|(i, _)| *i).collect();
+    assert_eq!(checkpoints, [0, 4, 8, 12, 16]);
+
+    // Editing a line re-highlights from the checkpoint before it, until the syntax state
+    // matches the highlighting done before the edit
+    editor.buffer_mut().lines[9].set_text("let y = 0;", AttrsList::new(Attrs::new()));
+    assert_eq!(editor.highlight_until(usize::MAX, || false), 4);
+    assert_eq!(editor.highlight_progress(), (20, 20));
+    assert!(editor
+        .buffer()
+        .lines
+        .iter()
+        .all(|line| line.metadata().is_some()));
+
+    // Opening a comment changes the syntax state of every following line
+    editor.buffer_mut().lines[9].set_text("/* let y = 0;", AttrsList::new(Attrs::new()));
+    assert_eq!(editor.highlight_until(usize::MAX, || false), 12);
+
+    editor.buffer_mut().lines[9].set_text("let y = 0;", AttrsList::new(Attrs::new()));
+    assert_eq!(editor.highlight_until(usize::MAX, || false), 12);
+
+    // Inserting a line shifts the checkpoints after it
+    editor.buffer_mut().lines.insert(
+        1,
+        crate::BufferLine::new("let z = 0;", AttrsList::new(Attrs::new())),
+    );
+    assert_eq!(editor.highlight_until(usize::MAX, || false), 5);
+    assert_eq!(editor.highlight_progress(), (21, 21));
+    let checkpoints: Vec<usize> = editor.syntax_cache.iter().map(|(i, _)| *i).collect();
+    assert_eq!(checkpoints, [0, 4, 5, 9, 13, 17]);
+
+    // Changes made through the editor are found past the end of the view
+    editor.set_highlight_margin(0);
+    editor.set_cursor(Cursor::new(15, 0));
+    editor.insert_string("/* ", None);
+    editor.set_cursor(Cursor::new(0, 0));
+    assert_eq!(editor.highlight_until(usize::MAX, || false), 8);
+    assert_eq!(editor.highlight_progress(), (21, 21));
+}
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_theme_style() {
+    use crate::Metrics;
+
+    let syntax_system = SyntaxSystem::new();
+    let themes = &syntax_system.theme_set.themes;
+    assert_eq!(
+        theme_style(&themes["InspiredGitHub"]),
+        EditorStyle {
+            selection_opt: Some(Color::rgb(0xF8, 0xEE, 0xC7)),
+            caret_opt: Some(Color::rgb(0x32, 0x32, 0x32)),
+            line_highlight_opt: Some(Color::rgb(0xF5, 0xF5, 0xF5)),
+            gutter_opt: Some(Color::rgb(0xFF, 0xFF, 0xFF)),
+            gutter_foreground_opt: Some(Color::rgb(0xB3, 0xB3, 0xB3)),
+            ..EditorStyle::new()
+        }
+    );
+
+    // Colors missing from the theme are left unset
+    let style = theme_style(&themes["base16-eighties.dark"]);
+    assert_eq!(
+        style,
+        EditorStyle {
+            selection_opt: Some(Color::rgb(0x51, 0x51, 0x51)),
+            caret_opt: Some(Color::rgb(0xD3, 0xD0, 0xC8)),
+            line_highlight_opt: Some(Color::rgba(0x74, 0x73, 0x69, 0x30)),
+            ..EditorStyle::new()
+        }
+    );
+
+    // The editor starts with the style of its theme
+    let mut font_system = FontSystem::new();
+    let buffer = Buffer::new(&mut font_system, Metrics::new(14.0, 20.0));
+    let editor =
+        SyntaxEditor::new(buffer, &syntax_system, "base16-eighties.dark").expect("theme not found");
+    assert_eq!(editor.style(), style);
+}
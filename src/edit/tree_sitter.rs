@@ -0,0 +1,756 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+#[cfg(not(feature = "std"))]
+use alloc::{string::String, vec::Vec};
+use core::cmp;
+#[cfg(feature = "std")]
+use std::{io, path::Path};
+use tree_sitter::{
+    InputEdit, Language, LanguageError, Parser, Point, Query, QueryCursor, QueryError, Tree,
+};
+
+use crate::{
+    Action, AttrsList, BorrowedWithFontSystem, Buffer, Color, Cursor, Edit, Editor, EditorBehavior,
+    EditorStyle, FontSystem, RichText, Style, TextChange, Weight,
+};
+
+/// Style applied to text matching a highlight capture
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct HighlightStyle {
+    pub color_opt: Option<Color>,
+    pub weight_opt: Option<Weight>,
+    pub style_opt: Option<Style>,
+}
+
+impl HighlightStyle {
+    /// Create a new [`HighlightStyle`] that only sets the color
+    pub const fn color(color: Color) -> Self {
+        Self {
+            color_opt: Some(color),
+            weight_opt: None,
+            style_opt: None,
+        }
+    }
+}
+
+/// A table mapping tree-sitter highlight capture names, like `keyword` or `function.method`, to
+/// [`HighlightStyle`]s
+#[derive(Clone, Debug)]
+pub struct HighlightTheme {
+    pub background: Color,
+    pub foreground: Color,
+    styles: Vec<(String, HighlightStyle)>,
+}
+
+impl HighlightTheme {
+    /// Create a new [`HighlightTheme`] with no capture styles
+    pub const fn new(background: Color, foreground: Color) -> Self {
+        Self {
+            background,
+            foreground,
+            styles: Vec::new(),
+        }
+    }
+
+    /// Set the style for a capture name
+    pub fn set(&mut self, name: &str, style: HighlightStyle) {
+        match self.styles.iter_mut().find(|(key, _)| key == name) {
+            Some((_, value)) => *value = style,
+            None => self.styles.push((name.into(), style)),
+        }
+    }
+
+    /// Get the style for a capture name
+    ///
+    /// Capture names are separated by dots, and the style for the longest matching prefix is
+    /// used, so `function.method` uses the style for `function` if it has no style of its own.
+    pub fn get(&self, name: &str) -> Option<HighlightStyle> {
+        let mut name = name;
+        loop {
+            if let Some((_, style)) = self.styles.iter().find(|(key, _)| key == name) {
+                return Some(*style);
+            }
+            name = &name[..name.rfind('.')?];
+        }
+    }
+}
+
+impl Default for HighlightTheme {
+    /// A dark theme using the colors of "base16-eighties.dark"
+    fn default() -> Self {
+        let mut theme = Self::new(Color::rgb(0x2D, 0x2D, 0x2D), Color::rgb(0xD3, 0xD0, 0xC8));
+        let red = Color::rgb(0xF2, 0x77, 0x7A);
+        let orange = Color::rgb(0xF9, 0x91, 0x57);
+        let yellow = Color::rgb(0xFF, 0xCC, 0x66);
+        let green = Color::rgb(0x99, 0xCC, 0x99);
+        let cyan = Color::rgb(0x66, 0xCC, 0xCC);
+        let blue = Color::rgb(0x66, 0x99, 0xCC);
+        let purple = Color::rgb(0xCC, 0x99, 0xCC);
+        let gray = Color::rgb(0x74, 0x73, 0x69);
+
+        theme.set(
+            "comment",
+            HighlightStyle {
+                style_opt: Some(Style::Italic),
+                ..HighlightStyle::color(gray)
+            },
+        );
+        theme.set("keyword", HighlightStyle::color(purple));
+        theme.set("string", HighlightStyle::color(green));
+        theme.set("escape", HighlightStyle::color(cyan));
+        theme.set("number", HighlightStyle::color(orange));
+        theme.set("constant", HighlightStyle::color(orange));
+        theme.set("function", HighlightStyle::color(blue));
+        theme.set("type", HighlightStyle::color(yellow));
+        theme.set("attribute", HighlightStyle::color(yellow));
+        theme.set("label", HighlightStyle::color(yellow));
+        theme.set("property", HighlightStyle::color(red));
+        theme.set("variable.builtin", HighlightStyle::color(red));
+        theme.set("operator", HighlightStyle::color(cyan));
+        theme
+    }
+}
+
+/// An error creating a [`TreeSitterEditor`]
+#[derive(Debug)]
+pub enum TreeSitterError {
+    /// The language is not supported by this version of tree-sitter
+    Language(LanguageError),
+    /// The highlights query is not valid for the language
+    Query(QueryError),
+}
+
+/// A wrapper of [`Editor`] with syntax highlighting provided by tree-sitter
+///
+/// The text is parsed incrementally, using the changes reported by [`Editor::take_changes`].
+/// Only lines that changed or whose syntax changed are highlighted again, up to the end of the
+/// view plus a margin.
+pub struct TreeSitterEditor<'a> {
+    editor: Editor,
+    theme: &'a HighlightTheme,
+    parser: Parser,
+    query: Query,
+    /// Style of each capture in the query
+    capture_styles: Vec<Option<HighlightStyle>>,
+    tree_opt: Option<Tree>,
+    /// Text of the buffer as of the last parse, with lines separated by `\n`
+    source: String,
+    /// Byte offset in `source` of the start of each line
+    line_starts: Vec<usize>,
+    highlight_margin: usize,
+}
+
+impl<'a> TreeSitterEditor<'a> {
+    /// Create a new [`TreeSitterEditor`] with the provided [`Buffer`], tree-sitter language,
+    /// highlights query, and [`HighlightTheme`].
+    ///
+    /// Highlights queries are usually provided by the crate of the language, for example as
+    /// `tree_sitter_rust::HIGHLIGHT_QUERY`.
+    ///
+    /// ## Errors
+    ///
+    /// Returns a [`TreeSitterError`] if the language is incompatible, or the query is invalid
+    pub fn new(
+        buffer: Buffer,
+        language: Language,
+        highlights_query: &str,
+        theme: &'a HighlightTheme,
+    ) -> Result<Self, TreeSitterError> {
+        let mut editor = Editor::new(buffer);
+        editor.set_track_changes(true);
+
+        let mut parser = Parser::new();
+        parser
+            .set_language(language)
+            .map_err(TreeSitterError::Language)?;
+        let query = Query::new(language, highlights_query).map_err(TreeSitterError::Query)?;
+        let capture_styles = query
+            .capture_names()
+            .iter()
+            .map(|name| theme.get(name))
+            .collect();
+
+        Ok(Self {
+            editor,
+            theme,
+            parser,
+            query,
+            capture_styles,
+            tree_opt: None,
+            source: String::new(),
+            line_starts: Vec::new(),
+            highlight_margin: 128,
+        })
+    }
+
+    /// Load text from a file
+    ///
+    /// The format and line endings of the file are kept, see [`Editor::load_text`]
+    ///
+    /// ## Errors
+    ///
+    /// Returns an [`io::Error`] if reading the file fails, or if it could not be decoded
+    #[cfg(feature = "std")]
+    pub fn load_text<P: AsRef<Path>>(
+        &mut self,
+        font_system: &mut FontSystem,
+        path: P,
+        attrs: crate::Attrs,
+    ) -> io::Result<()> {
+        self.editor.load_text(font_system, path, attrs)?;
+        // Parse the new text from scratch
+        self.tree_opt = None;
+        Ok(())
+    }
+
+    /// Save text to a file in the format it was loaded with, and clear the changed flag
+    ///
+    /// ## Errors
+    ///
+    /// Returns an [`io::Error`] if writing the file fails
+    #[cfg(feature = "std")]
+    pub fn save_text<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        self.editor.save_text(path)
+    }
+
+    /// Set the number of lines past the end of the view, or the cursor, that are highlighted by
+    /// [`Edit::shape_as_needed`]
+    pub fn set_highlight_margin(&mut self, lines: usize) {
+        self.highlight_margin = lines;
+    }
+
+    /// Get the syntax tree as of the last highlighting pass
+    pub fn tree(&self) -> Option<&Tree> {
+        self.tree_opt.as_ref()
+    }
+
+    /// Get the default background color
+    pub fn background_color(&self) -> Color {
+        self.theme.background
+    }
+
+    /// Get the default foreground (text) color
+    pub fn foreground_color(&self) -> Color {
+        self.theme.foreground
+    }
+
+    /// Get the byte offset of a position in `source`
+    fn offset(&self, line: usize, index: usize) -> Option<usize> {
+        let start = *self.line_starts.get(line)?;
+        let end = self
+            .line_starts
+            .get(line + 1)
+            .map_or(self.source.len(), |next| next - 1);
+        if start + index <= end {
+            Some(start + index)
+        } else {
+            None
+        }
+    }
+
+    /// Apply a change to `source` and the syntax tree. Returns false if the change does not
+    /// apply to `source`, in which case the caller must start over.
+    fn apply_change(&mut self, change: &TextChange) -> bool {
+        let (start_byte, old_end_byte) = match (
+            self.offset(change.start.line, change.start.index),
+            self.offset(change.end.line, change.end.index),
+        ) {
+            (Some(start), Some(end)) if start <= end => (start, end),
+            _ => return false,
+        };
+        if !self.source.is_char_boundary(start_byte) || !self.source.is_char_boundary(old_end_byte)
+        {
+            return false;
+        }
+        let new_end_byte = start_byte + change.text.len();
+        self.source
+            .replace_range(start_byte..old_end_byte, &change.text);
+
+        let new_starts: Vec<usize> = change
+            .text
+            .match_indices('\n')
+            .map(|(i, _)| start_byte + i + 1)
+            .collect();
+        let new_lines = new_starts.len();
+        self.line_starts
+            .splice(change.start.line + 1..change.end.line + 1, new_starts);
+        for line_start in self.line_starts[change.start.line + 1 + new_lines..].iter_mut() {
+            *line_start = *line_start + new_end_byte - old_end_byte;
+        }
+
+        let new_end_position = match change.text.rfind('\n') {
+            Some(i) => Point::new(change.start.line + new_lines, change.text.len() - i - 1),
+            None => Point::new(change.start.line, change.start.index + change.text.len()),
+        };
+        if let Some(tree) = &mut self.tree_opt {
+            tree.edit(&InputEdit {
+                start_byte,
+                old_end_byte,
+                new_end_byte,
+                start_position: Point::new(change.start.line, change.start.index),
+                old_end_position: Point::new(change.end.line, change.end.index),
+                new_end_position,
+            });
+        }
+
+        true
+    }
+
+    /// Get the text of a line in `source`
+    fn source_line(&self, line_i: usize) -> &str {
+        let start = self.line_starts[line_i];
+        let end = self
+            .line_starts
+            .get(line_i + 1)
+            .map_or(self.source.len(), |next| next - 1);
+        &self.source[start..end]
+    }
+
+    /// Parse changes and highlight lines that need it until `end`
+    ///
+    /// Returns the number of lines highlighted
+    fn highlight_until(&mut self, end: usize) -> usize {
+        let mut changed = false;
+        let mut valid = self.tree_opt.is_some();
+        for change in self.editor.take_changes() {
+            if valid {
+                valid = self.apply_change(&change);
+            }
+            changed = true;
+        }
+
+        // Check lines that changed for changes made directly to the buffer
+        let lines = &self.editor.buffer().lines;
+        let end = cmp::min(end, lines.len());
+        if valid {
+            valid = lines.len() == self.line_starts.len()
+                && (0..end).all(|line_i| {
+                    lines[line_i].metadata().is_some()
+                        || lines[line_i].text() == self.source_line(line_i)
+                });
+        }
+        if !valid {
+            self.source.clear();
+            self.line_starts.clear();
+            for (line_i, line) in lines.iter().enumerate() {
+                if line_i > 0 {
+                    self.source.push('\n');
+                }
+                self.line_starts.push(self.source.len());
+                self.source.push_str(line.text());
+            }
+            self.tree_opt = None;
+            changed = true;
+        }
+
+        if changed {
+            let tree = self
+                .parser
+                .parse(&self.source, self.tree_opt.as_ref())
+                .expect("failed to parse syntax");
+
+            // Lines where the syntax changed must be highlighted again
+            let lines = &mut self.editor.buffer_mut().lines;
+            match &self.tree_opt {
+                Some(old_tree) => {
+                    for range in old_tree.changed_ranges(&tree) {
+                        let range_end = cmp::min(range.end_point.row + 1, lines.len());
+                        for line in lines[range.start_point.row..range_end].iter_mut() {
+                            line.reset();
+                        }
+                    }
+                }
+                None => {
+                    for line in lines.iter_mut() {
+                        line.reset();
+                    }
+                }
+            }
+
+            self.tree_opt = Some(tree);
+        }
+
+        let tree = match &self.tree_opt {
+            Some(some) => some,
+            None => return 0,
+        };
+
+        let buffer = self.editor.buffer_mut();
+        let wrap = buffer.wrap();
+        let mut query_cursor = QueryCursor::new();
+        let mut highlighted = 0;
+        let mut line_i = 0;
+        while line_i < end {
+            if buffer.lines[line_i].metadata().is_some() {
+                line_i += 1;
+                continue;
+            }
+
+            // Highlight a group of lines that need it at once
+            let group_start = line_i;
+            while line_i < end && buffer.lines[line_i].metadata().is_none() {
+                line_i += 1;
+            }
+            let group_end = line_i;
+
+            let mut attrs_lists: Vec<AttrsList> = buffer.lines[group_start..group_end]
+                .iter()
+                .map(|line| AttrsList::new(line.attrs_list().defaults()))
+                .collect();
+
+            let byte_start = self.line_starts[group_start];
+            let byte_end = self
+                .line_starts
+                .get(group_end)
+                .map_or(self.source.len(), |next| next - 1);
+            query_cursor.set_byte_range(byte_start..byte_end);
+
+            let mut last_range = None;
+            for (query_match, capture_i) in
+                query_cursor.captures(&self.query, tree.root_node(), self.source.as_bytes())
+            {
+                let capture = query_match.captures[capture_i];
+                let style = match self.capture_styles[capture.index as usize] {
+                    Some(some) => some,
+                    None => continue,
+                };
+
+                // The first capture of a node takes precedence, as in tree-sitter-highlight
+                let range = capture.node.byte_range();
+                if last_range.as_ref() == Some(&range) {
+                    continue;
+                }
+                last_range = Some(range);
+
+                let start = capture.node.start_position();
+                let end = capture.node.end_position();
+                let row_start = cmp::max(start.row, group_start);
+                let row_end = cmp::min(end.row + 1, group_end);
+                for row in row_start..row_end {
+                    let attrs_list = &mut attrs_lists[row - group_start];
+                    let mut attrs = buffer.lines[row].attrs_list().defaults();
+                    if let Some(color) = style.color_opt {
+                        attrs = attrs.color(color);
+                    }
+                    if let Some(weight) = style.weight_opt {
+                        attrs = attrs.weight(weight);
+                    }
+                    if let Some(style) = style.style_opt {
+                        attrs = attrs.style(style);
+                    }
+
+                    let column_start = if row == start.row { start.column } else { 0 };
+                    let column_end = if row == end.row {
+                        end.column
+                    } else {
+                        buffer.lines[row].text().len()
+                    };
+                    if column_start < column_end {
+                        attrs_list.add_span(column_start..column_end, attrs);
+                    }
+                }
+            }
+
+            for (line, attrs_list) in buffer.lines[group_start..group_end]
+                .iter_mut()
+                .zip(attrs_lists)
+            {
+                // Update line attributes. This operation only resets if the line changes
+                line.set_attrs_list(attrs_list);
+                line.set_wrap(wrap);
+                // Mark the line as highlighted
+                line.set_metadata(0);
+            }
+
+            highlighted += group_end - group_start;
+        }
+
+        highlighted
+    }
+}
+
+impl<'a> Edit for TreeSitterEditor<'a> {
+    fn buffer(&self) -> &Buffer {
+        self.editor.buffer()
+    }
+
+    fn buffer_mut(&mut self) -> &mut Buffer {
+        self.editor.buffer_mut()
+    }
+
+    fn cursor(&self) -> Cursor {
+        self.editor.cursor()
+    }
+
+    fn set_cursor(&mut self, cursor: Cursor) {
+        self.editor.set_cursor(cursor);
+    }
+
+    fn select_opt(&self) -> Option<Cursor> {
+        self.editor.select_opt()
+    }
+
+    fn set_select_opt(&mut self, select_opt: Option<Cursor>) {
+        self.editor.set_select_opt(select_opt);
+    }
+
+    fn changed(&self) -> bool {
+        self.editor.changed()
+    }
+
+    fn set_changed(&mut self, changed: bool) {
+        self.editor.set_changed(changed);
+    }
+
+    fn behavior(&self) -> EditorBehavior {
+        self.editor.behavior()
+    }
+
+    fn set_behavior(&mut self, behavior: EditorBehavior) {
+        self.editor.set_behavior(behavior);
+    }
+
+    fn style(&self) -> EditorStyle {
+        self.editor.style()
+    }
+
+    fn set_style(&mut self, style: EditorStyle) {
+        self.editor.set_style(style);
+    }
+
+    fn gutter_width(&self) -> i32 {
+        self.editor.gutter_width()
+    }
+
+    fn shape_as_needed(&mut self, font_system: &mut FontSystem) {
+        #[cfg(feature = "std")]
+        let now = std::time::Instant::now();
+
+        // Highlight up to the end of the view and the cursor, plus a margin
+        let end = self.editor.highlight_end(self.highlight_margin);
+
+        let highlighted = self.highlight_until(end);
+
+        if highlighted > 0 {
+            self.editor.buffer_mut().set_redraw(true);
+            #[cfg(feature = "std")]
+            log::debug!(
+                "Syntax highlighted {} lines in {:?}",
+                highlighted,
+                now.elapsed()
+            );
+        }
+
+        self.editor.shape_as_needed(font_system);
+    }
+
+    fn copy_selection(&mut self) -> Option<String> {
+        self.editor.copy_selection()
+    }
+
+    fn copy_selection_rich(&self) -> Option<RichText> {
+        self.editor.copy_selection_rich()
+    }
+
+    fn delete_selection(&mut self) -> bool {
+        self.editor.delete_selection()
+    }
+
+    fn insert_string(&mut self, data: &str, attrs_list: Option<AttrsList>) {
+        self.editor.insert_string(data, attrs_list);
+    }
+
+    fn insert_rich_text(&mut self, rich_text: &RichText) {
+        self.editor.insert_rich_text(rich_text);
+    }
+
+    fn action(&mut self, font_system: &mut FontSystem, action: Action) {
+        self.editor.action(font_system, action);
+    }
+
+    /// Draw the editor
+    #[cfg(feature = "swash")]
+    fn draw<F>(
+        &self,
+        font_system: &mut FontSystem,
+        cache: &mut crate::SwashCache,
+        _color: Color,
+        mut f: F,
+    ) where
+        F: FnMut(i32, i32, u32, u32, Color),
+    {
+        let size = self.buffer().size();
+        f(
+            0,
+            0,
+            size.0 as u32 + self.gutter_width() as u32,
+            size.1 as u32,
+            self.background_color(),
+        );
+        self.editor
+            .draw(font_system, cache, self.foreground_color(), f);
+    }
+}
+
+impl<'a, 'b> BorrowedWithFontSystem<'b, TreeSitterEditor<'a>> {
+    /// Load text from a file
+    ///
+    /// ## Errors
+    ///
+    /// Returns an [`io::Error`] if reading the file fails
+    #[cfg(feature = "std")]
+    pub fn load_text<P: AsRef<Path>>(&mut self, path: P, attrs: crate::Attrs) -> io::Result<()> {
+        self.inner.load_text(self.font_system, path, attrs)
+    }
+}
+
+#[test]
+fn test_highlight_theme_fallback() {
+    let mut theme = HighlightTheme::new(Color::rgb(0, 0, 0), Color::rgb(0xFF, 0xFF, 0xFF));
+    let function = HighlightStyle::color(Color::rgb(0, 0, 0xFF));
+    let method = HighlightStyle::color(Color::rgb(0, 0xFF, 0));
+    theme.set("function", function);
+    assert_eq!(theme.get("function.method"), Some(function));
+    theme.set("function.method", method);
+    assert_eq!(theme.get("function.method"), Some(method));
+    assert_eq!(theme.get("function.builtin"), Some(function));
+    assert_eq!(theme.get("keyword"), None);
+}
+
+#[cfg(test)]
+fn test_editor<'a>(
+    font_system: &mut FontSystem,
+    text: &str,
+    theme: &'a HighlightTheme,
+) -> TreeSitterEditor<'a> {
+    let mut buffer = Buffer::new(font_system, crate::Metrics::new(14.0, 20.0));
+    buffer.set_text(font_system, text, crate::Attrs::new());
+    TreeSitterEditor::new(
+        buffer,
+        tree_sitter_rust::language(),
+        tree_sitter_rust::HIGHLIGHT_QUERY,
+        theme,
+    )
+    .expect("failed to create editor")
+}
+
+/// Check that the incrementally edited tree matches a tree parsed from scratch
+#[cfg(test)]
+fn check_tree(editor: &mut TreeSitterEditor) {
+    let old_tree = editor.tree_opt.take().expect("tree missing");
+    let tree = editor
+        .parser
+        .parse(&editor.source, Some(&old_tree))
+        .expect("failed to parse syntax");
+    let fresh_tree = editor
+        .parser
+        .parse(&editor.source, None)
+        .expect("failed to parse syntax");
+    assert_eq!(tree.root_node().to_sexp(), fresh_tree.root_node().to_sexp());
+    editor.tree_opt = Some(tree);
+}
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_apply_change() {
+    let mut font_system = FontSystem::new();
+    let theme = HighlightTheme::default();
+    let mut editor = test_editor(
+        &mut font_system,
+        "fn main() {\r\n    let é = 1;\r\n}",
+        &theme,
+    );
+    editor.highlight_until(usize::MAX);
+    assert_eq!(editor.source, "fn main() {\n    let é = 1;\n}");
+    assert_eq!(editor.line_starts, [0, 12, 28]);
+
+    // Insert lines after a non-ASCII character
+    assert!(editor.apply_change(&TextChange {
+        start: Cursor::new(1, 10),
+        end: Cursor::new(1, 10),
+        text: " = 2;\n    let ü".into(),
+    }));
+    assert_eq!(
+        editor.source,
+        "fn main() {\n    let é = 2;\n    let ü = 1;\n}"
+    );
+    assert_eq!(editor.line_starts, [0, 12, 28, 44]);
+    assert_eq!(editor.source_line(2), "    let ü = 1;");
+    check_tree(&mut editor);
+
+    // Positions inside a character do not apply
+    let change = TextChange {
+        start: Cursor::new(1, 9),
+        end: Cursor::new(1, 10),
+        text: "e".into(),
+    };
+    assert!(!editor.apply_change(&change));
+    assert_eq!(
+        editor.source,
+        "fn main() {\n    let é = 2;\n    let ü = 1;\n}"
+    );
+
+    // Positions past the end of a line do not apply
+    let change = TextChange {
+        start: Cursor::new(0, 12),
+        end: Cursor::new(0, 12),
+        text: "x".into(),
+    };
+    assert!(!editor.apply_change(&change));
+
+    // Delete lines
+    assert!(editor.apply_change(&TextChange {
+        start: Cursor::new(1, 15),
+        end: Cursor::new(2, 15),
+        text: String::new(),
+    }));
+    assert_eq!(editor.source, "fn main() {\n    let é = 2;\n}");
+    assert_eq!(editor.line_starts, [0, 12, 28]);
+    check_tree(&mut editor);
+
+    // Replace lines with other lines
+    assert!(editor.apply_change(&TextChange {
+        start: Cursor::new(0, 11),
+        end: Cursor::new(2, 0),
+        text: "\n    a();\n    b();\n".into(),
+    }));
+    assert_eq!(editor.source, "fn main() {\n    a();\n    b();\n}");
+    assert_eq!(editor.line_starts, [0, 12, 21, 30]);
+    check_tree(&mut editor);
+}
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_incremental_highlight() {
+    let mut font_system = FontSystem::new();
+    let theme = HighlightTheme::default();
+    let keyword = theme.get("keyword").and_then(|style| style.color_opt);
+    let text = "fn a() {}\r\nfn b() {}\r\nfn c() {}\r\nfn d() {}";
+    let mut editor = test_editor(&mut font_system, text, &theme);
+    assert_eq!(editor.highlight_until(usize::MAX), 4);
+    assert_eq!(editor.highlight_until(usize::MAX), 0);
+
+    // Only the edited line is highlighted again
+    editor.set_cursor(Cursor::new(1, 4));
+    editor.insert_string("x", None);
+    assert_eq!(editor.highlight_until(usize::MAX), 1);
+    assert_eq!(editor.source_line(1), "fn bx() {}");
+
+    // Inserted lines are highlighted, and lines after them are not
+    editor.set_cursor(Cursor::new(2, 0));
+    editor.insert_string("fn é() {}\nfn ü() {}\n", None);
+    assert_eq!(editor.highlight_until(usize::MAX), 3);
+    assert_eq!(editor.line_starts.len(), 6);
+    assert_eq!(editor.source_line(3), "fn ü() {}");
+    check_tree(&mut editor);
+
+    for line in editor.buffer().lines.iter() {
+        assert_eq!(line.attrs_list().get_span(0).color_opt, keyword);
+    }
+
+    // Changes made directly to the buffer are detected
+    let attrs_list = AttrsList::new(crate::Attrs::new());
+    editor.buffer_mut().lines[0].set_text("struct A;", attrs_list);
+    assert_eq!(editor.highlight_until(usize::MAX), 6);
+    assert_eq!(editor.source_line(0), "struct A;");
+}
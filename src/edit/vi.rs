@@ -1,12 +1,14 @@
-use alloc::string::String;
-use core::cmp;
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use core::{cmp, mem, ops::Range};
 use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
-    Action, AttrsList, BorrowedWithFontSystem, Buffer, Color, Cursor, Edit, FontSystem,
-    SyntaxEditor,
+    Action, AttrsList, BorrowedWithFontSystem, Buffer, Color, Cursor, Edit, EditorBehavior,
+    EditorStyle, FoldMethod, FontSystem, RichText, SyntaxEditor, Wrap,
 };
 
+use super::style::Gutter;
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum Mode {
     Normal,
@@ -16,19 +18,475 @@ enum Mode {
     SearchBackwards,
 }
 
-pub struct ViEditor<'a> {
-    editor: SyntaxEditor<'a>,
+/// A command that must be carried out by the host application, see
+/// [`ViEditor::set_host_callback`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ViHostCommand {
+    /// Write the buffer, to `path_opt` if provided (`:w [path]`)
+    Write {
+        path_opt: Option<String>,
+        force: bool,
+    },
+    /// Edit a file, or reload the current file if no path is provided (`:e [path]`)
+    Edit {
+        path_opt: Option<String>,
+        force: bool,
+    },
+    /// Close the editor (`:q`)
+    Quit { force: bool },
+    /// Write the buffer, to `path_opt` if provided, and close the editor (`:wq`, `:x`)
+    WriteQuit {
+        path_opt: Option<String>,
+        force: bool,
+    },
+}
+
+/// Callback used to execute a [`ViHostCommand`], returning an error message on failure
+type ViHostCallback<'a, E> =
+    Box<dyn FnMut(&mut FontSystem, &mut E, ViHostCommand) -> Result<(), String> + 'a>;
+
+/// A line address in an ex command
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ExAddress {
+    /// Absolute line number, starting at 1
+    Line(usize),
+    /// Current line (`.`), with an offset
+    Current(isize),
+    /// Last line (`$`), with an offset
+    Last(isize),
+}
+
+impl ExAddress {
+    /// Resolve to a line index, which may be out of range
+    fn resolve(self, cursor_line: usize, lines: usize) -> isize {
+        match self {
+            // Line 0 is treated as line 1, like in vim
+            Self::Line(line) => line.saturating_sub(1) as isize,
+            Self::Current(offset) => cursor_line as isize + offset,
+            Self::Last(offset) => lines as isize - 1 + offset,
+        }
+    }
+}
+
+/// A parsed ex command, as entered in command mode
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum ExCommand {
+    /// Jump to a line (`:N`)
+    Line(ExAddress),
+    /// Jump to a byte offset in the buffer, starting at 1 (`:goto N`)
+    Byte(usize),
+    /// Replace `pattern` with `replacement` in a range of lines (`:s/pattern/replacement/g`)
+    ///
+    /// The pattern is matched literally. A `\r` or `\n` in the replacement splits the line.
+    Substitute {
+        range: Option<(ExAddress, ExAddress)>,
+        pattern: String,
+        replacement: String,
+        global: bool,
+    },
+    /// Change the wrapping setting (`:set wrap`, `:set nowrap`)
+    SetWrap(bool),
+    /// Pass a command to the host application
+    Host(ViHostCommand),
+}
+
+impl ExCommand {
+    /// Parse an ex command, returning None for an empty command
+    fn parse(input: &str) -> Result<Option<Self>, String> {
+        let mut chars = input.trim_start().chars().peekable();
+
+        // Parse optional range
+        let mut range = None;
+        if chars.peek() == Some(&'%') {
+            chars.next();
+            range = Some((ExAddress::Line(1), ExAddress::Last(0)));
+        } else if let Some(start) = Self::parse_address(&mut chars)? {
+            let end = if chars.peek() == Some(&',') {
+                chars.next();
+                match Self::parse_address(&mut chars)? {
+                    Some(end) => end,
+                    None => return Err(String::from("E14: Invalid address")),
+                }
+            } else {
+                start
+            };
+            range = Some((start, end));
+        }
+
+        // Parse command name and force flag
+        let mut name = String::new();
+        while let Some(c) = chars.peek() {
+            if !c.is_ascii_alphabetic() {
+                break;
+            }
+            name.push(*c);
+            chars.next();
+        }
+        let force = if chars.peek() == Some(&'!') {
+            chars.next();
+            true
+        } else {
+            false
+        };
+
+        let args: String = chars.collect();
+        let path_opt = || {
+            let path = args.trim();
+            if path.is_empty() {
+                None
+            } else {
+                Some(String::from(path))
+            }
+        };
+
+        let command = match name.as_str() {
+            "" => match range {
+                Some((_, end)) => Self::Line(end),
+                None => return Ok(None),
+            },
+            "s" | "substitute" => Self::parse_substitute(range, &args)?,
+            "go" | "goto" => {
+                let args = args.trim();
+                let byte = if args.is_empty() {
+                    1
+                } else {
+                    args.parse()
+                        .map_err(|_| format!("E488: Trailing characters: {}", args))?
+                };
+                Self::Byte(byte)
+            }
+            "se" | "set" => match args.trim() {
+                "wrap" => Self::SetWrap(true),
+                "nowrap" => Self::SetWrap(false),
+                option => return Err(format!("E518: Unknown option: {}", option)),
+            },
+            "w" | "write" => Self::Host(ViHostCommand::Write {
+                path_opt: path_opt(),
+                force,
+            }),
+            "e" | "edit" => Self::Host(ViHostCommand::Edit {
+                path_opt: path_opt(),
+                force,
+            }),
+            "q" | "quit" => Self::Host(ViHostCommand::Quit { force }),
+            "wq" | "x" | "xit" => Self::Host(ViHostCommand::WriteQuit {
+                path_opt: path_opt(),
+                force,
+            }),
+            _ => return Err(format!("E492: Not an editor command: {}", input)),
+        };
+
+        Ok(Some(command))
+    }
+
+    fn parse_address<I: Iterator<Item = char>>(
+        chars: &mut core::iter::Peekable<I>,
+    ) -> Result<Option<ExAddress>, String> {
+        let mut address = match chars.peek() {
+            Some('.') => {
+                chars.next();
+                ExAddress::Current(0)
+            }
+            Some('$') => {
+                chars.next();
+                ExAddress::Last(0)
+            }
+            Some(c) if c.is_ascii_digit() => ExAddress::Line(Self::parse_number(chars)),
+            Some('+') | Some('-') => ExAddress::Current(0),
+            _ => return Ok(None),
+        };
+
+        // Apply any offsets
+        while let Some(sign) = chars.peek().copied() {
+            let sign = match sign {
+                '+' => 1,
+                '-' => -1,
+                _ => break,
+            };
+            chars.next();
+            let offset = match chars.peek() {
+                Some(c) if c.is_ascii_digit() => Self::parse_number(chars) as isize,
+                _ => 1,
+            } * sign;
+            address = match address {
+                ExAddress::Line(line) => {
+                    let line = line as isize + offset;
+                    if line < 0 {
+                        return Err(String::from("E16: Invalid range"));
+                    }
+                    ExAddress::Line(line as usize)
+                }
+                ExAddress::Current(current) => ExAddress::Current(current + offset),
+                ExAddress::Last(last) => ExAddress::Last(last + offset),
+            };
+        }
+
+        Ok(Some(address))
+    }
+
+    fn parse_number<I: Iterator<Item = char>>(chars: &mut core::iter::Peekable<I>) -> usize {
+        let mut number = 0usize;
+        while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+            number = number.saturating_mul(10).saturating_add(digit as usize);
+            chars.next();
+        }
+        number
+    }
+
+    fn parse_substitute(range: Option<(ExAddress, ExAddress)>, args: &str) -> Result<Self, String> {
+        let mut chars = args.chars();
+        let delimiter = match chars.next() {
+            Some(c) if !c.is_alphanumeric() && !c.is_whitespace() && c != '\\' => c,
+            Some(_) => {
+                return Err(String::from(
+                    "E146: Regular expressions can't be delimited by letters",
+                ))
+            }
+            None => return Err(String::from("E35: No previous regular expression")),
+        };
+
+        // Split on unescaped delimiters
+        let mut parts = [String::new(), String::new(), String::new()];
+        let mut part_i = 0;
+        while let Some(c) = chars.next() {
+            if c == '\\' {
+                match chars.next() {
+                    Some(escaped) if escaped == delimiter || escaped == '\\' => {
+                        parts[part_i].push(escaped);
+                    }
+                    Some('t') => parts[part_i].push('\t'),
+                    // Line breaks can only be inserted, as lines are matched one at a time
+                    Some('r' | 'n') if part_i == 1 => parts[part_i].push('\n'),
+                    Some(escaped) => {
+                        parts[part_i].push('\\');
+                        parts[part_i].push(escaped);
+                    }
+                    None => parts[part_i].push('\\'),
+                }
+            } else if c == delimiter && part_i < 2 {
+                part_i += 1;
+            } else {
+                parts[part_i].push(c);
+            }
+        }
+
+        let [pattern, replacement, flags] = parts;
+        if pattern.is_empty() {
+            return Err(String::from("E35: No previous regular expression"));
+        }
+        let mut global = false;
+        for flag in flags.trim().chars() {
+            match flag {
+                'g' => global = true,
+                _ => return Err(format!("E488: Trailing characters: {}", flags)),
+            }
+        }
+
+        Ok(Self::Substitute {
+            range,
+            pattern,
+            replacement,
+            global,
+        })
+    }
+}
+
+/// Get the index of the first non-whitespace character, or the end of the text
+fn first_non_blank(text: &str) -> usize {
+    text.char_indices()
+        .find(|(_, c)| !c.is_whitespace())
+        .map_or(text.len(), |(i, _)| i)
+}
+
+/// A wrapper of an editor, like [`SyntaxEditor`] or `TreeSitterEditor`, with vi key bindings
+pub struct ViEditor<'a, E: Edit> {
+    editor: E,
     mode: Mode,
+    command: String,
+    message_opt: Option<String>,
+    host_callback_opt: Option<ViHostCallback<'a, E>>,
+    /// Line number gutter, which is drawn here as the gutter of the wrapped editor is private
+    gutter: Gutter,
 }
 
-impl<'a> ViEditor<'a> {
-    pub fn new(editor: SyntaxEditor<'a>) -> Self {
+impl<'a, E: Edit> ViEditor<'a, E> {
+    pub fn new(editor: E) -> Self {
         Self {
             editor,
             mode: Mode::Normal,
+            command: String::new(),
+            message_opt: None,
+            host_callback_opt: None,
+            gutter: Gutter::new(),
+        }
+    }
+
+    /// Get the wrapped editor
+    pub fn editor(&self) -> &E {
+        &self.editor
+    }
+
+    /// Get the wrapped editor, mutably
+    pub fn editor_mut(&mut self) -> &mut E {
+        &mut self.editor
+    }
+
+    /// Get the command line being entered, without the leading `:`, if in command mode
+    pub fn command_line(&self) -> Option<&str> {
+        match self.mode {
+            Mode::Command => Some(&self.command),
+            _ => None,
         }
     }
 
+    /// Get the message produced by the last command, usually an error
+    pub fn message(&self) -> Option<&str> {
+        self.message_opt.as_deref()
+    }
+
+    /// Set the callback used to execute commands like `:w`, `:e`, and `:q`
+    ///
+    /// The callback should return an error message if the command failed. Without a callback,
+    /// these commands report an error.
+    pub fn set_host_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut(&mut FontSystem, &mut E, ViHostCommand) -> Result<(), String> + 'a,
+    {
+        self.host_callback_opt = Some(Box::new(callback));
+    }
+
+    /// Move to the start of the line, even if smart home is enabled
+    fn home(&mut self, font_system: &mut FontSystem) {
+        let behavior = self.editor.behavior();
+        self.editor.set_behavior(EditorBehavior {
+            smart_home: false,
+            ..behavior
+        });
+        self.editor.action(font_system, Action::Home);
+        self.editor.set_behavior(behavior);
+    }
+
+    /// Execute an ex command, as entered in command mode without the leading `:`
+    pub fn execute_command(&mut self, font_system: &mut FontSystem, command: &str) {
+        self.message_opt = match ExCommand::parse(command) {
+            Ok(Some(command)) => self.execute_ex(font_system, command).err(),
+            Ok(None) => None,
+            Err(err) => Some(err),
+        };
+        self.buffer_mut().set_redraw(true);
+    }
+
+    fn execute_ex(
+        &mut self,
+        font_system: &mut FontSystem,
+        command: ExCommand,
+    ) -> Result<(), String> {
+        let cursor_line = self.cursor().line;
+        let lines = self.buffer().lines.len();
+        match command {
+            ExCommand::Line(address) => {
+                // Jumping past either end of the buffer goes to the first or last line
+                let line = address
+                    .resolve(cursor_line, lines)
+                    .clamp(0, lines as isize - 1) as usize;
+                let index = first_non_blank(self.buffer().lines[line].text());
+                self.set_cursor(Cursor::new(line, index));
+            }
+            ExCommand::Byte(byte) => {
+                let mut remaining = byte.saturating_sub(1);
+                let mut cursor =
+                    Cursor::new(lines - 1, self.buffer().lines[lines - 1].text().len());
+                for (line_i, line) in self.buffer().lines.iter().enumerate() {
+                    // Each line is followed by a newline
+                    if remaining <= line.text().len() {
+                        let mut index = remaining;
+                        while !line.text().is_char_boundary(index) {
+                            index -= 1;
+                        }
+                        cursor = Cursor::new(line_i, index);
+                        break;
+                    }
+                    remaining -= line.text().len() + 1;
+                }
+                self.set_cursor(cursor);
+            }
+            ExCommand::Substitute {
+                range,
+                pattern,
+                replacement,
+                global,
+            } => {
+                let (start, end) = match range {
+                    Some((start, end)) => {
+                        let resolve = |address: ExAddress| {
+                            let line = address.resolve(cursor_line, lines);
+                            if line >= 0 && (line as usize) < lines {
+                                Ok(line as usize)
+                            } else {
+                                Err(String::from("E16: Invalid range"))
+                            }
+                        };
+                        let start = resolve(start)?;
+                        let end = resolve(end)?;
+                        if start > end {
+                            return Err(String::from("E493: Backwards range given"));
+                        }
+                        (start, end)
+                    }
+                    None => (cursor_line, cursor_line),
+                };
+
+                // Replace through the editor, so that the changes are tracked and the attributes
+                // of the rest of the line are kept. Matches are replaced from the last one, so
+                // that the positions of the others do not move.
+                let mut last_line_opt = None;
+                let replacement_breaks = replacement.matches('\n').count();
+                let mut inserted_lines = 0;
+                for line_i in (start..=end).rev() {
+                    let text = self.buffer().lines[line_i].text();
+                    let mut matches: Vec<usize> = text
+                        .match_indices(pattern.as_str())
+                        .map(|(index, _)| index)
+                        .collect();
+                    if !global {
+                        matches.truncate(1);
+                    }
+                    for &index in matches.iter().rev() {
+                        self.set_select_opt(Some(Cursor::new(line_i, index)));
+                        self.set_cursor(Cursor::new(line_i, index + pattern.len()));
+                        self.insert_string(&replacement, None);
+                    }
+                    if !matches.is_empty() && last_line_opt.is_none() {
+                        last_line_opt = Some(line_i);
+                    }
+                    inserted_lines += matches.len() * replacement_breaks;
+                }
+
+                // The cursor goes to the last line of the last substitution, which is moved down
+                // by the line breaks inserted in it and in the lines before it
+                match last_line_opt {
+                    Some(line) => {
+                        let line = line + inserted_lines;
+                        let index = first_non_blank(self.buffer().lines[line].text());
+                        self.set_cursor(Cursor::new(line, index));
+                    }
+                    None => return Err(format!("E486: Pattern not found: {}", pattern)),
+                }
+            }
+            ExCommand::SetWrap(wrap) => {
+                self.buffer_mut()
+                    .set_wrap(font_system, if wrap { Wrap::Word } else { Wrap::None });
+            }
+            ExCommand::Host(host_command) => match self.host_callback_opt.as_mut() {
+                Some(callback) => callback(font_system, &mut self.editor, host_command)?,
+                None => return Err(String::from("E37: No host callback to handle command")),
+            },
+        }
+        Ok(())
+    }
+}
+
+impl<'a> ViEditor<'a, SyntaxEditor<'a>> {
     /// Load text from a file, and also set syntax to the best option
     #[cfg(feature = "std")]
     pub fn load_text<P: AsRef<std::path::Path>>(
@@ -40,6 +498,16 @@ impl<'a> ViEditor<'a> {
         self.editor.load_text(font_system, path, attrs)
     }
 
+    /// Save text to a file in the format it was loaded with, and clear the changed flag
+    ///
+    /// ## Errors
+    ///
+    /// Returns an [`std::io::Error`] if writing the file fails
+    #[cfg(feature = "std")]
+    pub fn save_text<P: AsRef<std::path::Path>>(&mut self, path: P) -> std::io::Result<()> {
+        self.editor.save_text(path)
+    }
+
     /// Get the default background color
     pub fn background_color(&self) -> Color {
         self.editor.background_color()
@@ -49,9 +517,25 @@ impl<'a> ViEditor<'a> {
     pub fn foreground_color(&self) -> Color {
         self.editor.foreground_color()
     }
+
+    /// Get the ranges of lines that can be folded, see [`SyntaxEditor::fold_ranges`]
+    pub fn fold_ranges(&self, method: FoldMethod) -> Vec<Range<usize>> {
+        self.editor.fold_ranges(method)
+    }
+
+    /// Get highlighting progress, see [`SyntaxEditor::highlight_progress`]
+    pub fn highlight_progress(&self) -> (usize, usize) {
+        self.editor.highlight_progress()
+    }
+
+    /// Highlight lines ahead of the view, see [`SyntaxEditor::highlight_ahead`]
+    #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+    pub fn highlight_ahead(&mut self, budget: std::time::Duration) -> bool {
+        self.editor.highlight_ahead(budget)
+    }
 }
 
-impl<'a> Edit for ViEditor<'a> {
+impl<'a, E: Edit> Edit for ViEditor<'a, E> {
     fn buffer(&self) -> &Buffer {
         self.editor.buffer()
     }
@@ -64,6 +548,10 @@ impl<'a> Edit for ViEditor<'a> {
         self.editor.cursor()
     }
 
+    fn set_cursor(&mut self, cursor: Cursor) {
+        self.editor.set_cursor(cursor);
+    }
+
     fn select_opt(&self) -> Option<Cursor> {
         self.editor.select_opt()
     }
@@ -72,14 +560,48 @@ impl<'a> Edit for ViEditor<'a> {
         self.editor.set_select_opt(select_opt);
     }
 
+    fn changed(&self) -> bool {
+        self.editor.changed()
+    }
+
+    fn set_changed(&mut self, changed: bool) {
+        self.editor.set_changed(changed);
+    }
+
+    fn behavior(&self) -> EditorBehavior {
+        self.editor.behavior()
+    }
+
+    fn set_behavior(&mut self, behavior: EditorBehavior) {
+        self.editor.set_behavior(behavior);
+    }
+
+    fn style(&self) -> EditorStyle {
+        self.editor.style()
+    }
+
+    fn set_style(&mut self, style: EditorStyle) {
+        self.editor.set_style(style);
+    }
+
+    fn gutter_width(&self) -> i32 {
+        self.editor.gutter_width()
+    }
+
     fn shape_as_needed(&mut self, font_system: &mut FontSystem) {
         self.editor.shape_as_needed(font_system);
+        self.gutter
+            .update(font_system, self.editor.buffer(), &self.editor.style());
     }
 
     fn copy_selection(&mut self) -> Option<String> {
         self.editor.copy_selection()
     }
 
+    fn copy_selection_rich(&self) -> Option<RichText> {
+        self.editor.copy_selection_rich()
+    }
+
     fn delete_selection(&mut self) -> bool {
         self.editor.delete_selection()
     }
@@ -88,6 +610,10 @@ impl<'a> Edit for ViEditor<'a> {
         self.editor.insert_string(data, attrs_list);
     }
 
+    fn insert_rich_text(&mut self, rich_text: &RichText) {
+        self.editor.insert_rich_text(rich_text);
+    }
+
     fn action(&mut self, font_system: &mut FontSystem, action: Action) {
         let old_mode = self.mode;
 
@@ -127,8 +653,7 @@ impl<'a> Edit for ViEditor<'a> {
                     }
                     // Enter insert mode at start of line
                     'I' => {
-                        //TODO: soft home, skip whitespace
-                        self.editor.action(font_system, Action::Home);
+                        self.editor.action(font_system, Action::SoftHome);
                         self.mode = Mode::Insert;
                     }
                     // Create line after and enter insert mode
@@ -172,7 +697,7 @@ impl<'a> Edit for ViEditor<'a> {
                         if self.editor.select_opt().is_some() {
                             self.editor.set_select_opt(None);
                         } else {
-                            self.editor.action(font_system, Action::Home);
+                            self.home(font_system);
                             self.editor.set_select_opt(Some(self.editor.cursor()));
                             //TODO: set cursor_x_opt to max
                             self.editor.action(font_system, Action::End);
@@ -183,14 +708,15 @@ impl<'a> Edit for ViEditor<'a> {
                     // Remove character before cursor
                     'X' => self.editor.action(font_system, Action::Backspace),
                     // Go to start of line
-                    '0' => self.editor.action(font_system, Action::Home),
+                    '0' => self.home(font_system),
                     // Go to end of line
                     '$' => self.editor.action(font_system, Action::End),
                     // Go to start of line after whitespace
-                    //TODO: implement this
-                    '^' => self.editor.action(font_system, Action::Home),
+                    '^' => self.editor.action(font_system, Action::SoftHome),
                     // Enter command mode
                     ':' => {
+                        self.command.clear();
+                        self.message_opt = None;
                         self.mode = Mode::Command;
                     }
                     // Enter search mode
@@ -216,6 +742,29 @@ impl<'a> Edit for ViEditor<'a> {
                 }
                 _ => self.editor.action(font_system, action),
             },
+            Mode::Command => match action {
+                Action::Escape => {
+                    self.command.clear();
+                    self.mode = Mode::Normal;
+                }
+                Action::Enter | Action::Insert('\n') => {
+                    let command = mem::take(&mut self.command);
+                    self.mode = Mode::Normal;
+                    self.execute_command(font_system, &command);
+                }
+                Action::Backspace => {
+                    // Leave command mode when backspacing past the start of the command
+                    if self.command.pop().is_none() {
+                        self.mode = Mode::Normal;
+                    }
+                    self.buffer_mut().set_redraw(true);
+                }
+                Action::Insert(c) if !c.is_control() => {
+                    self.command.push(c);
+                    self.buffer_mut().set_redraw(true);
+                }
+                _ => (),
+            },
             _ => {
                 //TODO: other modes
                 self.mode = Mode::Normal;
@@ -238,16 +787,49 @@ impl<'a> Edit for ViEditor<'a> {
         F: FnMut(i32, i32, u32, u32, Color),
     {
         let font_size = self.buffer().metrics().font_size;
-        let line_height = self.buffer().metrics().line_height;
+        let style = self.style();
+        let selection_color = style.selection(color);
+        let caret_color = style.caret(color);
+
+        self.gutter.draw(
+            font_system,
+            cache,
+            self.buffer(),
+            self.cursor().line,
+            &style,
+            color,
+            &mut f,
+        );
+        // Offset the text by the gutter width
+        let gutter_width = self.gutter_width();
+        let mut f = |x, y, w, h, color| f(x + gutter_width, y, w, h, color);
 
         for run in self.buffer().layout_runs() {
             let line_i = run.line_i;
             let line_y = run.line_y;
+            let line_top = run.line_top;
+            let line_height = run.line_height;
+
+            // Highlight the line with the cursor
+            if let Some(line_highlight) = style.line_highlight_opt {
+                if line_i == self.cursor().line {
+                    f(
+                        0,
+                        line_top as i32,
+                        self.buffer().size().0 as u32,
+                        line_height as u32,
+                        line_highlight,
+                    );
+                }
+            }
+
+            // Fold placeholders have no cursor positions
+            let folded = run.fold_opt.is_some();
 
             let cursor_glyph_opt = |cursor: &Cursor| -> Option<(usize, f32, f32)> {
                 //TODO: better calculation of width
                 let default_width = font_size / 2.0;
-                if cursor.line == line_i {
+                if cursor.line == line_i && !folded {
                     for (glyph_i, glyph) in run.glyphs.iter().enumerate() {
                         if cursor.index >= glyph.start && cursor.index < glyph.end {
                             // Guess x offset based on characters
@@ -281,7 +863,7 @@ impl<'a> Edit for ViEditor<'a> {
                 None
             };
 
-            // Highlight selection (TODO: HIGHLIGHT COLOR!)
+            // Highlight selection
             if let Some(select) = self.select_opt() {
                 let (start, end) = match select.line.cmp(&self.cursor().line) {
                     cmp::Ordering::Greater => (self.cursor(), select),
@@ -297,7 +879,7 @@ impl<'a> Edit for ViEditor<'a> {
                     }
                 };
 
-                if line_i >= start.line && line_i <= end.line {
+                if line_i >= start.line && line_i <= end.line && !folded {
                     let mut range_opt = None;
                     for glyph in run.glyphs.iter() {
                         // Guess x offset based on characters
@@ -321,10 +903,10 @@ impl<'a> Edit for ViEditor<'a> {
                             } else if let Some((min, max)) = range_opt.take() {
                                 f(
                                     min,
-                                    (line_y - font_size) as i32,
+                                    line_top as i32,
                                     cmp::max(0, max - min) as u32,
                                     line_height as u32,
-                                    Color::rgba(color.r(), color.g(), color.b(), 0x33),
+                                    selection_color,
                                 );
                             }
                             c_x += c_w;
@@ -347,10 +929,10 @@ impl<'a> Edit for ViEditor<'a> {
                         }
                         f(
                             min,
-                            (line_y - font_size) as i32,
+                            line_top as i32,
                             cmp::max(0, max - min) as u32,
                             line_height as u32,
-                            Color::rgba(color.r(), color.g(), color.b(), 0x33),
+                            selection_color,
                         );
                     }
                 }
@@ -396,7 +978,8 @@ impl<'a> Edit for ViEditor<'a> {
                         }
                         None => {
                             // Start of empty line
-                            (0, cursor_glyph_width as i32)
+                            let x = self.buffer().run_start_x(&run);
+                            (x as i32, (x + cursor_glyph_width) as i32)
                         }
                     },
                 };
@@ -406,23 +989,27 @@ impl<'a> Edit for ViEditor<'a> {
                     let right_x = cmp::max(start_x, end_x);
                     f(
                         left_x,
-                        (line_y - font_size) as i32,
+                        line_top as i32,
                         (right_x - left_x) as u32,
                         line_height as u32,
-                        Color::rgba(color.r(), color.g(), color.b(), 0x33),
+                        Color::rgba(caret_color.r(), caret_color.g(), caret_color.b(), 0x33),
                     );
                 } else {
                     f(
                         start_x,
-                        (line_y - font_size) as i32,
-                        1,
+                        line_top as i32,
+                        style.caret_width,
                         line_height as u32,
-                        color,
+                        caret_color,
                     );
                 }
             }
 
             for glyph in run.glyphs.iter() {
+                if glyph.object_opt.is_some() {
+                    // Inline objects are drawn by the application
+                    continue;
+                }
                 let (cache_key, x_int, y_int) = (glyph.cache_key, glyph.x_int, glyph.y_int);
 
                 let glyph_color = match glyph.color_opt {
@@ -438,7 +1025,7 @@ impl<'a> Edit for ViEditor<'a> {
     }
 }
 
-impl<'a, 'b> BorrowedWithFontSystem<'b, ViEditor<'a>> {
+impl<'a, 'b> BorrowedWithFontSystem<'b, ViEditor<'a, SyntaxEditor<'a>>> {
     /// Load text from a file, and also set syntax to the best option
     #[cfg(feature = "std")]
     pub fn load_text<P: AsRef<std::path::Path>>(
@@ -449,3 +1036,141 @@ impl<'a, 'b> BorrowedWithFontSystem<'b, ViEditor<'a>> {
         self.inner.load_text(self.font_system, path, attrs)
     }
 }
+
+#[test]
+fn test_ex_command_parse() {
+    assert_eq!(ExCommand::parse(""), Ok(None));
+    assert_eq!(
+        ExCommand::parse("42"),
+        Ok(Some(ExCommand::Line(ExAddress::Line(42))))
+    );
+    assert_eq!(
+        ExCommand::parse("$-2"),
+        Ok(Some(ExCommand::Line(ExAddress::Last(-2))))
+    );
+    assert_eq!(ExCommand::parse("goto 7"), Ok(Some(ExCommand::Byte(7))));
+    assert_eq!(
+        ExCommand::parse("%s/a\\/b/c/g"),
+        Ok(Some(ExCommand::Substitute {
+            range: Some((ExAddress::Line(1), ExAddress::Last(0))),
+            pattern: String::from("a/b"),
+            replacement: String::from("c"),
+            global: true,
+        }))
+    );
+    assert_eq!(
+        ExCommand::parse(".,.+3s#x##"),
+        Ok(Some(ExCommand::Substitute {
+            range: Some((ExAddress::Current(0), ExAddress::Current(3))),
+            pattern: String::from("x"),
+            replacement: String::new(),
+            global: false,
+        }))
+    );
+    assert_eq!(
+        ExCommand::parse("s/, /\\r\\n/"),
+        Ok(Some(ExCommand::Substitute {
+            range: None,
+            pattern: String::from(", "),
+            replacement: String::from("\n\n"),
+            global: false,
+        }))
+    );
+    assert_eq!(
+        ExCommand::parse("set nowrap"),
+        Ok(Some(ExCommand::SetWrap(false)))
+    );
+    assert_eq!(
+        ExCommand::parse("w! out.txt"),
+        Ok(Some(ExCommand::Host(ViHostCommand::Write {
+            path_opt: Some(String::from("out.txt")),
+            force: true,
+        })))
+    );
+    assert!(ExCommand::parse("frobnicate").is_err());
+}
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_substitute() {
+    use crate::{Attrs, Metrics, SyntaxSystem};
+
+    let mut font_system = FontSystem::new();
+    let syntax_system = SyntaxSystem::new();
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(14.0, 20.0));
+    let attrs = Attrs::new();
+    buffer.set_text(&mut font_system, "foo red foo\nfoo", attrs);
+    let mut attrs_list = AttrsList::new(attrs);
+    attrs_list.add_span(4..7, attrs.color(Color::rgb(0xFF, 0, 0)));
+    buffer.lines[0].set_attrs_list(attrs_list);
+    let editor =
+        SyntaxEditor::new(buffer, &syntax_system, "base16-eighties.dark").expect("theme not found");
+    let mut editor = ViEditor::new(editor);
+
+    editor.execute_command(&mut font_system, "s/foo/x/g");
+    assert_eq!(editor.message_opt, None);
+    assert_eq!(editor.buffer().lines[0].text(), "x red x");
+    assert_eq!(editor.buffer().lines[1].text(), "foo");
+    assert!(editor.changed());
+
+    // The attributes of the rest of the line are kept
+    let attrs_list = editor.buffer().lines[0].attrs_list();
+    assert_eq!(
+        attrs_list.get_span(3).color_opt,
+        Some(Color::rgb(0xFF, 0, 0))
+    );
+    assert_eq!(attrs_list.get_span(0).color_opt, None);
+
+    // Line breaks in the replacement split the line
+    editor.execute_command(&mut font_system, "%s/ /\\r/g");
+    assert_eq!(editor.message_opt, None);
+    let lines: Vec<&str> = editor
+        .buffer()
+        .lines
+        .iter()
+        .map(|line| line.text())
+        .collect();
+    assert_eq!(lines, ["x", "red", "x", "foo"]);
+    assert_eq!(editor.cursor(), Cursor::new(2, 0));
+}
+
+#[cfg(feature = "tree-sitter")]
+#[test]
+#[ignore = "needs system fonts"]
+fn test_substitute_tree_sitter() {
+    use crate::{Attrs, HighlightTheme, Metrics, TreeSitterEditor};
+
+    let mut font_system = FontSystem::new();
+    let theme = HighlightTheme::default();
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(14.0, 20.0));
+    buffer.set_text(&mut font_system, "fn foo() {}\nfn bar() {}", Attrs::new());
+    let editor = TreeSitterEditor::new(
+        buffer,
+        tree_sitter_rust::language(),
+        tree_sitter_rust::HIGHLIGHT_QUERY,
+        &theme,
+    )
+    .expect("failed to create editor");
+    let mut editor = ViEditor::new(editor);
+
+    editor.execute_command(&mut font_system, "%s/fn/pub fn/");
+    assert_eq!(editor.message_opt, None);
+    assert_eq!(editor.buffer().lines[0].text(), "pub fn foo() {}");
+    assert_eq!(editor.buffer().lines[1].text(), "pub fn bar() {}");
+    assert!(editor.changed());
+
+    // The host callback gets the wrapped editor
+    editor.set_host_callback(|_, editor: &mut TreeSitterEditor, command| {
+        assert_eq!(command, ViHostCommand::Quit { force: false });
+        assert_eq!(editor.buffer().lines[1].text(), "pub fn bar() {}");
+        Ok(())
+    });
+    editor.execute_command(&mut font_system, "q");
+    assert_eq!(editor.message_opt, None);
+
+    // The substituted text is parsed when highlighting
+    editor.shape_as_needed(&mut font_system);
+    let root = editor.editor().tree().expect("tree missing").root_node();
+    assert!(!root.has_error());
+    assert_eq!(root.end_byte(), "pub fn foo() {}\npub fn bar() {}".len());
+}
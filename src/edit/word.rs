@@ -0,0 +1,287 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+use core::{mem, ops::Range};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Rules used to find words when moving the cursor by word or selecting a word
+#[derive(Clone, Copy, Debug, Default)]
+pub enum WordBoundaries {
+    /// Unicode word boundaries, see [UAX #29](https://www.unicode.org/reports/tr29/). Only
+    /// segments with letters or numbers are words.
+    #[default]
+    Unicode,
+    /// Identifiers, runs of punctuation, and runs of whitespace are segments. Words stop at the
+    /// parts of `camelCase` and `snake_case` identifiers, and at runs of punctuation.
+    Subword,
+    /// Unicode word boundaries, with runs of CJK ideographs, and the Hiragana following them,
+    /// joined into single words instead of one word per character
+    Cjk,
+    /// Segments returned by a function, which must cover all of the text of a line in order, as
+    /// byte ranges. Only segments with letters or numbers are words.
+    Custom(fn(&str) -> Vec<Range<usize>>),
+}
+
+impl PartialEq for WordBoundaries {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            // Functions are compared by address, which may differ for the same function
+            (Self::Custom(a), Self::Custom(b)) => *a as usize == *b as usize,
+            _ => mem::discriminant(self) == mem::discriminant(other),
+        }
+    }
+}
+
+impl Eq for WordBoundaries {}
+
+impl WordBoundaries {
+    /// Get the ranges of the words in a line, used when moving the cursor by word
+    pub fn words(self, text: &str) -> Vec<Range<usize>> {
+        let segments = self.segments(text);
+        match self {
+            Self::Unicode | Self::Cjk | Self::Custom(_) => segments
+                .into_iter()
+                .filter(|segment| text[segment.clone()].chars().any(char::is_alphanumeric))
+                .collect(),
+            Self::Subword => {
+                let mut words = Vec::new();
+                for segment in segments {
+                    match text[segment.clone()].chars().next().map(CharClass::new) {
+                        Some(CharClass::Word) => subwords(text, segment, &mut words),
+                        Some(CharClass::Punctuation) => words.push(segment),
+                        _ => {}
+                    }
+                }
+                words
+            }
+        }
+    }
+
+    /// Get the range of the segment at `index` in a line, used when selecting a word
+    ///
+    /// If `index` is between whitespace and another segment, the other segment is preferred.
+    pub fn word_at(self, text: &str, index: usize) -> Range<usize> {
+        let segments = self.segments(text);
+        let segment_i = match segments.iter().position(|segment| segment.contains(&index)) {
+            Some(some) => some,
+            // Past the end of the line
+            None => match segments.len().checked_sub(1) {
+                Some(some) => some,
+                None => return index..index,
+            },
+        };
+
+        let is_space =
+            |segment: &Range<usize>| text[segment.clone()].chars().all(char::is_whitespace);
+        if segment_i > 0 && segments[segment_i].start == index && is_space(&segments[segment_i]) {
+            // Prefer the segment ending at the index
+            let prev = &segments[segment_i - 1];
+            if !is_space(prev) {
+                return prev.clone();
+            }
+        }
+        segments[segment_i].clone()
+    }
+
+    /// Get the segments of a line, which cover all of its text
+    fn segments(self, text: &str) -> Vec<Range<usize>> {
+        match self {
+            Self::Unicode => text
+                .split_word_bound_indices()
+                .map(|(i, segment)| i..i + segment.len())
+                .collect(),
+            Self::Subword => {
+                let mut segments: Vec<Range<usize>> = Vec::new();
+                let mut last_class_opt = None;
+                for (i, c) in text.char_indices() {
+                    let class = CharClass::new(c);
+                    match segments.last_mut() {
+                        Some(segment) if last_class_opt == Some(class) => {
+                            segment.end = i + c.len_utf8();
+                        }
+                        _ => segments.push(i..i + c.len_utf8()),
+                    }
+                    last_class_opt = Some(class);
+                }
+                segments
+            }
+            Self::Cjk => {
+                let mut segments: Vec<Range<usize>> = Vec::new();
+                let mut last_script_opt: Option<CjkScript> = None;
+                for (i, segment) in text.split_word_bound_indices() {
+                    let script_opt = CjkScript::new(segment);
+                    let joined = match (last_script_opt, script_opt) {
+                        (Some(last), Some(script)) => last.join(script),
+                        _ => None,
+                    };
+                    match (joined, segments.last_mut()) {
+                        (Some(joined), Some(last)) => {
+                            last.end = i + segment.len();
+                            last_script_opt = Some(joined);
+                        }
+                        _ => {
+                            segments.push(i..i + segment.len());
+                            last_script_opt = script_opt;
+                        }
+                    }
+                }
+                segments
+            }
+            Self::Custom(segments) => segments(text),
+        }
+    }
+}
+
+/// Classes of characters used by [`WordBoundaries::Subword`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum CharClass {
+    Word,
+    Whitespace,
+    Punctuation,
+}
+
+impl CharClass {
+    fn new(c: char) -> Self {
+        if c.is_alphanumeric() || c == '_' {
+            Self::Word
+        } else if c.is_whitespace() {
+            Self::Whitespace
+        } else {
+            Self::Punctuation
+        }
+    }
+}
+
+/// Add the parts of an identifier, split at underscores and changes of case
+fn subwords(text: &str, range: Range<usize>, words: &mut Vec<Range<usize>>) {
+    let mut start_opt: Option<usize> = None;
+    let mut prev_opt: Option<char> = None;
+    let mut chars = text[range.clone()].char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let i = range.start + i;
+        if c == '_' {
+            if let Some(start) = start_opt.take() {
+                words.push(start..i);
+            }
+            prev_opt = None;
+            continue;
+        }
+
+        let next_opt = chars.peek().map(|(_, next)| *next);
+        let boundary = match prev_opt {
+            // Start of a capitalized part, like "camel|Case" or "v2|Beta"
+            Some(prev) if (prev.is_lowercase() || prev.is_numeric()) && c.is_uppercase() => true,
+            // End of an acronym, like "HTTP|Response"
+            Some(prev) if prev.is_uppercase() && c.is_uppercase() => {
+                matches!(next_opt, Some(next) if next.is_lowercase())
+            }
+            _ => false,
+        };
+        if boundary {
+            if let Some(start) = start_opt.take() {
+                words.push(start..i);
+            }
+        }
+        if start_opt.is_none() {
+            start_opt = Some(i);
+        }
+        prev_opt = Some(c);
+    }
+    if let Some(start) = start_opt {
+        words.push(start..range.end);
+    }
+}
+
+/// Scripts joined by [`WordBoundaries::Cjk`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum CjkScript {
+    Han,
+    /// Han followed by Hiragana, like "書く"
+    HanHiragana,
+    Hiragana,
+    Katakana,
+}
+
+impl CjkScript {
+    /// Get the script of a segment, if all of its characters have the same one
+    fn new(segment: &str) -> Option<Self> {
+        let mut script_opt = None;
+        for c in segment.chars() {
+            let script = match c {
+                '\u{3005}'
+                | '\u{3007}'
+                | '\u{3400}'..='\u{4DBF}'
+                | '\u{4E00}'..='\u{9FFF}'
+                | '\u{F900}'..='\u{FAFF}'
+                | '\u{20000}'..='\u{2FA1F}' => Self::Han,
+                '\u{3041}'..='\u{309F}' => Self::Hiragana,
+                '\u{30A0}'..='\u{30FF}' | '\u{31F0}'..='\u{31FF}' | '\u{FF66}'..='\u{FF9F}' => {
+                    Self::Katakana
+                }
+                _ => return None,
+            };
+            if matches!(script_opt, Some(prev) if prev != script) {
+                return None;
+            }
+            script_opt = Some(script);
+        }
+        script_opt
+    }
+
+    /// Get the script of a word when a segment of another script follows it, if they join
+    fn join(self, next: Self) -> Option<Self> {
+        match (self, next) {
+            (Self::Han, Self::Han) => Some(Self::Han),
+            (Self::Han | Self::HanHiragana, Self::Hiragana) => Some(Self::HanHiragana),
+            (Self::Hiragana, Self::Hiragana) => Some(Self::Hiragana),
+            (Self::Katakana, Self::Katakana) => Some(Self::Katakana),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn test_word_boundaries() {
+    fn words(boundaries: WordBoundaries, text: &str) -> Vec<&str> {
+        boundaries
+            .words(text)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect()
+    }
+
+    assert_eq!(
+        words(WordBoundaries::Unicode, "let snake_case = a->b;"),
+        ["let", "snake_case", "a", "b"]
+    );
+    assert_eq!(
+        words(
+            WordBoundaries::Subword,
+            "let snake_case = parseHTTPResponse->v2Beta;"
+        ),
+        ["let", "snake", "case", "=", "parse", "HTTP", "Response", "->", "v2", "Beta", ";"]
+    );
+    assert_eq!(
+        words(WordBoundaries::Cjk, "漢字を書くカタカナ text"),
+        ["漢字を", "書く", "カタカナ", "text"]
+    );
+    assert_eq!(
+        WordBoundaries::Subword.word_at("let snake_case = 1", 14),
+        4..14
+    );
+
+    // Split at spaces only
+    let custom = WordBoundaries::Custom(|text| {
+        let mut segments = Vec::new();
+        let mut start = 0;
+        for (i, _) in text.match_indices(' ') {
+            segments.extend([start..i, i..i + 1]);
+            start = i + 1;
+        }
+        segments.push(start..text.len());
+        segments
+    });
+    assert_eq!(words(custom, "a->b  c.d"), ["a->b", "c.d"]);
+    assert_eq!(custom.word_at("a->b  c.d", 2), 0..4);
+}
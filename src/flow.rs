@@ -0,0 +1,552 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+#[cfg(not(feature = "std"))]
+use alloc::{vec, vec::Vec};
+use core::{iter, slice};
+
+#[cfg(feature = "swash")]
+use crate::Color;
+use crate::{
+    Buffer, BufferLine, Cursor, FontSystem, LayoutLine, LayoutRun, LineBox, Metrics, Wrap,
+};
+
+/// Size of a box that text flows into, see [`Flow`]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FlowBox {
+    /// Width of the box
+    pub width: f32,
+    /// Height of the box
+    pub height: f32,
+}
+
+impl FlowBox {
+    /// Create a new box with a width and height
+    pub const fn new(width: f32, height: f32) -> Self {
+        Self { width, height }
+    }
+}
+
+/// Rules for breaking paragraphs between the boxes of a [`Flow`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Pagination {
+    /// Move a paragraph that does not fit in the rest of a box to the next box, if it fits there
+    pub keep_together: bool,
+    /// Minimum number of lines of a broken paragraph at the end of a box (orphan control)
+    pub orphans: usize,
+    /// Minimum number of lines of a broken paragraph at the start of a box (widow control)
+    pub widows: usize,
+}
+
+impl Pagination {
+    /// Create new rules that break paragraphs wherever a box is full
+    pub const fn new() -> Self {
+        Self {
+            keep_together: false,
+            orphans: 1,
+            widows: 1,
+        }
+    }
+
+    /// Set [`Self::keep_together`]
+    pub fn keep_together(mut self, keep_together: bool) -> Self {
+        self.keep_together = keep_together;
+        self
+    }
+
+    /// Set [`Self::orphans`]
+    pub fn orphans(mut self, orphans: usize) -> Self {
+        self.orphans = orphans;
+        self
+    }
+
+    /// Set [`Self::widows`]
+    pub fn widows(mut self, widows: usize) -> Self {
+        self.widows = widows;
+        self
+    }
+}
+
+impl Default for Pagination {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The text in one box of a [`Flow`]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FlowRange {
+    /// Start of the text in the box
+    pub start: Cursor,
+    /// End of the text in the box, which is the same as the start if the box is empty
+    pub end: Cursor,
+    /// Line index and layout index of each visual line in the box, from top to bottom
+    pub layout_lines: Vec<(usize, usize)>,
+}
+
+/// The text of a [`Buffer`] flowed through a sequence of boxes, such as the columns of a page or
+/// the pages of a document
+///
+/// Visual lines are stacked with their heights from [`Metrics::layout_line_height`] and the
+/// spacing of their paragraphs, so each box holds as many whole lines as fit in its height, and
+/// each visual line is laid out with the width of the box it lands in. Text that does not fit in
+/// the last box overflows. The layout is kept by the flow rather than by the buffer, so it has to
+/// be redone with [`Self::layout`] when the text of the buffer changes.
+pub struct Flow {
+    boxes: Vec<FlowBox>,
+    pagination: Pagination,
+    layouts: Vec<Vec<LayoutLine>>,
+    ranges: Vec<FlowRange>,
+    /// Top of each visual line in each box
+    tops: Vec<Vec<f32>>,
+    overflow_opt: Option<Cursor>,
+}
+
+impl Flow {
+    /// Create a new flow through `boxes`, which is laid out with [`Self::layout`]
+    pub fn new(boxes: Vec<FlowBox>, pagination: Pagination) -> Self {
+        Self {
+            boxes,
+            pagination,
+            layouts: Vec::new(),
+            ranges: Vec::new(),
+            tops: Vec::new(),
+            overflow_opt: None,
+        }
+    }
+
+    /// Get the boxes
+    pub fn boxes(&self) -> &[FlowBox] {
+        &self.boxes
+    }
+
+    /// Set the boxes, which takes effect on the next [`Self::layout`]
+    pub fn set_boxes(&mut self, boxes: Vec<FlowBox>) {
+        self.boxes = boxes;
+    }
+
+    /// Get the current [`Pagination`]
+    pub fn pagination(&self) -> Pagination {
+        self.pagination
+    }
+
+    /// Set the current [`Pagination`], which takes effect on the next [`Self::layout`]
+    pub fn set_pagination(&mut self, pagination: Pagination) {
+        self.pagination = pagination;
+    }
+
+    /// Get the text in each box, which is empty until [`Self::layout`] is called
+    pub fn ranges(&self) -> &[FlowRange] {
+        &self.ranges
+    }
+
+    /// Get the start of the text that does not fit in the boxes, if there is any
+    pub fn overflow(&self) -> Option<Cursor> {
+        self.overflow_opt
+    }
+
+    /// Check if the bottom of a line at `bottom` is inside a box, where boxes past the last one
+    /// never fill up
+    fn fits(&self, box_i: usize, bottom: f32) -> bool {
+        match self.boxes.get(box_i) {
+            Some(flow_box) => bottom <= flow_box.height,
+            None => true,
+        }
+    }
+
+    /// Lay out a paragraph with its top at a box and position in the box, with at most
+    /// `limit.1` of its lines in box `limit.0`, returning the layout and the box and top of each
+    /// of its visual lines
+    ///
+    /// Each visual line gets the width of the box it would be in if it were as tall as the line
+    /// above it, or `line_height` for the first line, and is then placed with its real height.
+    fn layout_paragraph(
+        &self,
+        line: &BufferLine,
+        metrics: Metrics,
+        wrap: Wrap,
+        start: (usize, f32),
+        limit_opt: Option<(usize, usize)>,
+    ) -> (Vec<LayoutLine>, Vec<(usize, f32)>) {
+        let shape = line.shape_opt().as_ref().expect("shape not found");
+        let paragraph_attrs = line.paragraph_attrs();
+        let space = |layout_i: usize| {
+            if layout_i == 0 {
+                paragraph_attrs.space_before
+            } else {
+                0.0
+            }
+        };
+
+        let mut placer = Placer::new(self, start, limit_opt);
+        let mut height = metrics.line_height;
+        let layout = shape.layout_to_boxes(
+            metrics.font_size,
+            wrap,
+            line.align(),
+            |layout_i, above_opt| {
+                if let Some(above) = above_opt {
+                    height = metrics.extents_line_height(above).0;
+                    placer.place(space(layout_i - 1), height);
+                }
+                let (box_i, _) = placer.clone().place(space(layout_i), height);
+
+                // Overflowing lines have the width of the last box
+                let width = self
+                    .boxes
+                    .get(box_i)
+                    .or(self.boxes.last())
+                    .map_or(0.0, |flow_box| flow_box.width);
+                paragraph_attrs.line_box(LineBox::new(0.0, width), layout_i, shape.rtl)
+            },
+        );
+
+        // Place the lines again with their real heights
+        let mut placer = Placer::new(self, start, limit_opt);
+        let positions = layout
+            .iter()
+            .enumerate()
+            .map(|(layout_i, layout_line)| {
+                let (height, _) = metrics.layout_line_height(layout_line);
+                placer.place(space(layout_i), height)
+            })
+            .collect();
+        (layout, positions)
+    }
+
+    /// Lay out the text of `buffer` in the boxes, with the metrics and wrapping of the buffer
+    pub fn layout(&mut self, font_system: &mut FontSystem, buffer: &mut Buffer) {
+        let metrics = buffer.metrics();
+        let wrap = buffer.wrap();
+
+        let mut layouts = Vec::with_capacity(buffer.lines.len());
+        let mut ranges = vec![FlowRange::default(); self.boxes.len()];
+        let mut tops = vec![Vec::new(); self.boxes.len()];
+        self.overflow_opt = None;
+
+        let mut next = (0, 0.0);
+        for line_i in 0..buffer.lines.len() {
+            buffer.lines[line_i].shape(font_system);
+            let line = &buffer.lines[line_i];
+            let paragraph_attrs = line.paragraph_attrs();
+
+            let mut start = next;
+            let mut limit_opt = None;
+            let (layout, positions) = loop {
+                let (layout, positions) =
+                    self.layout_paragraph(line, metrics, wrap, start, limit_opt);
+
+                // Number of visual lines in each box
+                let mut chunks: Vec<(usize, usize)> = Vec::new();
+                for &(box_i, _) in positions.iter() {
+                    match chunks.last_mut() {
+                        Some((last_box, count)) if *last_box == box_i => *count += 1,
+                        _ => chunks.push((box_i, 1)),
+                    }
+                }
+                if chunks.len() < 2 {
+                    break (layout, positions);
+                }
+
+                // A paragraph already at the top of a box cannot be moved to the next box
+                let (first_box, first_count) = chunks[0];
+                let movable = positions[0].1 > paragraph_attrs.space_before;
+                let height =
+                    paragraph_attrs.height(&layout, &metrics) - paragraph_attrs.space_after;
+                let fits_next = self.fits(first_box + 1, height);
+                if movable
+                    && ((self.pagination.keep_together && fits_next)
+                        || first_count < self.pagination.orphans)
+                {
+                    start = (first_box + 1, 0.0);
+                    limit_opt = None;
+                    continue;
+                }
+
+                let (_, last_count) = chunks[chunks.len() - 1];
+                let (prev_box, prev_count) = chunks[chunks.len() - 2];
+                if last_count < self.pagination.widows && limit_opt.is_none() {
+                    // Carry lines over from the box before the last one
+                    let keep = prev_count.saturating_sub(self.pagination.widows - last_count);
+                    let min_keep = if chunks.len() == 2 {
+                        self.pagination.orphans.max(1)
+                    } else {
+                        1
+                    };
+                    if keep >= min_keep {
+                        limit_opt = Some((prev_box, keep));
+                        continue;
+                    } else if chunks.len() == 2 && movable {
+                        start = (first_box + 1, 0.0);
+                        continue;
+                    }
+                }
+
+                break (layout, positions);
+            };
+
+            let text_len = line.text().len();
+            for (layout_i, &(box_i, top)) in positions.iter().enumerate() {
+                let layout_line = &layout[layout_i];
+                match ranges.get_mut(box_i) {
+                    Some(range) => {
+                        if range.layout_lines.is_empty() {
+                            range.start = line_start(line_i, layout_i, layout_line);
+                        }
+                        range.end = line_end(line_i, layout_i, &layout, text_len);
+                        range.layout_lines.push((line_i, layout_i));
+                        tops[box_i].push(top);
+                    }
+                    None => {
+                        if self.overflow_opt.is_none() {
+                            self.overflow_opt = Some(line_start(line_i, layout_i, layout_line));
+                        }
+                    }
+                }
+                let (height, _) = metrics.layout_line_height(layout_line);
+                next = (box_i, top + height + paragraph_attrs.space_after);
+            }
+            layouts.push(layout);
+        }
+
+        // Empty boxes are at the position where the text continues
+        let mut end = Cursor::new(0, 0);
+        for range in ranges.iter_mut() {
+            if range.layout_lines.is_empty() {
+                range.start = end;
+                range.end = end;
+            } else {
+                end = range.end;
+            }
+        }
+
+        self.layouts = layouts;
+        self.ranges = ranges;
+        self.tops = tops;
+    }
+
+    /// Get the visual lines of a box, with `line_y` from the top of the box
+    pub fn layout_runs<'a>(&'a self, buffer: &'a Buffer, box_i: usize) -> FlowRunIter<'a> {
+        let layout_lines = self
+            .ranges
+            .get(box_i)
+            .map_or(&[][..], |range| &range.layout_lines);
+        let tops = self.tops.get(box_i).map_or(&[][..], |tops| &tops[..]);
+        FlowRunIter {
+            flow: self,
+            buffer,
+            layout_lines: layout_lines.iter().zip(tops.iter()),
+        }
+    }
+
+    /// Draw a box, with positions from the top left of the box
+    #[cfg(feature = "swash")]
+    pub fn draw<F>(
+        &self,
+        font_system: &mut FontSystem,
+        cache: &mut crate::SwashCache,
+        buffer: &Buffer,
+        box_i: usize,
+        color: Color,
+        mut f: F,
+    ) where
+        F: FnMut(i32, i32, u32, u32, Color),
+    {
+        for run in self.layout_runs(buffer, box_i) {
+            for glyph in run.glyphs.iter() {
+                if glyph.object_opt.is_some() {
+                    // Inline objects are drawn by the application
+                    continue;
+                }
+                let (cache_key, x_int, y_int) = (glyph.cache_key, glyph.x_int, glyph.y_int);
+
+                let glyph_color = match glyph.color_opt {
+                    Some(some) => some,
+                    None => color,
+                };
+
+                cache.with_pixels(font_system, cache_key, glyph_color, |x, y, color| {
+                    f(x_int + x, run.line_y as i32 + y_int + y, 1, 1, color);
+                });
+            }
+        }
+    }
+}
+
+/// Cursor at the start of a visual line
+fn line_start(line_i: usize, layout_i: usize, layout_line: &LayoutLine) -> Cursor {
+    let index = if layout_i == 0 {
+        0
+    } else {
+        layout_line
+            .glyphs
+            .iter()
+            .map(|glyph| glyph.start)
+            .min()
+            .unwrap_or_default()
+    };
+    Cursor::new(line_i, index)
+}
+
+/// Cursor at the end of a visual line, which includes the whitespace a line is wrapped at
+fn line_end(line_i: usize, layout_i: usize, layout: &[LayoutLine], text_len: usize) -> Cursor {
+    match layout.get(layout_i + 1) {
+        Some(next_line) => line_start(line_i, layout_i + 1, next_line),
+        None => Cursor::new(line_i, text_len),
+    }
+}
+
+/// Places the visual lines of a paragraph in the boxes of a [`Flow`] one after another
+#[derive(Clone)]
+struct Placer<'a> {
+    flow: &'a Flow,
+    box_i: usize,
+    y: f32,
+    box_lines: usize,
+    limit_opt: Option<(usize, usize)>,
+}
+
+impl<'a> Placer<'a> {
+    fn new(flow: &'a Flow, start: (usize, f32), limit_opt: Option<(usize, usize)>) -> Self {
+        Self {
+            flow,
+            box_i: start.0,
+            y: start.1,
+            box_lines: 0,
+            limit_opt,
+        }
+    }
+
+    /// Place the next visual line with `space` above it, returning its box and top
+    fn place(&mut self, space: f32, height: f32) -> (usize, f32) {
+        loop {
+            let limit = match self.limit_opt {
+                Some((limit_box, limit)) if limit_box == self.box_i => limit,
+                _ => usize::MAX,
+            };
+            // A line is always placed at the top of an empty box, even if it is taller than the
+            // box, so that the text after it is not pushed out of every box
+            let empty = self.box_lines == 0 && self.y == 0.0;
+            let fits = self.flow.fits(self.box_i, self.y + space + height);
+            if (fits || empty) && self.box_lines < limit {
+                break;
+            }
+            self.box_i += 1;
+            self.y = 0.0;
+            self.box_lines = 0;
+        }
+        let top = self.y + space;
+        self.y = top + height;
+        self.box_lines += 1;
+        (self.box_i, top)
+    }
+}
+
+/// An iterator of the visual lines in a box of a [`Flow`]
+pub struct FlowRunIter<'a> {
+    flow: &'a Flow,
+    buffer: &'a Buffer,
+    layout_lines: iter::Zip<slice::Iter<'a, (usize, usize)>, slice::Iter<'a, f32>>,
+}
+
+impl<'a> Iterator for FlowRunIter<'a> {
+    type Item = LayoutRun<'a>;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.layout_lines.size_hint()
+    }
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&(line_i, layout_i), top) = self.layout_lines.next()?;
+        let line = self.buffer.lines.get(line_i)?;
+        let layout_line = self.flow.layouts.get(line_i)?.get(layout_i)?;
+        let (line_height, baseline) = self.buffer.metrics().layout_line_height(layout_line);
+        Some(LayoutRun {
+            line_i,
+            layout_i,
+            text: line.text(),
+            rtl: line.shape_opt().as_ref()?.rtl,
+            glyphs: &layout_line.glyphs,
+            line_y: top + baseline,
+            line_top: *top,
+            line_height,
+            line_w: layout_line.w,
+            fold_opt: None,
+        })
+    }
+}
+
+impl<'a> ExactSizeIterator for FlowRunIter<'a> {}
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_flow() {
+    use crate::Attrs;
+
+    let mut font_system = FontSystem::new();
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(14.0, 20.0));
+    buffer.set_size(&mut font_system, 1000.0, 1000.0);
+    buffer.set_text(&mut font_system, "word", Attrs::new());
+    // Boxes with room for one word on each of three lines
+    let width = buffer.layout_runs().next().expect("no layout runs").line_w * 1.5;
+    buffer.set_text(
+        &mut font_system,
+        "word\nword word word\nword word",
+        Attrs::new(),
+    );
+
+    let box_lines = |flow: &Flow| -> Vec<Vec<(usize, usize)>> {
+        flow.ranges()
+            .iter()
+            .map(|range| range.layout_lines.clone())
+            .collect()
+    };
+
+    let mut flow = Flow::new(vec![FlowBox::new(width, 60.0); 3], Pagination::new());
+    flow.layout(&mut font_system, &mut buffer);
+    assert_eq!(
+        box_lines(&flow),
+        [
+            vec![(0, 0), (1, 0), (1, 1)],
+            vec![(1, 2), (2, 0), (2, 1)],
+            vec![],
+        ]
+    );
+    assert_eq!(flow.ranges()[0].start, Cursor::new(0, 0));
+    assert_eq!(flow.ranges()[1].start, Cursor::new(1, 10));
+    assert_eq!(flow.ranges()[1].end, Cursor::new(2, 9));
+    assert_eq!(flow.ranges()[2].start, Cursor::new(2, 9));
+    assert_eq!(flow.overflow(), None);
+
+    let runs: Vec<(usize, usize, f32)> = flow
+        .layout_runs(&buffer, 1)
+        .map(|run| (run.line_i, run.layout_i, run.line_top))
+        .collect();
+    assert_eq!(runs, [(1, 2, 0.0), (2, 0, 20.0), (2, 1, 40.0)]);
+
+    flow.set_pagination(Pagination::new().widows(2));
+    flow.layout(&mut font_system, &mut buffer);
+    assert_eq!(
+        box_lines(&flow),
+        [
+            vec![(0, 0), (1, 0)],
+            vec![(1, 1), (1, 2)],
+            vec![(2, 0), (2, 1)],
+        ]
+    );
+
+    flow.set_pagination(Pagination::new().keep_together(true));
+    flow.set_boxes(vec![FlowBox::new(width, 60.0); 2]);
+    flow.layout(&mut font_system, &mut buffer);
+    assert_eq!(
+        box_lines(&flow),
+        [vec![(0, 0)], vec![(1, 0), (1, 1), (1, 2)]]
+    );
+    assert_eq!(flow.overflow(), Some(Cursor::new(2, 0)));
+
+    // Boxes shorter than a line still get one line each
+    flow.set_pagination(Pagination::new());
+    flow.set_boxes(vec![FlowBox::new(width, 10.0); 2]);
+    flow.layout(&mut font_system, &mut buffer);
+    assert_eq!(box_lines(&flow), [vec![(0, 0)], vec![(1, 0)]]);
+    assert_eq!(flow.overflow(), Some(Cursor::new(1, 5)));
+}
@@ -2,11 +2,19 @@
 
 use alloc::sync::Arc;
 #[cfg(not(feature = "std"))]
-use alloc::vec::Vec;
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
 use fontdb::Family;
-use unicode_script::Script;
+/// Script of text, for [`Fallback::script_families`]
+///
+/// This is re-exported from the version of the `unicode-script` crate used by this crate, so the
+/// script of text can be found with its `UnicodeScript` trait without depending on a matching
+/// version.
+pub use unicode_script::Script;
 
-use crate::{Font, FontSystem};
+use crate::{Attrs, Font, FontSystem, Synthesis};
 
 use self::platform::*;
 
@@ -26,8 +34,122 @@ mod platform;
 #[path = "windows.rs"]
 mod platform;
 
+/// Families of fonts to try for text that the fonts of its own family cannot shape, see
+/// [`FontSystem::set_fallback`]
+///
+/// The families for the script of the text are tried first, then the common families, and then
+/// every other face if [`Self::all_faces`] is set. Families that are not set use the defaults for
+/// the platform, and scripts without families use the defaults for the platform if
+/// [`Self::platform_scripts`] is set.
+#[derive(Clone, Debug)]
+pub struct Fallback {
+    /// Families for scripts, replacing the families of the platform for those scripts
+    pub scripts: Vec<(Script, Vec<String>)>,
+    /// Use the families of the platform for scripts that are not in [`Self::scripts`]
+    pub platform_scripts: bool,
+    /// Families for any script, or `None` to use the families of the platform
+    pub common_opt: Option<Vec<String>>,
+    /// Families to never use, or `None` to use the families of the platform
+    pub forbidden_opt: Option<Vec<String>>,
+    /// Try every other face that is not forbidden, after the families for scripts and the
+    /// common families
+    pub all_faces: bool,
+}
+
+impl Fallback {
+    /// Create a new fallback with the families of the platform, trying every other face last
+    pub const fn new() -> Self {
+        Self {
+            scripts: Vec::new(),
+            platform_scripts: true,
+            common_opt: None,
+            forbidden_opt: None,
+            all_faces: true,
+        }
+    }
+
+    /// Create a new fallback without any families, which only uses the family of the text
+    pub const fn empty() -> Self {
+        Self {
+            scripts: Vec::new(),
+            platform_scripts: false,
+            common_opt: Some(Vec::new()),
+            forbidden_opt: Some(Vec::new()),
+            all_faces: false,
+        }
+    }
+
+    /// Set the families for a script in [`Self::scripts`]
+    ///
+    /// Other scripts keep their families.
+    pub fn script_families(mut self, script: Script, families: &[&str]) -> Self {
+        let families = families.iter().map(|family| family.to_string()).collect();
+        match self.scripts.iter_mut().find(|(s, _)| *s == script) {
+            Some((_, existing)) => *existing = families,
+            None => self.scripts.push((script, families)),
+        }
+        self
+    }
+
+    /// Set [`Self::platform_scripts`]
+    pub fn platform_scripts(mut self, platform_scripts: bool) -> Self {
+        self.platform_scripts = platform_scripts;
+        self
+    }
+
+    /// Set [`Self::common_opt`]
+    pub fn common_families(mut self, families: &[&str]) -> Self {
+        self.common_opt = Some(families.iter().map(|family| family.to_string()).collect());
+        self
+    }
+
+    /// Set [`Self::forbidden_opt`]
+    pub fn forbidden_families(mut self, families: &[&str]) -> Self {
+        self.forbidden_opt = Some(families.iter().map(|family| family.to_string()).collect());
+        self
+    }
+
+    /// Set [`Self::all_faces`]
+    pub fn all_faces(mut self, all_faces: bool) -> Self {
+        self.all_faces = all_faces;
+        self
+    }
+
+    /// Get a family for a script by its index
+    fn script_family<'a>(&'a self, script: Script, locale: &str, i: usize) -> Option<&'a str> {
+        match self.scripts.iter().find(|(s, _)| *s == script) {
+            Some((_, families)) => families.get(i).map(String::as_str),
+            None if self.platform_scripts => script_fallback(script, locale).get(i).copied(),
+            None => None,
+        }
+    }
+
+    /// Get a common family by its index
+    fn common_family(&self, i: usize) -> Option<&str> {
+        match &self.common_opt {
+            Some(families) => families.get(i).map(String::as_str),
+            None => common_fallback().get(i).copied(),
+        }
+    }
+
+    /// Check if a family is forbidden
+    fn forbidden(&self, family_name: &str) -> bool {
+        match &self.forbidden_opt {
+            Some(families) => families.iter().any(|family| family == family_name),
+            None => forbidden_fallback().contains(&family_name),
+        }
+    }
+}
+
+impl Default for Fallback {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct FontFallbackIter<'a> {
     font_system: &'a mut FontSystem,
+    fallback: Arc<Fallback>,
     font_ids: &'a [fontdb::ID],
     default_families: &'a [&'a Family<'a>],
     default_i: usize,
@@ -46,6 +168,7 @@ impl<'a> FontFallbackIter<'a> {
         scripts: Vec<Script>,
     ) -> Self {
         Self {
+            fallback: font_system.fallback_arc(),
             font_system,
             font_ids,
             default_families,
@@ -75,7 +198,10 @@ impl<'a> FontFallbackIter<'a> {
                 word
             );
         } else if !self.scripts.is_empty() && self.common_i > 0 {
-            let family = common_fallback()[self.common_i - 1];
+            let family = self
+                .fallback
+                .common_family(self.common_i - 1)
+                .unwrap_or_default();
             log::debug!(
                 "Failed to find script fallback for {:?} locale '{}', used '{}': '{}'",
                 self.scripts,
@@ -98,6 +224,18 @@ impl<'a> FontFallbackIter<'a> {
         }
     }
 
+    /// Get the styles that have to be synthesized to use a font with `attrs`, see
+    /// [`Attrs::synthesis`]
+    pub fn synthesis(&self, font: &Font, attrs: &Attrs) -> Synthesis {
+        if font.has_color_glyphs() {
+            return Synthesis::new();
+        }
+        match self.font_system.db().face(font.id()) {
+            Some(face) => attrs.synthesis(face),
+            None => Synthesis::new(),
+        }
+    }
+
     fn face_contains_family(&self, id: fontdb::ID, family_name: &str) -> bool {
         if let Some(face) = self.font_system.db().face(id) {
             face.families.iter().any(|(name, _)| name == family_name)
@@ -125,13 +263,17 @@ impl<'a> Iterator for FontFallbackIter<'a> {
             }
         }
 
+        let fallback = Arc::clone(&self.fallback);
         while self.script_i.0 < self.scripts.len() {
             let script = self.scripts[self.script_i.0];
 
-            let script_families = script_fallback(script, self.font_system.locale());
-            while self.script_i.1 < script_families.len() {
-                let script_family = script_families[self.script_i.1];
+            while let Some(script_family) =
+                fallback.script_family(script, self.font_system.locale(), self.script_i.1)
+            {
                 self.script_i.1 += 1;
+                if fallback.forbidden(script_family) {
+                    continue;
+                }
                 for id in self.font_ids.iter() {
                     if self.face_contains_family(*id, script_family) {
                         if let Some(font) = self.font_system.get_font(*id) {
@@ -151,10 +293,11 @@ impl<'a> Iterator for FontFallbackIter<'a> {
             self.script_i.1 = 0;
         }
 
-        let common_families = common_fallback();
-        while self.common_i < common_families.len() {
-            let common_family = common_families[self.common_i];
+        while let Some(common_family) = fallback.common_family(self.common_i) {
             self.common_i += 1;
+            if fallback.forbidden(common_family) {
+                continue;
+            }
             for id in self.font_ids.iter() {
                 if self.face_contains_family(*id, common_family) {
                     if let Some(font) = self.font_system.get_font(*id) {
@@ -165,16 +308,18 @@ impl<'a> Iterator for FontFallbackIter<'a> {
             log::debug!("failed to find family '{}'", common_family);
         }
 
-        //TODO: do we need to do this?
         //TODO: do not evaluate fonts more than once!
-        let forbidden_families = forbidden_fallback();
-        while self.other_i < self.font_ids.len() {
+        while fallback.all_faces && self.other_i < self.font_ids.len() {
             let id = self.font_ids[self.other_i];
             self.other_i += 1;
-            if forbidden_families
-                .iter()
-                .all(|family_name| !self.face_contains_family(id, family_name))
-            {
+            let forbidden = match self.font_system.db().face(id) {
+                Some(face) => face
+                    .families
+                    .iter()
+                    .any(|(name, _)| fallback.forbidden(name)),
+                None => false,
+            };
+            if !forbidden {
                 if let Some(font) = self.font_system.get_font(id) {
                     return Some(font);
                 }
@@ -185,3 +330,77 @@ impl<'a> Iterator for FontFallbackIter<'a> {
         None
     }
 }
+
+#[test]
+fn test_script_families() {
+    let locale = "en-US";
+    let platform = |script| script_fallback(script, locale).first().copied();
+
+    // Setting the families of one script keeps the families of the platform for the others
+    let fallback = Fallback::new().script_families(Script::Hebrew, &["Hebrew Sans"]);
+    assert_eq!(
+        fallback.script_family(Script::Hebrew, locale, 0),
+        Some("Hebrew Sans")
+    );
+    assert_eq!(fallback.script_family(Script::Hebrew, locale, 1), None);
+    assert_eq!(
+        fallback.script_family(Script::Arabic, locale, 0),
+        platform(Script::Arabic)
+    );
+
+    let fallback = fallback.platform_scripts(false);
+    assert_eq!(fallback.script_family(Script::Arabic, locale, 0), None);
+    let fallback = Fallback::empty().script_families(Script::Arabic, &["Arabic Sans"]);
+    assert_eq!(
+        fallback.script_family(Script::Arabic, locale, 0),
+        Some("Arabic Sans")
+    );
+    assert_eq!(fallback.script_family(Script::Han, locale, 0), None);
+}
+
+#[test]
+#[ignore = "needs the DejaVu Sans and DejaVu Serif system fonts"]
+fn test_fallback() {
+    use crate::{Attrs, AttrsList, ShapeLine, Wrap};
+
+    let mut font_system = FontSystem::new();
+    let has_family = |font_system: &FontSystem, family: &str| {
+        font_system
+            .db()
+            .faces()
+            .any(|face| face.families.iter().any(|(name, _)| name == family))
+    };
+    assert!(has_family(&font_system, "DejaVu Serif") && has_family(&font_system, "DejaVu Sans"));
+
+    // Hebrew is in DejaVu Sans but not in DejaVu Serif
+    let shape = |font_system: &mut FontSystem, fallback: Fallback| {
+        font_system.set_fallback(fallback);
+        let attrs_list = AttrsList::new(Attrs::new().family(Family::Name("DejaVu Serif")));
+        let shape = ShapeLine::new(font_system, "\u{5D0}", &attrs_list);
+        let glyph = shape
+            .layout(16.0, 1000.0, Wrap::Word, None)
+            .remove(0)
+            .glyphs
+            .remove(0);
+        let face = font_system
+            .db()
+            .face(glyph.cache_key.font_id)
+            .expect("no face");
+        (face.families[0].0.clone(), glyph.cache_key.glyph_id)
+    };
+
+    let (_, glyph_id) = shape(&mut font_system, Fallback::empty());
+    assert_eq!(glyph_id, 0);
+
+    let sans = Fallback::empty().script_families(Script::Hebrew, &["DejaVu Sans"]);
+    let (family, glyph_id) = shape(&mut font_system, sans);
+    assert_eq!(family, "DejaVu Sans");
+    assert_ne!(glyph_id, 0);
+
+    let forbidden = Fallback::empty()
+        .common_families(&["DejaVu Sans"])
+        .forbidden_families(&["DejaVu Sans"])
+        .all_faces(true);
+    let (_, glyph_id) = shape(&mut font_system, forbidden);
+    assert_eq!(glyph_id, 0);
+}
@@ -1,4 +1,5 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
+pub use self::fallback::{Fallback, Script};
 pub(crate) mod fallback;
 
 use alloc::sync::Arc;
@@ -76,6 +77,14 @@ impl Font {
         self.0.borrow_rustybuzz()
     }
 
+    /// Check if the font has color glyphs, like emoji fonts, from its color tables
+    pub fn has_color_glyphs(&self) -> bool {
+        let raw_face = self.rustybuzz().raw_face();
+        [b"COLR", b"CBDT", b"sbix", b"SVG "]
+            .iter()
+            .any(|tag| raw_face.table(rustybuzz::Tag::from_bytes(tag)).is_some())
+    }
+
     #[cfg(feature = "swash")]
     pub fn as_swash(&self) -> swash::FontRef {
         let swash = self.0.borrow_swash();
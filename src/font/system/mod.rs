@@ -1,5 +1,10 @@
+use alloc::collections::BTreeMap;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
 use core::ops::{Deref, DerefMut};
 
+use crate::Attrs;
+
 #[cfg(not(feature = "std"))]
 pub use self::no_std::*;
 #[cfg(not(feature = "std"))]
@@ -10,6 +15,32 @@ pub use self::std::*;
 #[cfg(feature = "std")]
 mod std;
 
+/// Get the nearest face of each family for `attrs`, see [`Attrs::match_distance`], in the order
+/// of the first face of each family in `db`
+fn font_matches(db: &fontdb::Database, attrs: &Attrs) -> Vec<fontdb::ID> {
+    let mut family_indexes: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut matches: Vec<(fontdb::ID, (u16, u8, u16))> = Vec::new();
+    for face in db.faces() {
+        let distance = attrs.match_distance(face);
+        let family = match face.families.first() {
+            Some((name, _)) => name.as_str(),
+            None => face.post_script_name.as_str(),
+        };
+        match family_indexes.get(family) {
+            Some(&i) => {
+                if distance < matches[i].1 {
+                    matches[i] = (face.id, distance);
+                }
+            }
+            None => {
+                family_indexes.insert(family, matches.len());
+                matches.push((face.id, distance));
+            }
+        }
+    }
+    matches.into_iter().map(|(id, _)| id).collect()
+}
+
 // re-export fontdb and rustybuzz
 pub use fontdb;
 pub use rustybuzz;
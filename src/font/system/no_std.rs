@@ -6,12 +6,13 @@ use alloc::{
     vec::Vec,
 };
 
-use crate::{Attrs, Font};
+use crate::{Attrs, Fallback, Font};
 
 /// Access system fonts
 pub struct FontSystem {
     locale: String,
     db: fontdb::Database,
+    fallback: Arc<Fallback>,
 }
 
 impl FontSystem {
@@ -25,11 +26,15 @@ impl FontSystem {
             db.set_serif_family("DejaVu Serif");
         }
 
-        Self { locale, db }
+        Self::new_with_locale_and_db(locale, db)
     }
 
     pub fn new_with_locale_and_db(locale: String, db: fontdb::Database) -> Self {
-        Self { locale, db }
+        Self {
+            locale,
+            db,
+            fallback: Arc::new(Fallback::new()),
+        }
     }
 
     pub fn locale(&self) -> &str {
@@ -40,6 +45,22 @@ impl FontSystem {
         &self.db
     }
 
+    /// Get the families of fonts to try for text that the fonts of its own family cannot shape
+    pub fn fallback(&self) -> &Fallback {
+        &self.fallback
+    }
+
+    /// Set the families of fonts to try for text that the fonts of its own family cannot shape
+    ///
+    /// Text that was already shaped has to be shaped again to use the new fallback.
+    pub fn set_fallback(&mut self, fallback: Fallback) {
+        self.fallback = Arc::new(fallback);
+    }
+
+    pub(crate) fn fallback_arc(&self) -> Arc<Fallback> {
+        Arc::clone(&self.fallback)
+    }
+
     pub fn db_mut(&mut self) -> &mut fontdb::Database {
         &mut self.db
     }
@@ -49,12 +70,7 @@ impl FontSystem {
     }
 
     pub fn get_font_matches(&mut self, attrs: Attrs) -> Arc<Vec<fontdb::ID>> {
-        let ids = self
-            .db
-            .faces()
-            .filter(|face| attrs.matches(face))
-            .map(|face| face.id)
-            .collect::<Vec<_>>();
+        let ids = super::font_matches(&self.db, &attrs);
 
         Arc::new(ids)
     }
@@ -2,12 +2,13 @@
 
 use std::{collections::HashMap, sync::Arc};
 
-use crate::{Attrs, AttrsOwned, Font};
+use crate::{Attrs, AttrsOwned, Fallback, Font};
 
 /// Access system fonts
 pub struct FontSystem {
     locale: String,
     db: fontdb::Database,
+    fallback: Arc<Fallback>,
     font_cache: HashMap<fontdb::ID, Option<Arc<Font>>>,
     font_matches_cache: HashMap<AttrsOwned, Arc<Vec<fontdb::ID>>>,
 }
@@ -66,6 +67,7 @@ impl FontSystem {
         Self {
             locale,
             db,
+            fallback: Arc::new(Fallback::new()),
             font_cache: HashMap::new(),
             font_matches_cache: HashMap::new(),
         }
@@ -79,6 +81,22 @@ impl FontSystem {
         &self.db
     }
 
+    /// Get the families of fonts to try for text that the fonts of its own family cannot shape
+    pub fn fallback(&self) -> &Fallback {
+        &self.fallback
+    }
+
+    /// Set the families of fonts to try for text that the fonts of its own family cannot shape
+    ///
+    /// Text that was already shaped has to be shaped again to use the new fallback.
+    pub fn set_fallback(&mut self, fallback: Fallback) {
+        self.fallback = Arc::new(fallback);
+    }
+
+    pub(crate) fn fallback_arc(&self) -> Arc<Fallback> {
+        Arc::clone(&self.fallback)
+    }
+
     pub fn db_mut(&mut self) -> &mut fontdb::Database {
         self.font_matches_cache.clear();
         &mut self.db
@@ -100,12 +118,7 @@ impl FontSystem {
                 #[cfg(not(target_arch = "wasm32"))]
                 let now = std::time::Instant::now();
 
-                let ids = self
-                    .db
-                    .faces()
-                    .filter(|face| attrs.matches(face))
-                    .map(|face| face.id)
-                    .collect::<Vec<_>>();
+                let ids = super::font_matches(&self.db, &attrs);
 
                 #[cfg(not(target_arch = "wasm32"))]
                 {
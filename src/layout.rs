@@ -1,11 +1,12 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+use core::cmp::Ordering;
 use core::fmt::Display;
 
 #[cfg(not(feature = "std"))]
 use alloc::vec::Vec;
 
-use crate::{CacheKey, Color};
+use crate::{CacheKey, Color, InlineObject, Metrics, Synthesis};
 
 /// A laid out glyph
 #[derive(Debug)]
@@ -46,22 +47,208 @@ pub struct LayoutGlyph {
     pub x_int: i32,
     /// Integer component of Y offset in line
     pub y_int: i32,
+    /// Styles that are synthesized because the font of the glyph does not have the requested
+    /// weight or style, which is also in [`CacheKey::synthesis`]
+    pub synthesis: Synthesis,
     /// Optional color override
     pub color_opt: Option<Color>,
     /// Metadata from `Attrs`
     pub metadata: usize,
+    /// Inline object from `Attrs`, which the application draws with the hitbox of the glyph as
+    /// its horizontal extent, from `ascent` above the baseline of the run to `descent` below it
+    pub object_opt: Option<InlineObject>,
 }
 
 /// A line of laid out glyphs
 pub struct LayoutLine {
     /// Width of the line
     pub w: f32,
+    /// Largest ascent of the glyphs in the line, which is zero if it has no glyphs
+    pub max_ascent: f32,
+    /// Largest descent of the glyphs in the line, which is zero if it has no glyphs
+    pub max_descent: f32,
+    /// Largest line gap of the fonts of the glyphs in the line
+    pub max_line_gap: f32,
     /// Glyphs in line
     pub glyphs: Vec<LayoutGlyph>,
 }
 
+impl LayoutLine {
+    /// Get the extents of the fonts of the glyphs in the line
+    pub fn extents(&self) -> LineExtents {
+        LineExtents {
+            max_ascent: self.max_ascent,
+            max_descent: self.max_descent,
+            max_line_gap: self.max_line_gap,
+        }
+    }
+}
+
+/// The extents of the fonts of the glyphs in a visual line, see [`LayoutLine::extents`]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LineExtents {
+    /// Largest ascent of the glyphs in the line, which is zero if it has no glyphs
+    pub max_ascent: f32,
+    /// Largest descent of the glyphs in the line, which is zero if it has no glyphs
+    pub max_descent: f32,
+    /// Largest line gap of the fonts of the glyphs in the line
+    pub max_line_gap: f32,
+}
+
+/// The horizontal extent of a visual line, see [`crate::ShapeLine::layout_to_boxes`]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LineBox {
+    /// X offset of the start of the box
+    pub x: f32,
+    /// Width of the box
+    pub w: f32,
+}
+
+impl LineBox {
+    /// Create a new box with an offset and width
+    pub const fn new(x: f32, w: f32) -> Self {
+        Self { x, w }
+    }
+
+    /// Get the widest part of `0..width` that is not covered by the exclusions overlapping
+    /// `y..y + height`, or an empty box at 0 if all of it is covered
+    pub fn around(exclusions: &[Exclusion], width: f32, y: f32, height: f32) -> Self {
+        let mut covered: Vec<(f32, f32)> = exclusions
+            .iter()
+            .filter(|exclusion| exclusion.y < y + height && exclusion.y + exclusion.h > y)
+            .map(|exclusion| (exclusion.x, exclusion.x + exclusion.w))
+            .collect();
+        covered.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+        let mut widest = Self::new(0.0, 0.0);
+        let mut start = 0.0;
+        for (left, right) in covered.into_iter().chain([(width, width)]) {
+            let end = left.min(width);
+            if end - start > widest.w {
+                widest = Self::new(start, end - start);
+            }
+            start = start.max(right);
+        }
+        widest
+    }
+}
+
+/// A rectangle that text flows around, see [`crate::Buffer::set_exclusions`]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Exclusion {
+    /// X offset of the left edge
+    pub x: f32,
+    /// Y offset of the top edge, from the top of the first line
+    pub y: f32,
+    /// Width of the rectangle
+    pub w: f32,
+    /// Height of the rectangle
+    pub h: f32,
+}
+
+impl Exclusion {
+    /// Create a new exclusion with an offset and size
+    pub const fn new(x: f32, y: f32, w: f32, h: f32) -> Self {
+        Self { x, y, w, h }
+    }
+}
+
+/// Indentation and spacing of a paragraph, see [`crate::BufferLine::set_paragraph_attrs`]
+///
+/// Indents are at the start of the line, which is on the right for RTL paragraphs.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct ParagraphAttrs {
+    /// Indent of the first visual line, from the start margin
+    pub first_line_indent: f32,
+    /// Indent of the visual lines after the first, from the start margin
+    pub hanging_indent: f32,
+    /// Space between the left edge and the text
+    pub margin_left: f32,
+    /// Space between the text and the right edge
+    pub margin_right: f32,
+    /// Space above the first visual line
+    pub space_before: f32,
+    /// Space below the last visual line
+    pub space_after: f32,
+}
+
+impl ParagraphAttrs {
+    /// Create new paragraph attributes without indentation or spacing
+    pub const fn new() -> Self {
+        Self {
+            first_line_indent: 0.0,
+            hanging_indent: 0.0,
+            margin_left: 0.0,
+            margin_right: 0.0,
+            space_before: 0.0,
+            space_after: 0.0,
+        }
+    }
+
+    /// Set [`Self::first_line_indent`]
+    pub fn first_line_indent(mut self, first_line_indent: f32) -> Self {
+        self.first_line_indent = first_line_indent;
+        self
+    }
+
+    /// Set [`Self::hanging_indent`]
+    pub fn hanging_indent(mut self, hanging_indent: f32) -> Self {
+        self.hanging_indent = hanging_indent;
+        self
+    }
+
+    /// Set [`Self::margin_left`] and [`Self::margin_right`]
+    pub fn margins(mut self, margin_left: f32, margin_right: f32) -> Self {
+        self.margin_left = margin_left;
+        self.margin_right = margin_right;
+        self
+    }
+
+    /// Set [`Self::space_before`] and [`Self::space_after`]
+    pub fn spacing(mut self, space_before: f32, space_after: f32) -> Self {
+        self.space_before = space_before;
+        self.space_after = space_after;
+        self
+    }
+
+    /// Get the box of a visual line inside `line_box`, with the margins and the indent of the
+    /// line removed
+    pub fn line_box(&self, line_box: LineBox, layout_i: usize, rtl: bool) -> LineBox {
+        let indent = if layout_i == 0 {
+            self.first_line_indent
+        } else {
+            self.hanging_indent
+        };
+        let (left, right) = if rtl {
+            (self.margin_left, self.margin_right + indent)
+        } else {
+            (self.margin_left + indent, self.margin_right)
+        };
+        LineBox::new(line_box.x + left, (line_box.w - left - right).max(0.0))
+    }
+
+    /// Get the height of a paragraph with its visual lines, including its spacing
+    pub fn height(&self, layout: &[LayoutLine], metrics: &Metrics) -> f32 {
+        let lines_height: f32 = layout
+            .iter()
+            .map(|layout_line| metrics.layout_line_height(layout_line).0)
+            .sum();
+        self.space_before + lines_height + self.space_after
+    }
+}
+
 /// Wrapping mode
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum Wrap {
     /// No wrapping
     None,
@@ -83,6 +270,11 @@ impl Display for Wrap {
 
 /// Align or justify
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum Align {
     Left,
     Right,
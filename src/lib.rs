@@ -107,12 +107,29 @@ mod cache;
 pub use self::edit::*;
 mod edit;
 
+pub use self::flow::*;
+mod flow;
+
 pub use self::font::*;
 mod font;
 
 pub use self::layout::*;
 mod layout;
 
+pub use self::line_ending::*;
+mod line_ending;
+
+#[cfg(feature = "markdown")]
+pub use self::markdown::*;
+#[cfg(feature = "markdown")]
+mod markdown;
+
+pub use self::markup::*;
+mod markup;
+
+pub use self::rich_text::*;
+mod rich_text;
+
 pub use self::shape::*;
 mod shape;
 
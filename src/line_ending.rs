@@ -0,0 +1,113 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use core::ops::Range;
+
+/// Line ending that terminates a line of text
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum LineEnding {
+    /// Line feed (`\n`), used by Unix-like systems
+    Lf,
+    /// Carriage return followed by line feed (`\r\n`), used by Windows
+    CrLf,
+    /// Carriage return (`\r`), used by classic Mac OS
+    Cr,
+    /// No line ending, as found at the end of text that does not end with a newline
+    None,
+}
+
+impl LineEnding {
+    /// Get the string representation of this line ending
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::CrLf => "\r\n",
+            Self::Cr => "\r",
+            Self::None => "",
+        }
+    }
+
+    /// Split the line ending off the end of `line`
+    pub fn strip(line: &str) -> (&str, Self) {
+        if let Some(text) = line.strip_suffix("\r\n") {
+            (text, Self::CrLf)
+        } else if let Some(text) = line.strip_suffix('\n') {
+            (text, Self::Lf)
+        } else if let Some(text) = line.strip_suffix('\r') {
+            (text, Self::Cr)
+        } else {
+            (line, Self::None)
+        }
+    }
+}
+
+/// An iterator over the lines of a string, yielding the range of each line's text and its
+/// [`LineEnding`]
+///
+/// Unlike [`str::lines`], this recognizes `\r` as a line ending. Like [`str::lines`], a final
+/// line ending does not produce an empty line after it, instead the last line will have that
+/// line ending.
+pub struct LineIter<'a> {
+    text: &'a str,
+    start: usize,
+}
+
+impl<'a> LineIter<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self { text, start: 0 }
+    }
+}
+
+impl<'a> Iterator for LineIter<'a> {
+    type Item = (Range<usize>, LineEnding);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.start;
+        let remaining = self.text.get(start..)?;
+        if remaining.is_empty() {
+            return None;
+        }
+
+        match remaining.find(&['\r', '\n'][..]) {
+            Some(i) => {
+                let end = start + i;
+                let ending = if remaining[i..].starts_with("\r\n") {
+                    LineEnding::CrLf
+                } else if remaining[i..].starts_with('\r') {
+                    LineEnding::Cr
+                } else {
+                    LineEnding::Lf
+                };
+                self.start = end + ending.as_str().len();
+                Some((start..end, ending))
+            }
+            None => {
+                self.start = self.text.len();
+                Some((start..self.text.len(), LineEnding::None))
+            }
+        }
+    }
+}
+
+#[test]
+fn test_line_iter() {
+    let text = "a\r\nb\n\nc\rd";
+    assert!(LineIter::new(text)
+        .map(|(range, ending)| (&text[range], ending))
+        .eq([
+            ("a", LineEnding::CrLf),
+            ("b", LineEnding::Lf),
+            ("", LineEnding::Lf),
+            ("c", LineEnding::Cr),
+            ("d", LineEnding::None),
+        ]));
+
+    // Same number of lines as str::lines
+    assert_eq!(LineIter::new("a\nb\n").count(), 2);
+    assert_eq!(LineIter::new("\n").count(), 1);
+    assert_eq!(LineIter::new("").count(), 0);
+}
@@ -0,0 +1,421 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use pulldown_cmark::{Event, HeadingLevel, Parser, Tag};
+
+use crate::{
+    Attrs, AttrsList, AttrsOwned, BufferLine, FamilyOwned, LineEnding, ParagraphAttrs, Style,
+    Weight,
+};
+
+/// A link in Markdown, see [`parse_markdown`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MarkdownLink {
+    /// Destination of the link
+    pub url: String,
+    /// Title of the link, which is empty if it has none
+    pub title: String,
+}
+
+/// Get the link of text with the given [`Attrs::metadata`], see [`parse_markdown`]
+pub fn markdown_link(links: &[MarkdownLink], metadata: usize) -> Option<&MarkdownLink> {
+    links.get(metadata.checked_sub(1)?)
+}
+
+/// Parse CommonMark into lines, with attributes starting from `attrs`, and its links
+///
+/// Headings and strong emphasis are bold, emphasis is italic, and code spans and code blocks are
+/// monospace. As the font size is set for a whole [`crate::Buffer`] by its [`crate::Metrics`],
+/// headings are set apart by [`ParagraphAttrs`] spacing instead, which is larger for higher
+/// levels. Spacing and indents are in multiples of `font_size`.
+///
+/// List items start with a bullet or number, with [`ParagraphAttrs::hanging_indent`] indenting
+/// the visual lines after the first, and the lines of nested blocks and block quotes are indented
+/// by [`ParagraphAttrs::margin_left`].
+///
+/// The [`Attrs::metadata`] of the text of a link is its index in the returned links plus one, so
+/// the link under a [`crate::Cursor`] from [`crate::Buffer::hit`] can be found by passing the
+/// metadata of [`AttrsList::get_span`] to [`markdown_link`]. Other text has the metadata of
+/// `attrs`, which should be zero.
+pub fn parse_markdown(
+    markdown: &str,
+    attrs: Attrs,
+    font_size: f32,
+) -> (Vec<BufferLine>, Vec<MarkdownLink>) {
+    let mut renderer = MarkdownRenderer {
+        font_size,
+        lines: Vec::new(),
+        links: Vec::new(),
+        text: String::new(),
+        attrs_list: AttrsList::new(attrs),
+        defaults: AttrsOwned::new(attrs),
+        attrs_stack: vec![AttrsOwned::new(attrs)],
+        containers: Vec::new(),
+        lists: Vec::new(),
+        line_started: false,
+        paragraph_attrs: ParagraphAttrs::new(),
+        heading_opt: None,
+        code_block: false,
+    };
+
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Start(tag) => renderer.start(tag),
+            Event::End(tag) => renderer.end(tag),
+            Event::Text(text) => {
+                if renderer.code_block {
+                    // Code blocks end with a line ending
+                    let text = text.strip_suffix('\n').unwrap_or(&text);
+                    for (i, line) in text.split('\n').enumerate() {
+                        if i > 0 {
+                            renderer.end_line();
+                        }
+                        renderer.push(line);
+                    }
+                } else {
+                    renderer.push(&text);
+                }
+            }
+            Event::Code(code) => {
+                let mut attrs = renderer.attrs().clone();
+                attrs.family_owned = FamilyOwned::Monospace;
+                renderer.attrs_stack.push(attrs);
+                renderer.push(&code);
+                renderer.attrs_stack.pop();
+            }
+            Event::FootnoteReference(label) => renderer.push(&format!("[{}]", label)),
+            Event::SoftBreak => renderer.push(" "),
+            Event::HardBreak => renderer.end_line(),
+            Event::Rule => {
+                renderer.block();
+                renderer.push("───");
+                renderer.end_line();
+            }
+            Event::TaskListMarker(checked) => renderer.push(if checked { "☑ " } else { "☐ " }),
+            Event::Html(_) => {}
+        }
+    }
+
+    if renderer.line_started {
+        renderer.end_line();
+    }
+    // Make sure there is always one line
+    if renderer.lines.is_empty() {
+        renderer
+            .lines
+            .push(BufferLine::new(String::new(), AttrsList::new(attrs)));
+    }
+    // The last line has no line ending
+    if let Some(line) = renderer.lines.last_mut() {
+        line.set_ending(LineEnding::None);
+    }
+    (renderer.lines, renderer.links)
+}
+
+/// Indent of the lines of a block quote, in multiples of the font size
+const QUOTE_INDENT: f32 = 1.5;
+/// Indent of the text of a list item after its marker, in multiples of the font size
+const ITEM_INDENT: f32 = 1.5;
+
+/// A block containing other blocks, which indents each of their lines
+enum Container {
+    /// A block quote
+    Quote,
+    /// A list item, with its marker before its first line
+    Item { marker: String, started: bool },
+}
+
+struct MarkdownRenderer {
+    font_size: f32,
+    lines: Vec<BufferLine>,
+    links: Vec<MarkdownLink>,
+    text: String,
+    attrs_list: AttrsList,
+    defaults: AttrsOwned,
+    /// Attributes of the current text, and the elements outside of it
+    attrs_stack: Vec<AttrsOwned>,
+    containers: Vec<Container>,
+    /// Next number of each list, or None if it is not ordered
+    lists: Vec<Option<u64>>,
+    /// True if the markers and paragraph attributes of the current line have been set
+    line_started: bool,
+    paragraph_attrs: ParagraphAttrs,
+    heading_opt: Option<HeadingLevel>,
+    code_block: bool,
+}
+
+impl MarkdownRenderer {
+    fn attrs(&self) -> &AttrsOwned {
+        self.attrs_stack.last().expect("no attrs")
+    }
+
+    fn push_attrs<F: FnOnce(&mut AttrsOwned)>(&mut self, f: F) {
+        let mut attrs = self.attrs().clone();
+        f(&mut attrs);
+        self.attrs_stack.push(attrs);
+    }
+
+    /// Add text to the current line, starting it with the markers of its list items and the
+    /// indents of its containers if needed
+    fn push(&mut self, text: &str) {
+        if !self.line_started {
+            self.line_started = true;
+            let mut margin = 0.0;
+            let mut hanging = 0.0;
+            for container in self.containers.iter_mut() {
+                match container {
+                    Container::Quote => margin += QUOTE_INDENT,
+                    Container::Item { marker, started } => {
+                        if *started {
+                            margin += ITEM_INDENT;
+                        } else {
+                            // The marker hangs before the indented text of the item
+                            self.text.push_str(marker);
+                            hanging += ITEM_INDENT;
+                            *started = true;
+                        }
+                    }
+                }
+            }
+            let spacing = match self.heading_opt {
+                // Higher levels have more space, from 0.25 for level 6 to 1.5 for level 1
+                Some(level) => (7 - level as usize) as f32 / 4.0,
+                None => 0.0,
+            };
+            self.paragraph_attrs = ParagraphAttrs::new()
+                .hanging_indent(hanging * self.font_size)
+                .margins(margin * self.font_size, 0.0)
+                .spacing(spacing * self.font_size, spacing * self.font_size / 2.0);
+        }
+
+        let start = self.text.len();
+        self.text.push_str(text);
+        let attrs = self.attrs_stack.last().expect("no attrs");
+        if *attrs != self.defaults {
+            self.attrs_list
+                .add_span(start..self.text.len(), attrs.as_attrs());
+        }
+    }
+
+    fn end_line(&mut self) {
+        if !self.line_started {
+            self.push("");
+        }
+        let attrs_list = AttrsList::new(self.defaults.as_attrs());
+        let mut line = BufferLine::new(
+            core::mem::take(&mut self.text),
+            core::mem::replace(&mut self.attrs_list, attrs_list),
+        );
+        line.set_ending(LineEnding::Lf);
+        line.set_paragraph_attrs(self.paragraph_attrs);
+        self.lines.push(line);
+        self.line_started = false;
+    }
+
+    /// Start a block, ending the current line, and separating it from the block before it with
+    /// an empty line outside of lists
+    fn block(&mut self) {
+        if self.line_started {
+            self.end_line();
+        }
+        let in_item = self
+            .containers
+            .iter()
+            .any(|container| matches!(container, Container::Item { .. }));
+        let after_block = matches!(self.lines.last(), Some(line) if !line.text().trim().is_empty());
+        if !in_item && after_block {
+            let mut line = BufferLine::new(String::new(), AttrsList::new(self.defaults.as_attrs()));
+            line.set_ending(LineEnding::Lf);
+            self.lines.push(line);
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => self.block(),
+            Tag::Heading(level, ..) => {
+                self.block();
+                self.heading_opt = Some(level);
+                self.push_attrs(|attrs| attrs.weight = Weight::BOLD);
+            }
+            Tag::BlockQuote => {
+                self.block();
+                self.containers.push(Container::Quote);
+            }
+            Tag::CodeBlock(_) => {
+                self.block();
+                self.code_block = true;
+                self.push_attrs(|attrs| attrs.family_owned = FamilyOwned::Monospace);
+            }
+            Tag::List(first_opt) => {
+                if self.lists.is_empty() {
+                    self.block();
+                }
+                self.lists.push(first_opt);
+            }
+            Tag::Item => {
+                if self.line_started {
+                    self.end_line();
+                }
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => String::from("• "),
+                };
+                self.containers.push(Container::Item {
+                    marker,
+                    started: false,
+                });
+            }
+            Tag::Emphasis => self.push_attrs(|attrs| attrs.style = Style::Italic),
+            Tag::Strong => self.push_attrs(|attrs| attrs.weight = Weight::BOLD),
+            Tag::Link(_, url, title) => {
+                self.links.push(MarkdownLink {
+                    url: url.to_string(),
+                    title: title.to_string(),
+                });
+                let metadata = self.links.len();
+                self.push_attrs(|attrs| attrs.metadata = metadata);
+            }
+            Tag::TableRow | Tag::TableHead => {
+                if self.line_started {
+                    self.end_line();
+                }
+            }
+            Tag::TableCell => {
+                if self.line_started {
+                    self.push("\t");
+                }
+            }
+            Tag::FootnoteDefinition(label) => {
+                self.block();
+                self.push(&format!("[{}]: ", label));
+            }
+            Tag::Table(_) => self.block(),
+            Tag::Strikethrough | Tag::Image(..) => {}
+        }
+    }
+
+    fn end(&mut self, tag: Tag) {
+        match tag {
+            Tag::Heading(..) => {
+                self.attrs_stack.pop();
+                if self.line_started {
+                    self.end_line();
+                }
+                self.heading_opt = None;
+            }
+            Tag::Emphasis | Tag::Strong | Tag::Link(..) => {
+                self.attrs_stack.pop();
+            }
+            Tag::CodeBlock(_) => {
+                self.attrs_stack.pop();
+                self.code_block = false;
+                if self.line_started {
+                    self.end_line();
+                }
+            }
+            Tag::BlockQuote | Tag::Item => {
+                if self.line_started {
+                    self.end_line();
+                }
+                self.containers.pop();
+            }
+            Tag::List(_) => {
+                self.lists.pop();
+            }
+            Tag::Paragraph
+            | Tag::Table(_)
+            | Tag::TableHead
+            | Tag::TableRow
+            | Tag::TableCell
+            | Tag::FootnoteDefinition(_)
+            | Tag::Strikethrough
+            | Tag::Image(..) => {}
+        }
+    }
+}
+
+#[test]
+fn test_markdown() {
+    use crate::Family;
+
+    let attrs = Attrs::new().family(Family::Serif);
+    let (lines, links) = parse_markdown(
+        "# Title\n\
+        \n\
+        Some *emphasis*, **strong** and `code`\n\
+        with a [link](https://example.com \"Example\").\n\
+        \n\
+        - First\n\
+        - Second\n  \
+          1. Nested\n\
+        \n\
+        > Quote\n\
+        \n\
+        ```\n\
+        fn main() {}\n\
+        ```\n",
+        attrs,
+        10.0,
+    );
+    let texts: Vec<&str> = lines.iter().map(|line| line.text()).collect();
+    assert_eq!(
+        texts,
+        [
+            "Title",
+            "",
+            "Some emphasis, strong and code with a link.",
+            "",
+            "• First",
+            "• Second",
+            "1. Nested",
+            "",
+            "Quote",
+            "",
+            "fn main() {}",
+        ]
+    );
+
+    assert_eq!(
+        lines[0].attrs_list().get_span(0),
+        attrs.weight(Weight::BOLD)
+    );
+    assert_eq!(
+        lines[0].paragraph_attrs(),
+        ParagraphAttrs::new().spacing(15.0, 7.5)
+    );
+    assert_eq!(lines[2].paragraph_attrs(), ParagraphAttrs::new());
+    assert_eq!(
+        lines[4].paragraph_attrs(),
+        ParagraphAttrs::new().hanging_indent(15.0)
+    );
+    assert_eq!(
+        lines[6].paragraph_attrs(),
+        ParagraphAttrs::new()
+            .hanging_indent(15.0)
+            .margins(15.0, 0.0)
+    );
+    assert_eq!(
+        lines[8].paragraph_attrs(),
+        ParagraphAttrs::new().margins(15.0, 0.0)
+    );
+    let attrs_list = lines[2].attrs_list();
+    assert_eq!(attrs_list.get_span(5), attrs.style(Style::Italic));
+    assert_eq!(attrs_list.get_span(15), attrs.weight(Weight::BOLD));
+    assert_eq!(attrs_list.get_span(26), attrs.family(Family::Monospace));
+    assert_eq!(
+        markdown_link(&links, attrs_list.get_span(38).metadata),
+        Some(&MarkdownLink {
+            url: "https://example.com".to_string(),
+            title: "Example".to_string(),
+        })
+    );
+    assert_eq!(markdown_link(&links, attrs_list.get_span(0).metadata), None);
+    assert_eq!(
+        lines[10].attrs_list().get_span(0),
+        attrs.family(Family::Monospace)
+    );
+    assert_eq!(lines[10].ending(), LineEnding::None);
+}
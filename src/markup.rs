@@ -0,0 +1,538 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+#[cfg(not(feature = "std"))]
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::{self, Write};
+
+use crate::{
+    attrs::{parse_stretch, stretch_keyword},
+    rich_text::{line_spans, parse_color, parse_family},
+    Attrs, AttrsList, AttrsOwned, BufferLine, Color, FamilyOwned, LineEnding, LineIter, Style,
+    Weight,
+};
+
+/// Kind of a [`MarkupError`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MarkupErrorKind {
+    /// A `<` without a `>` after it
+    UnterminatedTag,
+    /// A tag other than `b`, `i`, and `span`
+    UnknownTag(String),
+    /// An attribute of `span` other than `color`, `font_family`, `weight`, `style`, and
+    /// `stretch`, or an attribute of another tag
+    UnknownAttribute(String),
+    /// An attribute without a quoted value
+    MalformedAttribute,
+    /// An attribute value that could not be parsed
+    InvalidValue { attribute: String, value: String },
+    /// A closing tag that does not match the innermost open tag, which is None if there is none
+    UnexpectedClose {
+        expected_opt: Option<String>,
+        found: String,
+    },
+    /// A tag that is not closed before the end of the markup
+    Unclosed(String),
+    /// An `&` that does not start `&amp;`, `&lt;`, `&gt;`, `&quot;`, `&apos;`, or a numeric
+    /// character reference
+    InvalidEntity,
+}
+
+/// An error parsing markup, see [`parse_markup`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MarkupError {
+    pub kind: MarkupErrorKind,
+    /// Byte offset of the error in the markup
+    pub offset: usize,
+    /// Line of the error in the markup, starting at 0
+    pub line: usize,
+    /// Byte index of the error in its line
+    pub index: usize,
+}
+
+impl MarkupError {
+    fn new(kind: MarkupErrorKind, markup: &str, offset: usize) -> Self {
+        let before = &markup[..offset];
+        let line_start = before.rfind(&['\r', '\n'][..]).map_or(0, |i| i + 1);
+        Self {
+            kind,
+            offset,
+            line: before.matches('\n').count() + before.matches('\r').count()
+                - before.matches("\r\n").count(),
+            index: offset - line_start,
+        }
+    }
+}
+
+impl fmt::Display for MarkupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, index {}: ", self.line + 1, self.index)?;
+        match &self.kind {
+            MarkupErrorKind::UnterminatedTag => write!(f, "unterminated tag"),
+            MarkupErrorKind::UnknownTag(tag) => write!(f, "unknown tag <{}>", tag),
+            MarkupErrorKind::UnknownAttribute(attribute) => {
+                write!(f, "unknown attribute {:?}", attribute)
+            }
+            MarkupErrorKind::MalformedAttribute => write!(f, "malformed attribute"),
+            MarkupErrorKind::InvalidValue { attribute, value } => {
+                write!(f, "invalid value {:?} for attribute {:?}", value, attribute)
+            }
+            MarkupErrorKind::UnexpectedClose {
+                expected_opt: Some(expected),
+                found,
+            } => write!(f, "expected </{}>, found </{}>", expected, found),
+            MarkupErrorKind::UnexpectedClose {
+                expected_opt: None,
+                found,
+            } => write!(f, "unexpected </{}>", found),
+            MarkupErrorKind::Unclosed(tag) => write!(f, "unclosed <{}>", tag),
+            MarkupErrorKind::InvalidEntity => write!(f, "invalid entity"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MarkupError {}
+
+/// Parse markup into lines, with attributes starting from `attrs`
+///
+/// The markup is text with these tags, which may be nested and span multiple lines:
+/// - `<b>` for bold text
+/// - `<i>` for italic text
+/// - `<span>` with any of the attributes `color` (like `"#ff0000"` or `"rgba(255, 0, 0, 0.5)"`),
+///   `font_family` (like `"monospace"` or `"Fira Sans"`), `weight` (like `"bold"` or `"600"`),
+///   `style` (`"normal"`, `"italic"`, or `"oblique"`), and `stretch` (a CSS keyword like `"semi-condensed"`)
+///
+/// The characters `&`, `<`, and `>` must be written as `&amp;`, `&lt;`, and `&gt;`. Like
+/// [`crate::Buffer::set_text`], lines are split at line endings, and a final line ending does not
+/// produce an empty line after it.
+///
+/// ## Errors
+///
+/// Returns a [`MarkupError`] with the position of the first problem in the markup.
+pub fn parse_markup(markup: &str, attrs: Attrs) -> Result<Vec<BufferLine>, MarkupError> {
+    let mut parser = MarkupParser {
+        lines: Vec::new(),
+        text: String::new(),
+        attrs_list: AttrsList::new(attrs),
+        defaults: AttrsOwned::new(attrs),
+        attrs: AttrsOwned::new(attrs),
+    };
+    // Open tags, with their offset and the attributes outside of them
+    let mut stack: Vec<(String, usize, AttrsOwned)> = Vec::new();
+
+    let mut offset = 0;
+    while offset < markup.len() {
+        let rest = &markup[offset..];
+        let error = |kind, error_offset| MarkupError::new(kind, markup, offset + error_offset);
+        if let Some(tag) = rest.strip_prefix('<') {
+            let end = tag
+                .find('>')
+                .ok_or_else(|| error(MarkupErrorKind::UnterminatedTag, 0))?;
+            let tag = &tag[..end];
+            if let Some(name) = tag.strip_prefix('/') {
+                let name = name.trim_end();
+                match stack.pop() {
+                    Some((open, _, attrs)) if open == name => parser.attrs = attrs,
+                    open_opt => {
+                        return Err(error(
+                            MarkupErrorKind::UnexpectedClose {
+                                expected_opt: open_opt.map(|(open, _, _)| open),
+                                found: name.to_string(),
+                            },
+                            0,
+                        ))
+                    }
+                }
+            } else {
+                let self_closing = tag.ends_with('/');
+                let tag = tag.strip_suffix('/').unwrap_or(tag);
+                let (name, attributes) = tag
+                    .split_once(|c: char| c.is_ascii_whitespace())
+                    .unwrap_or((tag, ""));
+                let mut attrs = parser.attrs.clone();
+                match name {
+                    "b" => attrs.weight = Weight::BOLD,
+                    "i" => attrs.style = Style::Italic,
+                    "span" => {}
+                    _ => {
+                        return Err(error(MarkupErrorKind::UnknownTag(name.to_string()), 0));
+                    }
+                }
+                // Offset of the attributes after the whitespace following the name, for errors
+                let attributes_offset = 1 + name.len() + 1;
+                parse_attributes(name, attributes, &mut attrs)
+                    .map_err(|(kind, i)| error(kind, attributes_offset + i))?;
+                if !self_closing {
+                    stack.push((name.to_string(), offset, parser.attrs.clone()));
+                    parser.attrs = attrs;
+                }
+            }
+            offset += 1 + end + 1;
+        } else if rest.starts_with('&') {
+            let (c, len) =
+                parse_entity(rest).ok_or_else(|| error(MarkupErrorKind::InvalidEntity, 0))?;
+            parser.push(c.encode_utf8(&mut [0; 4]));
+            offset += len;
+        } else {
+            let end = rest.find(&['<', '&'][..]).unwrap_or(rest.len());
+            for (range, ending) in LineIter::new(&rest[..end]) {
+                parser.push(&rest[range]);
+                if ending != LineEnding::None {
+                    parser.end_line(ending);
+                }
+            }
+            offset += end;
+        }
+    }
+
+    if let Some((name, open_offset, _)) = stack.pop() {
+        return Err(MarkupError::new(
+            MarkupErrorKind::Unclosed(name),
+            markup,
+            open_offset,
+        ));
+    }
+    // Make sure there is always one line, and keep text after the last line ending
+    if parser.lines.is_empty() || !parser.text.is_empty() {
+        parser.end_line(LineEnding::None);
+    }
+    Ok(parser.lines)
+}
+
+/// Convert lines to markup that [`parse_markup`] converts back to the same lines
+///
+/// Attributes are written relative to the defaults of the attributes list of each line, and
+/// metadata is not written.
+pub fn to_markup(lines: &[BufferLine]) -> String {
+    let mut markup = String::new();
+    for line in lines.iter() {
+        let defaults = AttrsOwned::new(line.attrs_list().defaults());
+        for span in line_spans(line, 0..line.text().len()) {
+            let mut tags = Vec::new();
+            let mut attributes = String::new();
+            let attrs = &span.attrs;
+            if attrs.color_opt != defaults.color_opt {
+                if let Some(color) = attrs.color_opt {
+                    write_color(&mut attributes, color);
+                }
+            }
+            if attrs.family_owned != defaults.family_owned {
+                attributes.push_str(" font_family=\"");
+                match &attrs.family_owned {
+                    FamilyOwned::Name(name) => escape_markup(&mut attributes, name),
+                    FamilyOwned::Serif => attributes.push_str("serif"),
+                    FamilyOwned::SansSerif => attributes.push_str("sans-serif"),
+                    FamilyOwned::Cursive => attributes.push_str("cursive"),
+                    FamilyOwned::Fantasy => attributes.push_str("fantasy"),
+                    FamilyOwned::Monospace => attributes.push_str("monospace"),
+                }
+                attributes.push('"');
+            }
+            if attrs.weight != defaults.weight {
+                if attrs.weight == Weight::BOLD {
+                    tags.push("b");
+                } else {
+                    let _ = write!(attributes, " weight=\"{}\"", attrs.weight.0);
+                }
+            }
+            if attrs.style != defaults.style {
+                if attrs.style == Style::Italic {
+                    tags.push("i");
+                } else {
+                    let _ = write!(attributes, " style=\"{}\"", style_name(attrs.style));
+                }
+            }
+            if attrs.stretch != defaults.stretch {
+                let _ = write!(
+                    attributes,
+                    " stretch=\"{}\"",
+                    stretch_keyword(attrs.stretch)
+                );
+            }
+
+            if !attributes.is_empty() {
+                let _ = write!(markup, "<span{}>", attributes);
+            }
+            for tag in tags.iter() {
+                let _ = write!(markup, "<{}>", tag);
+            }
+            escape_markup(&mut markup, &span.text);
+            for tag in tags.iter().rev() {
+                let _ = write!(markup, "</{}>", tag);
+            }
+            if !attributes.is_empty() {
+                markup.push_str("</span>");
+            }
+        }
+        markup.push_str(line.ending().as_str());
+    }
+    markup
+}
+
+struct MarkupParser {
+    lines: Vec<BufferLine>,
+    text: String,
+    attrs_list: AttrsList,
+    defaults: AttrsOwned,
+    attrs: AttrsOwned,
+}
+
+impl MarkupParser {
+    fn push(&mut self, text: &str) {
+        let start = self.text.len();
+        self.text.push_str(text);
+        if self.attrs != self.defaults {
+            self.attrs_list
+                .add_span(start..self.text.len(), self.attrs.as_attrs());
+        }
+    }
+
+    fn end_line(&mut self, ending: LineEnding) {
+        let attrs_list = AttrsList::new(self.defaults.as_attrs());
+        let mut line = BufferLine::new(
+            core::mem::take(&mut self.text),
+            core::mem::replace(&mut self.attrs_list, attrs_list),
+        );
+        line.set_ending(ending);
+        self.lines.push(line);
+    }
+}
+
+/// Parse the attributes of a tag into `attrs`, returning the kind and offset of any error
+fn parse_attributes(
+    name: &str,
+    attributes: &str,
+    attrs: &mut AttrsOwned,
+) -> Result<(), (MarkupErrorKind, usize)> {
+    let mut rest = attributes;
+    loop {
+        let trimmed = rest.trim_start();
+        if trimmed.is_empty() {
+            return Ok(());
+        }
+        let offset = attributes.len() - trimmed.len();
+        let (attribute, value) = match trimmed.split_once('=') {
+            Some((attribute, value)) => (attribute.trim_end(), value.trim_start()),
+            None => return Err((MarkupErrorKind::MalformedAttribute, offset)),
+        };
+        let quote = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => return Err((MarkupErrorKind::MalformedAttribute, offset)),
+        };
+        let (value, after) = match value[1..].split_once(quote) {
+            Some(some) => some,
+            None => return Err((MarkupErrorKind::MalformedAttribute, offset)),
+        };
+        rest = after;
+
+        let invalid = || {
+            (
+                MarkupErrorKind::InvalidValue {
+                    attribute: attribute.to_string(),
+                    value: value.to_string(),
+                },
+                offset,
+            )
+        };
+        let value = unescape_value(value).ok_or_else(invalid)?;
+        match attribute {
+            _ if name != "span" => {
+                return Err((
+                    MarkupErrorKind::UnknownAttribute(attribute.to_string()),
+                    offset,
+                ))
+            }
+            "color" => attrs.color_opt = Some(parse_color(&value).ok_or_else(invalid)?),
+            "font_family" => attrs.family_owned = parse_family(&value),
+            "weight" => attrs.weight = parse_weight(&value).ok_or_else(invalid)?,
+            "style" => {
+                attrs.style = match value.as_str() {
+                    "normal" => Style::Normal,
+                    "italic" => Style::Italic,
+                    "oblique" => Style::Oblique,
+                    _ => return Err(invalid()),
+                }
+            }
+            "stretch" => {
+                attrs.stretch = parse_stretch(&value).ok_or_else(invalid)?;
+            }
+            _ => {
+                return Err((
+                    MarkupErrorKind::UnknownAttribute(attribute.to_string()),
+                    offset,
+                ))
+            }
+        }
+    }
+}
+
+/// Parse the entity at the start of `text`, returning its character and length
+fn parse_entity(text: &str) -> Option<(char, usize)> {
+    let end = text.find(';')?;
+    let c = match &text[1..end] {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        entity => {
+            let code = if let Some(hex) = entity.strip_prefix("#x") {
+                u32::from_str_radix(hex, 16).ok()?
+            } else {
+                entity.strip_prefix('#')?.parse().ok()?
+            };
+            char::from_u32(code)?
+        }
+    };
+    Some((c, end + 1))
+}
+
+/// Replace the entities in an attribute value, returning None if one is invalid
+fn unescape_value(value: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        let (c, len) = parse_entity(&rest[start..])?;
+        unescaped.push(c);
+        rest = &rest[start + len..];
+    }
+    unescaped.push_str(rest);
+    Some(unescaped)
+}
+
+fn escape_markup(markup: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => markup.push_str("&amp;"),
+            '<' => markup.push_str("&lt;"),
+            '>' => markup.push_str("&gt;"),
+            '"' => markup.push_str("&quot;"),
+            _ => markup.push(c),
+        }
+    }
+}
+
+fn write_color(attributes: &mut String, color: Color) {
+    let _ = write!(
+        attributes,
+        " color=\"#{:02x}{:02x}{:02x}",
+        color.r(),
+        color.g(),
+        color.b()
+    );
+    if color.a() != 0xFF {
+        let _ = write!(attributes, "{:02x}", color.a());
+    }
+    attributes.push('"');
+}
+
+const WEIGHTS: [(Weight, &str); 9] = [
+    (Weight::THIN, "thin"),
+    (Weight::EXTRA_LIGHT, "ultralight"),
+    (Weight::LIGHT, "light"),
+    (Weight::NORMAL, "normal"),
+    (Weight::MEDIUM, "medium"),
+    (Weight::SEMIBOLD, "semibold"),
+    (Weight::BOLD, "bold"),
+    (Weight::EXTRA_BOLD, "ultrabold"),
+    (Weight::BLACK, "heavy"),
+];
+
+/// Parse a weight keyword, like `"semibold"`, or a number from 1 to 1000
+fn parse_weight(value: &str) -> Option<Weight> {
+    if let Some((weight, _)) = WEIGHTS.iter().find(|(_, name)| *name == value) {
+        return Some(*weight);
+    }
+    match value.parse() {
+        Ok(weight @ 1..=1000) => Some(Weight(weight)),
+        _ => None,
+    }
+}
+
+fn style_name(style: Style) -> &'static str {
+    match style {
+        Style::Normal => "normal",
+        Style::Italic => "italic",
+        Style::Oblique => "oblique",
+    }
+}
+
+#[test]
+fn test_markup() {
+    use crate::{Family, Stretch};
+
+    let attrs = Attrs::new().family(Family::Serif);
+    let markup = "<b>Bold <i>both</i></b> &amp; <span color=\"#ff0000\" \
+        font_family=\"monospace\">red</span>\r\n\
+        <span weight=\"semibold\" style=\"oblique\" stretch=\"semi-condensed\">last</span>";
+    let lines = parse_markup(markup, attrs).expect("failed to parse markup");
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0].text(), "Bold both & red");
+    assert_eq!(lines[0].ending(), LineEnding::CrLf);
+    let attrs_list = lines[0].attrs_list();
+    assert_eq!(attrs_list.get_span(0), attrs.weight(Weight::BOLD));
+    assert_eq!(
+        attrs_list.get_span(5),
+        attrs.weight(Weight::BOLD).style(Style::Italic)
+    );
+    assert_eq!(attrs_list.get_span(10), attrs);
+    assert_eq!(
+        attrs_list.get_span(12),
+        attrs
+            .color(Color::rgb(0xFF, 0, 0))
+            .family(Family::Monospace)
+    );
+    assert_eq!(
+        lines[1].attrs_list().get_span(0),
+        attrs
+            .weight(Weight::SEMIBOLD)
+            .style(Style::Oblique)
+            .stretch(Stretch::SemiCondensed)
+    );
+
+    let markup_again = to_markup(&lines);
+    let lines_again = parse_markup(&markup_again, attrs).expect("failed to parse markup");
+    assert_eq!(lines_again.len(), lines.len());
+    for (line_again, line) in lines_again.iter().zip(lines.iter()) {
+        assert_eq!(line_again.text(), line.text());
+        assert_eq!(line_again.ending(), line.ending());
+        assert_eq!(line_again.attrs_list(), line.attrs_list());
+    }
+
+    let error = |markup| {
+        parse_markup(markup, attrs)
+            .err()
+            .map(|err| (err.kind, err.line, err.index))
+    };
+    assert_eq!(
+        error("ok\n<b>bold</i>"),
+        Some((
+            MarkupErrorKind::UnexpectedClose {
+                expected_opt: Some("b".to_string()),
+                found: "i".to_string()
+            },
+            1,
+            7
+        ))
+    );
+    assert_eq!(
+        error("a <b>b"),
+        Some((MarkupErrorKind::Unclosed("b".to_string()), 0, 2))
+    );
+    assert_eq!(
+        error("<span weight=\"heavier\">"),
+        Some((
+            MarkupErrorKind::InvalidValue {
+                attribute: "weight".to_string(),
+                value: "heavier".to_string()
+            },
+            0,
+            6
+        ))
+    );
+    assert_eq!(error("a & b"), Some((MarkupErrorKind::InvalidEntity, 0, 2)));
+}
@@ -0,0 +1,665 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+#[cfg(not(feature = "std"))]
+use alloc::{
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{fmt::Write, ops::Range};
+
+use crate::{
+    Attrs, AttrsList, AttrsOwned, Buffer, BufferLine, Color, Cursor, FamilyOwned, Style, Weight,
+};
+
+/// A span of text with the same attributes, see [`RichText`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RichSpan {
+    pub text: String,
+    pub attrs: AttrsOwned,
+}
+
+/// Text of one or more lines with attribute spans, used for copy and paste with attributes
+///
+/// This does not refer to a [`Buffer`] or [`crate::FontSystem`], so it can be kept on an
+/// application clipboard, or converted to and from HTML for the clipboard of the host.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RichText {
+    /// The spans of each line, an empty line has no spans
+    pub lines: Vec<Vec<RichSpan>>,
+}
+
+impl RichText {
+    /// Create a new empty [`RichText`]
+    pub fn new() -> Self {
+        Self { lines: Vec::new() }
+    }
+
+    /// Create a [`RichText`] from the text between two cursors in a [`Buffer`]
+    pub fn from_buffer(buffer: &Buffer, start: Cursor, end: Cursor) -> Self {
+        let mut lines = Vec::new();
+        for line_i in start.line..=end.line {
+            let line = match buffer.lines.get(line_i) {
+                Some(some) => some,
+                None => break,
+            };
+            let line_start = if line_i == start.line { start.index } else { 0 };
+            let line_end = if line_i == end.line {
+                end.index
+            } else {
+                line.text().len()
+            };
+            lines.push(line_spans(line, line_start..line_end));
+        }
+        Self { lines }
+    }
+
+    /// Get the text without attributes, with lines separated by `\n`
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for (line_i, line) in self.lines.iter().enumerate() {
+            if line_i > 0 {
+                text.push('\n');
+            }
+            for span in line.iter() {
+                text.push_str(&span.text);
+            }
+        }
+        text
+    }
+
+    /// Get the attributes of [`Self::text`], with `defaults` used for line endings
+    pub fn attrs_list(&self, defaults: Attrs) -> AttrsList {
+        let mut attrs_list = AttrsList::new(defaults);
+        let mut offset = 0;
+        for (line_i, line) in self.lines.iter().enumerate() {
+            if line_i > 0 {
+                offset += '\n'.len_utf8();
+            }
+            for span in line.iter() {
+                let end = offset + span.text.len();
+                attrs_list.add_span(offset..end, span.attrs.as_attrs());
+                offset = end;
+            }
+        }
+        attrs_list
+    }
+
+    /// Convert to HTML, with the color, weight, style, and family of each span
+    pub fn to_html(&self) -> String {
+        let mut html = String::from("<pre>");
+        for (line_i, line) in self.lines.iter().enumerate() {
+            if line_i > 0 {
+                html.push('\n');
+            }
+            for span in line.iter() {
+                html.push_str("<span style=\"");
+                write_style(&mut html, &span.attrs);
+                html.push_str("\">");
+                escape_html(&mut html, &span.text);
+                html.push_str("</span>");
+            }
+        }
+        html.push_str("</pre>");
+        html
+    }
+
+    /// Convert from HTML, such as the clipboard contents of another application
+    ///
+    /// Colors, weights, styles, and families set by elements and `style` attributes are used,
+    /// starting from `attrs`. Other markup is ignored, and whitespace is collapsed outside of
+    /// `pre` elements.
+    pub fn from_html(html: &str, attrs: Attrs) -> Self {
+        let mut parser = HtmlParser {
+            lines: vec![Vec::new()],
+            stack: Vec::new(),
+            attrs: AttrsOwned::new(attrs),
+            pre: false,
+            skip_depth: 0,
+            block_break: false,
+        };
+
+        let mut rest = html;
+        while !rest.is_empty() {
+            if let Some(comment) = rest.strip_prefix("<!--") {
+                rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            } else if let Some(tag) = rest.strip_prefix('<') {
+                let end = match tag_end(tag) {
+                    Some(some) => some,
+                    None => break,
+                };
+                parser.tag(&tag[..end]);
+                rest = &tag[end + 1..];
+            } else {
+                let end = rest.find('<').unwrap_or(rest.len());
+                if parser.skip_depth == 0 {
+                    parser.text(&decode_entities(&rest[..end]));
+                }
+                rest = &rest[end..];
+            }
+        }
+
+        if parser.block_break && parser.lines.len() > 1 {
+            // Remove the line started after the last block
+            parser.lines.pop();
+        }
+        Self {
+            lines: parser.lines,
+        }
+    }
+}
+
+/// Get the spans of a range of a line
+pub(crate) fn line_spans(line: &BufferLine, range: Range<usize>) -> Vec<RichSpan> {
+    let text = line.text();
+    let attrs_list = line.attrs_list();
+
+    let mut bounds = vec![range.start, range.end];
+    for (span_range, _) in attrs_list.spans() {
+        for bound in [span_range.start, span_range.end] {
+            if bound > range.start && bound < range.end {
+                bounds.push(bound);
+            }
+        }
+    }
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    let mut spans: Vec<RichSpan> = Vec::new();
+    for bound in bounds.windows(2) {
+        let attrs = AttrsOwned::new(attrs_list.get_span(bound[0]));
+        let span_text = &text[bound[0]..bound[1]];
+        match spans.last_mut() {
+            Some(span) if span.attrs == attrs => span.text.push_str(span_text),
+            _ => spans.push(RichSpan {
+                text: span_text.to_string(),
+                attrs,
+            }),
+        }
+    }
+    spans
+}
+
+fn escape_html(html: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            _ => html.push(c),
+        }
+    }
+}
+
+fn write_style(html: &mut String, attrs: &AttrsOwned) {
+    if let Some(color) = attrs.color_opt {
+        if color.a() == 0xFF {
+            let _ = write!(
+                html,
+                "color: #{:02x}{:02x}{:02x}; ",
+                color.r(),
+                color.g(),
+                color.b()
+            );
+        } else {
+            let _ = write!(
+                html,
+                "color: rgba({}, {}, {}, {:.3}); ",
+                color.r(),
+                color.g(),
+                color.b(),
+                f32::from(color.a()) / 255.0
+            );
+        }
+    }
+    if attrs.weight != Weight::NORMAL {
+        let _ = write!(html, "font-weight: {}; ", attrs.weight.0);
+    }
+    match attrs.style {
+        Style::Normal => {}
+        Style::Italic => html.push_str("font-style: italic; "),
+        Style::Oblique => html.push_str("font-style: oblique; "),
+    }
+    html.push_str("font-family: ");
+    match &attrs.family_owned {
+        FamilyOwned::Name(name) => {
+            html.push('\'');
+            escape_html(html, &name.replace('\'', "\\'"));
+            html.push('\'');
+        }
+        FamilyOwned::Serif => html.push_str("serif"),
+        FamilyOwned::SansSerif => html.push_str("sans-serif"),
+        FamilyOwned::Cursive => html.push_str("cursive"),
+        FamilyOwned::Fantasy => html.push_str("fantasy"),
+        FamilyOwned::Monospace => html.push_str("monospace"),
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = match rest.find(';') {
+            Some(end) if end <= 10 => end,
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let c_opt = match &rest[1..end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{A0}'),
+            entity => {
+                let code_opt = if let Some(hex) = entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                {
+                    u32::from_str_radix(hex, 16).ok()
+                } else if let Some(dec) = entity.strip_prefix('#') {
+                    dec.parse().ok()
+                } else {
+                    None
+                };
+                code_opt.and_then(char::from_u32)
+            }
+        };
+        match c_opt {
+            Some(c) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+pub(crate) fn parse_color(value: &str) -> Option<Color> {
+    let value = value.trim();
+    if let Some(hex) = value.strip_prefix('#') {
+        let digit = |i: usize| u8::from_str_radix(hex.get(i..i + 1)?, 16).ok();
+        let byte = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        return match hex.len() {
+            3 => Some(Color::rgb(digit(0)? * 17, digit(1)? * 17, digit(2)? * 17)),
+            6 => Some(Color::rgb(byte(0)?, byte(2)?, byte(4)?)),
+            8 => Some(Color::rgba(byte(0)?, byte(2)?, byte(4)?, byte(6)?)),
+            _ => None,
+        };
+    }
+
+    let args = value
+        .strip_prefix("rgba(")
+        .or_else(|| value.strip_prefix("rgb("))?
+        .strip_suffix(')')?;
+    let mut args = args.split(',').map(str::trim);
+    let mut component = || args.next()?.parse::<u8>().ok();
+    let (r, g, b) = (component()?, component()?, component()?);
+    let a = match args.next() {
+        Some(a) => (a.parse::<f32>().ok()?.clamp(0.0, 1.0) * 255.0 + 0.5) as u8,
+        None => 0xFF,
+    };
+    Some(Color::rgba(r, g, b, a))
+}
+
+pub(crate) fn parse_family(value: &str) -> FamilyOwned {
+    let first = value.split(',').next().unwrap_or_default().trim();
+    match first {
+        "serif" => FamilyOwned::Serif,
+        "sans-serif" => FamilyOwned::SansSerif,
+        "cursive" => FamilyOwned::Cursive,
+        "fantasy" => FamilyOwned::Fantasy,
+        "monospace" => FamilyOwned::Monospace,
+        _ => FamilyOwned::Name(
+            first
+                .trim_matches(|c| c == '\'' || c == '"')
+                .replace("\\'", "'"),
+        ),
+    }
+}
+
+/// Elements that start and end on their own lines
+const BLOCK_TAGS: [&str; 14] = [
+    "blockquote",
+    "div",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "tr",
+    "ul",
+];
+
+/// Elements without content, which are not closed
+const VOID_TAGS: [&str; 7] = ["br", "hr", "img", "input", "link", "meta", "wbr"];
+
+/// Elements with content that is not text
+const SKIP_TAGS: [&str; 4] = ["head", "script", "style", "title"];
+
+struct HtmlParser {
+    lines: Vec<Vec<RichSpan>>,
+    /// Open elements, with the attributes and whitespace mode outside of them
+    stack: Vec<(String, AttrsOwned, bool)>,
+    attrs: AttrsOwned,
+    pre: bool,
+    skip_depth: usize,
+    /// True if the last line was started by the end of a block, and has no content
+    block_break: bool,
+}
+
+impl HtmlParser {
+    fn line(&self) -> &Vec<RichSpan> {
+        self.lines.last().expect("no lines")
+    }
+
+    fn new_line(&mut self) {
+        self.lines.push(Vec::new());
+        self.block_break = false;
+    }
+
+    fn block_boundary(&mut self) {
+        if !self.line().is_empty() {
+            self.new_line();
+            self.block_break = true;
+        }
+    }
+
+    fn push(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.block_break = false;
+        let attrs = &self.attrs;
+        let line = self.lines.last_mut().expect("no lines");
+        match line.last_mut() {
+            Some(span) if &span.attrs == attrs => span.text.push_str(text),
+            _ => line.push(RichSpan {
+                text: text.to_string(),
+                attrs: attrs.clone(),
+            }),
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.pre {
+            for (i, part) in text.split('\n').enumerate() {
+                if i > 0 {
+                    self.new_line();
+                }
+                self.push(part.strip_suffix('\r').unwrap_or(part));
+            }
+            return;
+        }
+
+        // Collapse whitespace, and remove it from the start of lines
+        let mut collapsed = String::new();
+        let mut space = match self.line().last() {
+            Some(span) => span.text.ends_with(' '),
+            None => true,
+        };
+        for c in text.chars() {
+            if c.is_ascii_whitespace() {
+                if !space {
+                    collapsed.push(' ');
+                    space = true;
+                }
+            } else {
+                collapsed.push(c);
+                space = false;
+            }
+        }
+        self.push(&collapsed);
+    }
+
+    fn tag(&mut self, tag: &str) {
+        if let Some(name) = tag.strip_prefix('/') {
+            let name = name.trim().to_ascii_lowercase();
+            if SKIP_TAGS.contains(&name.as_str()) {
+                self.skip_depth = self.skip_depth.saturating_sub(1);
+                return;
+            }
+            // Close the element, and any unclosed elements inside of it
+            if let Some(i) = self.stack.iter().rposition(|(open, _, _)| *open == name) {
+                if let Some((_, attrs, pre)) = self.stack.drain(i..).next() {
+                    self.attrs = attrs;
+                    self.pre = pre;
+                }
+            }
+            if BLOCK_TAGS.contains(&name.as_str()) {
+                self.block_boundary();
+            }
+            return;
+        }
+
+        let self_closing = tag.ends_with('/');
+        let tag = tag.strip_suffix('/').unwrap_or(tag);
+        let (name, attributes) = tag
+            .split_once(|c: char| c.is_ascii_whitespace())
+            .unwrap_or((tag, ""));
+        let name = name.to_ascii_lowercase();
+        if name.starts_with('!') || name.starts_with('?') {
+            // Doctype or processing instruction
+            return;
+        }
+        if SKIP_TAGS.contains(&name.as_str()) {
+            self.skip_depth += 1;
+            return;
+        }
+        if name == "br" {
+            self.new_line();
+            return;
+        }
+        if BLOCK_TAGS.contains(&name.as_str()) {
+            self.block_boundary();
+        }
+        if VOID_TAGS.contains(&name.as_str()) || self_closing {
+            return;
+        }
+
+        self.stack
+            .push((name.clone(), self.attrs.clone(), self.pre));
+        match name.as_str() {
+            "b" | "strong" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.attrs.weight = Weight::BOLD;
+            }
+            "i" | "em" | "cite" | "var" => self.attrs.style = Style::Italic,
+            "code" | "kbd" | "samp" | "tt" => self.attrs.family_owned = FamilyOwned::Monospace,
+            "pre" => {
+                self.attrs.family_owned = FamilyOwned::Monospace;
+                self.pre = true;
+            }
+            _ => {}
+        }
+
+        for (key, value) in parse_attributes(attributes) {
+            match key.as_str() {
+                "style" => self.style(&value),
+                "color" if name == "font" => {
+                    if let Some(color) = parse_color(&value) {
+                        self.attrs.color_opt = Some(color);
+                    }
+                }
+                "face" if name == "font" => self.attrs.family_owned = parse_family(&value),
+                _ => {}
+            }
+        }
+    }
+
+    fn style(&mut self, style: &str) {
+        for declaration in style.split(';') {
+            let (property, value) = match declaration.split_once(':') {
+                Some(some) => some,
+                None => continue,
+            };
+            let value = value.trim();
+            match property.trim().to_ascii_lowercase().as_str() {
+                "color" => {
+                    if let Some(color) = parse_color(value) {
+                        self.attrs.color_opt = Some(color);
+                    }
+                }
+                "font-weight" => {
+                    self.attrs.weight = match value {
+                        "normal" => Weight::NORMAL,
+                        "bold" | "bolder" => Weight::BOLD,
+                        "lighter" => Weight::LIGHT,
+                        _ => match value.parse() {
+                            Ok(weight) => Weight(weight),
+                            Err(_) => continue,
+                        },
+                    };
+                }
+                "font-style" => {
+                    self.attrs.style = match value {
+                        "normal" => Style::Normal,
+                        "italic" => Style::Italic,
+                        _ if value.starts_with("oblique") => Style::Oblique,
+                        _ => continue,
+                    };
+                }
+                "font-family" => self.attrs.family_owned = parse_family(value),
+                "white-space" => self.pre = value.starts_with("pre"),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Parse the attributes of an element, with names in lowercase and entities decoded
+fn parse_attributes(mut attributes: &str) -> Vec<(String, String)> {
+    let mut parsed = Vec::new();
+    loop {
+        attributes = attributes.trim_start();
+        let name_end = attributes
+            .find(|c: char| c == '=' || c.is_ascii_whitespace())
+            .unwrap_or(attributes.len());
+        if name_end == 0 {
+            break;
+        }
+        let name = attributes[..name_end].to_ascii_lowercase();
+        attributes = attributes[name_end..].trim_start();
+
+        let value = if let Some(value) = attributes.strip_prefix('=') {
+            let value = value.trim_start();
+            let (value, rest) = match value.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let value = &value[1..];
+                    let end = value.find(quote).unwrap_or(value.len());
+                    (&value[..end], value.get(end + 1..).unwrap_or_default())
+                }
+                _ => {
+                    let end = value
+                        .find(|c: char| c.is_ascii_whitespace())
+                        .unwrap_or(value.len());
+                    (&value[..end], &value[end..])
+                }
+            };
+            attributes = rest;
+            decode_entities(value)
+        } else {
+            String::new()
+        };
+        parsed.push((name, value));
+    }
+    parsed
+}
+
+/// Get the index of the `>` ending a tag, skipping those in quoted attribute values
+fn tag_end(tag: &str) -> Option<usize> {
+    let mut quote_opt = None;
+    let mut after_equals = false;
+    for (i, c) in tag.char_indices() {
+        match quote_opt {
+            Some(quote) => {
+                if c == quote {
+                    quote_opt = None;
+                }
+            }
+            None if c == '>' => return Some(i),
+            None if after_equals && (c == '"' || c == '\'') => quote_opt = Some(c),
+            None => {}
+        }
+        if !c.is_ascii_whitespace() {
+            after_equals = c == '=' && quote_opt.is_none();
+        }
+    }
+    None
+}
+
+#[test]
+fn test_rich_text_html() {
+    use crate::Family;
+
+    let attrs = Attrs::new().family(Family::Monospace);
+    let rich_text = RichText {
+        lines: vec![
+            vec![
+                RichSpan {
+                    text: "fn".to_string(),
+                    attrs: AttrsOwned::new(attrs.color(Color::rgb(0xCC, 0x99, 0xCC))),
+                },
+                RichSpan {
+                    text: " main() <&> ".to_string(),
+                    attrs: AttrsOwned::new(attrs),
+                },
+            ],
+            Vec::new(),
+            vec![RichSpan {
+                text: "  'quoted'".to_string(),
+                attrs: AttrsOwned::new(
+                    attrs
+                        .family(Family::Name("Fira Mono"))
+                        .weight(Weight::BOLD)
+                        .style(Style::Italic)
+                        .color(Color::rgba(1, 2, 3, 0x80)),
+                ),
+            }],
+        ],
+    };
+    assert_eq!(
+        RichText::from_html(&rich_text.to_html(), Attrs::new()),
+        rich_text
+    );
+
+    let html = "<meta charset='utf-8'><div style=\"color: #d4d4d4;font-family: Consolas, 'Courier New', monospace;white-space: pre;\"><div><span style=\"color: #569cd6;\">let</span>&nbsp;x</div><div><br></div><div>  y</div></div>";
+    let rich_text = RichText::from_html(html, Attrs::new());
+    assert_eq!(rich_text.text(), "let\u{A0}x\n\n  y");
+    assert_eq!(
+        rich_text.lines[0][0].attrs.color_opt,
+        Some(Color::rgb(0x56, 0x9c, 0xd6))
+    );
+    assert_eq!(
+        rich_text.lines[0][0].attrs.family_owned,
+        FamilyOwned::Name("Consolas".to_string())
+    );
+
+    // Quoted attribute values may contain `>`
+    let html = "<span title=\"a > b\" style='color: #ff0000' data-x = \"'>'\">red</span> don't";
+    let rich_text = RichText::from_html(html, Attrs::new());
+    assert_eq!(rich_text.text(), "red don't");
+    assert_eq!(
+        rich_text.lines[0][0].attrs.color_opt,
+        Some(Color::rgb(0xFF, 0, 0))
+    );
+    assert_eq!(rich_text.lines[0][1].text, " don't");
+}
@@ -1,7 +1,7 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 #[cfg(not(feature = "std"))]
-use alloc::vec::Vec;
+use alloc::{vec, vec::Vec};
 use core::cmp::{max, min};
 use core::mem;
 use core::ops::Range;
@@ -9,7 +9,10 @@ use unicode_script::{Script, UnicodeScript};
 use unicode_segmentation::UnicodeSegmentation;
 
 use crate::fallback::FontFallbackIter;
-use crate::{Align, AttrsList, CacheKey, Color, Font, FontSystem, LayoutGlyph, LayoutLine, Wrap};
+use crate::{
+    Align, AttrsList, CacheKey, Color, Font, FontSystem, InlineObject, LayoutGlyph, LayoutLine,
+    LineBox, LineExtents, Synthesis, Wrap,
+};
 
 fn shape_fallback(
     font: &Font,
@@ -18,10 +21,14 @@ fn shape_fallback(
     start_run: usize,
     end_run: usize,
     span_rtl: bool,
+    synthesis: Synthesis,
 ) -> (Vec<ShapeGlyph>, Vec<usize>) {
     let run = &line[start_run..end_run];
 
     let font_scale = font.rustybuzz().units_per_em() as f32;
+    let ascent = font.rustybuzz().ascender() as f32 / font_scale;
+    let descent = -font.rustybuzz().descender() as f32 / font_scale;
+    let line_gap = font.rustybuzz().line_gap() as f32 / font_scale;
 
     let mut buffer = rustybuzz::UnicodeBuffer::new();
     buffer.set_direction(if span_rtl {
@@ -42,7 +49,10 @@ fn shape_fallback(
     let mut missing = Vec::new();
     let mut glyphs = Vec::with_capacity(glyph_infos.len());
     for (info, pos) in glyph_infos.iter().zip(glyph_positions.iter()) {
-        let x_advance = pos.x_advance as f32 / font_scale;
+        let mut x_advance = pos.x_advance as f32 / font_scale;
+        if synthesis.bold && x_advance != 0.0 {
+            x_advance += Synthesis::EMBOLDEN_STRENGTH;
+        }
         let y_advance = pos.y_advance as f32 / font_scale;
         let x_offset = pos.x_offset as f32 / font_scale;
         let y_offset = pos.y_offset as f32 / font_scale;
@@ -61,11 +71,16 @@ fn shape_fallback(
             y_advance,
             x_offset,
             y_offset,
+            ascent,
+            descent,
+            line_gap,
             font_id: font.id(),
             glyph_id: info.glyph_id.try_into().expect("failed to cast glyph ID"),
+            synthesis,
             //TODO: color should not be related to shaping
             color_opt: attrs.color_opt,
             metadata: attrs.metadata,
+            object_opt: None,
         });
     }
 
@@ -97,6 +112,45 @@ fn shape_fallback(
     (glyphs, missing)
 }
 
+/// Create one glyph with the size of the object for each grapheme of a run of an inline object
+fn shape_object(
+    line: &str,
+    attrs_list: &AttrsList,
+    start_run: usize,
+    end_run: usize,
+    span_rtl: bool,
+    object: InlineObject,
+) -> Vec<ShapeGlyph> {
+    let attrs = attrs_list.get_span(start_run);
+    let mut glyphs: Vec<ShapeGlyph> = line[start_run..end_run]
+        .grapheme_indices(true)
+        .map(|(egc_i, egc)| ShapeGlyph {
+            start: start_run + egc_i,
+            end: start_run + egc_i + egc.len(),
+            x_advance: 0.0,
+            y_advance: 0.0,
+            x_offset: 0.0,
+            y_offset: 0.0,
+            ascent: 0.0,
+            descent: 0.0,
+            line_gap: 0.0,
+            font_id: fontdb::ID::dummy(),
+            glyph_id: 0,
+            synthesis: Synthesis::new(),
+            color_opt: attrs.color_opt,
+            metadata: attrs.metadata,
+            object_opt: Some(object),
+        })
+        .collect();
+
+    // Shaped RTL runs are in visual order
+    if span_rtl {
+        glyphs.reverse();
+    }
+
+    glyphs
+}
+
 fn shape_run(
     font_system: &mut FontSystem,
     line: &str,
@@ -105,6 +159,10 @@ fn shape_run(
     end_run: usize,
     span_rtl: bool,
 ) -> Vec<ShapeGlyph> {
+    if let Some(object) = attrs_list.get_span(start_run).object_opt {
+        return shape_object(line, attrs_list, start_run, end_run, span_rtl, object);
+    }
+
     //TODO: use smallvec?
     let mut scripts = Vec::new();
     for c in line[start_run..end_run].chars() {
@@ -129,8 +187,10 @@ fn shape_run(
 
     let font = font_iter.next().expect("no default font found");
 
-    let (mut glyphs, mut missing) =
-        shape_fallback(&font, line, attrs_list, start_run, end_run, span_rtl);
+    let synthesis = font_iter.synthesis(&font, &attrs);
+    let (mut glyphs, mut missing) = shape_fallback(
+        &font, line, attrs_list, start_run, end_run, span_rtl, synthesis,
+    );
 
     //TODO: improve performance!
     while !missing.is_empty() {
@@ -143,8 +203,10 @@ fn shape_run(
             "Evaluating fallback with font '{}'",
             font_iter.face_name(font.id())
         );
-        let (mut fb_glyphs, fb_missing) =
-            shape_fallback(&font, line, attrs_list, start_run, end_run, span_rtl);
+        let synthesis = font_iter.synthesis(&font, &attrs);
+        let (mut fb_glyphs, fb_missing) = shape_fallback(
+            &font, line, attrs_list, start_run, end_run, span_rtl, synthesis,
+        );
 
         // Insert all matching glyphs
         let mut fb_i = 0;
@@ -221,13 +283,55 @@ pub struct ShapeGlyph {
     pub y_advance: f32,
     pub x_offset: f32,
     pub y_offset: f32,
+    /// Ascent of the font of the glyph, see [`Self::ascent`]
+    pub ascent: f32,
+    /// Descent of the font of the glyph, see [`Self::descent`]
+    pub descent: f32,
+    /// Line gap of the font of the glyph, see [`Self::line_gap`]
+    pub line_gap: f32,
     pub font_id: fontdb::ID,
     pub glyph_id: u16,
+    /// Styles that are synthesized because the font does not have them
+    pub synthesis: Synthesis,
     pub color_opt: Option<Color>,
     pub metadata: usize,
+    /// The inline object of the glyph, which has a width in pixels instead of `x_advance`
+    pub object_opt: Option<InlineObject>,
 }
 
 impl ShapeGlyph {
+    /// Get the width of the glyph in pixels
+    pub fn width(&self, font_size: f32) -> f32 {
+        match self.object_opt {
+            Some(object) => object.width,
+            None => font_size * self.x_advance,
+        }
+    }
+
+    /// Get the height of the glyph above the baseline in pixels, which is the ascent of its font
+    pub fn ascent(&self, font_size: f32) -> f32 {
+        match self.object_opt {
+            Some(object) => object.ascent,
+            None => font_size * self.ascent,
+        }
+    }
+
+    /// Get the depth of the glyph below the baseline in pixels, which is the descent of its font
+    pub fn descent(&self, font_size: f32) -> f32 {
+        match self.object_opt {
+            Some(object) => object.descent,
+            None => font_size * self.descent,
+        }
+    }
+
+    /// Get the recommended space between lines of the font of the glyph in pixels
+    pub fn line_gap(&self, font_size: f32) -> f32 {
+        match self.object_opt {
+            Some(_) => 0.0,
+            None => font_size * self.line_gap,
+        }
+    }
+
     fn layout(
         &self,
         font_size: f32,
@@ -239,12 +343,13 @@ impl ShapeGlyph {
         let x_offset = font_size * self.x_offset;
         let y_offset = font_size * self.y_offset;
 
-        let (cache_key, x_int, y_int) = CacheKey::new(
+        let (mut cache_key, x_int, y_int) = CacheKey::new(
             self.font_id,
             self.glyph_id,
             font_size,
             (x + x_offset, y - y_offset),
         );
+        cache_key.synthesis = self.synthesis;
         LayoutGlyph {
             start: self.start,
             end: self.end,
@@ -256,8 +361,10 @@ impl ShapeGlyph {
             y_offset,
             x_int,
             y_int,
+            synthesis: self.synthesis,
             color_opt: self.color_opt,
             metadata: self.metadata,
+            object_opt: self.object_opt,
         }
     }
 }
@@ -268,6 +375,8 @@ pub struct ShapeWord {
     pub glyphs: Vec<ShapeGlyph>,
     pub x_advance: f32,
     pub y_advance: f32,
+    /// Width in pixels of the inline objects in the word, which are not included in `x_advance`
+    pub object_width: f32,
 }
 
 impl ShapeWord {
@@ -324,9 +433,13 @@ impl ShapeWord {
 
         let mut x_advance = 0.0;
         let mut y_advance = 0.0;
+        let mut object_width = 0.0;
         for glyph in &glyphs {
             x_advance += glyph.x_advance;
             y_advance += glyph.y_advance;
+            if let Some(object) = glyph.object_opt {
+                object_width += object.width;
+            }
         }
 
         Self {
@@ -334,8 +447,14 @@ impl ShapeWord {
             glyphs,
             x_advance,
             y_advance,
+            object_width,
         }
     }
+
+    /// Get the width of the word in pixels
+    pub fn width(&self, font_size: f32) -> f32 {
+        font_size * self.x_advance + self.object_width
+    }
 }
 
 /// A shaped span (for bidirectional processing)
@@ -615,6 +734,52 @@ impl ShapeLine {
         line_width: f32,
         wrap: Wrap,
         align: Option<Align>,
+    ) -> Vec<LayoutLine> {
+        self.layout_to_boxes(font_size, wrap, align, |_, _| LineBox::new(0.0, line_width))
+    }
+
+    /// Get the extents of the fonts of the glyphs in a visual line
+    fn visual_line_extents(&self, visual_line: &VisualLine, font_size: f32) -> LineExtents {
+        let mut extents = LineExtents::default();
+        for (span_index, (starting_word, starting_glyph), (ending_word, ending_glyph)) in
+            visual_line.ranges.iter()
+        {
+            let span = &self.spans[*span_index];
+            for i in *starting_word..*ending_word + 1 {
+                if let Some(word) = span.words.get(i) {
+                    let (g1, g2) = if starting_word == ending_word {
+                        (*starting_glyph, *ending_glyph)
+                    } else if i == *starting_word {
+                        (*starting_glyph, word.glyphs.len())
+                    } else if i == *ending_word {
+                        (0, *ending_glyph)
+                    } else {
+                        (0, word.glyphs.len())
+                    };
+                    for glyph in &word.glyphs[g1..g2] {
+                        extents.max_ascent = extents.max_ascent.max(glyph.ascent(font_size));
+                        extents.max_descent = extents.max_descent.max(glyph.descent(font_size));
+                        extents.max_line_gap = extents.max_line_gap.max(glyph.line_gap(font_size));
+                    }
+                }
+            }
+        }
+        extents
+    }
+
+    /// Lay out the line with its own horizontal extent for each visual line, given by calling
+    /// `line_box` with the index of the visual line and the extents of the visual line above it
+    ///
+    /// Words are wrapped to the width of the box of each line, and aligned within it. The extents
+    /// of the line above, which is `None` for the first line, give its height with
+    /// [`crate::Metrics::extents_line_height`], so that the box of a line can depend on its
+    /// position.
+    pub fn layout_to_boxes<F: FnMut(usize, Option<LineExtents>) -> LineBox>(
+        &self,
+        font_size: f32,
+        wrap: Wrap,
+        align: Option<Align>,
+        mut line_box: F,
     ) -> Vec<LayoutLine> {
         let mut layout_lines = Vec::with_capacity(1);
 
@@ -651,7 +816,27 @@ impl ShapeLine {
             vl.spaces += number_of_blanks;
         }
 
-        let start_x = if self.rtl { line_width } else { 0.0 };
+        // Start a new visual line, returning the width of the box of the new line
+        fn push_visual_line<F: FnMut(usize, Option<LineExtents>) -> LineBox>(
+            visual_lines: &mut Vec<VisualLine>,
+            vl: &mut VisualLine,
+            boxes: &mut Vec<LineBox>,
+            line_box: &mut F,
+            shape: &ShapeLine,
+            font_size: f32,
+        ) -> f32 {
+            // Empty visual lines are not laid out, so they do not take a box
+            if !vl.ranges.is_empty() {
+                let extents = shape.visual_line_extents(vl, font_size);
+                visual_lines.push(mem::take(vl));
+                boxes.push(line_box(visual_lines.len(), Some(extents)));
+            }
+            boxes[visual_lines.len()].w
+        }
+
+        // The box of each visual line, and of the line after the last one
+        let mut boxes = vec![line_box(0, None)];
+        let mut line_width = boxes[0].w;
         let mut x;
         let mut y;
 
@@ -678,7 +863,7 @@ impl ShapeLine {
                     // incongruent directions
                     let mut fitting_start = (span.words.len(), 0);
                     for (i, word) in span.words.iter().enumerate().rev() {
-                        let word_width = font_size * word.x_advance;
+                        let word_width = word.width(font_size);
                         if fit_x - word_width >= 0. {
                             // fits
                             fit_x -= word_width;
@@ -689,7 +874,7 @@ impl ShapeLine {
                             continue;
                         } else if wrap == Wrap::Glyph {
                             for (glyph_i, glyph) in word.glyphs.iter().enumerate().rev() {
-                                let glyph_width = font_size * glyph.x_advance;
+                                let glyph_width = glyph.width(font_size);
                                 if fit_x - glyph_width >= 0. {
                                     fit_x -= glyph_width;
                                     word_range_width += glyph_width;
@@ -703,8 +888,14 @@ impl ShapeLine {
                                         word_range_width,
                                         number_of_blanks,
                                     );
-                                    visual_lines.push(current_visual_line);
-                                    current_visual_line = VisualLine::default();
+                                    line_width = push_visual_line(
+                                        &mut visual_lines,
+                                        &mut current_visual_line,
+                                        &mut boxes,
+                                        &mut line_box,
+                                        self,
+                                        font_size,
+                                    );
 
                                     number_of_blanks = 0;
                                     fit_x = line_width - glyph_width;
@@ -719,8 +910,7 @@ impl ShapeLine {
                                 // Current word causing a wrap is not whitespace, so we ignore the
                                 // previous word if it's a whitespace
                                 if previous_word.blank {
-                                    trailing_space_width =
-                                        Some(previous_word.x_advance * font_size);
+                                    trailing_space_width = Some(previous_word.width(font_size));
                                     number_of_blanks = number_of_blanks.saturating_sub(1);
                                 }
                             }
@@ -743,8 +933,14 @@ impl ShapeLine {
                                     number_of_blanks,
                                 );
                             }
-                            visual_lines.push(current_visual_line);
-                            current_visual_line = VisualLine::default();
+                            line_width = push_visual_line(
+                                &mut visual_lines,
+                                &mut current_visual_line,
+                                &mut boxes,
+                                &mut line_box,
+                                self,
+                                font_size,
+                            );
 
                             number_of_blanks = 0;
                             if word.blank {
@@ -770,7 +966,7 @@ impl ShapeLine {
                     // congruent direction
                     let mut fitting_start = (0, 0);
                     for (i, word) in span.words.iter().enumerate() {
-                        let word_width = font_size * word.x_advance;
+                        let word_width = word.width(font_size);
                         if fit_x - word_width >= 0. {
                             // fits
                             fit_x -= word_width;
@@ -781,7 +977,7 @@ impl ShapeLine {
                             continue;
                         } else if wrap == Wrap::Glyph {
                             for (glyph_i, glyph) in word.glyphs.iter().enumerate() {
-                                let glyph_width = font_size * glyph.x_advance;
+                                let glyph_width = glyph.width(font_size);
                                 if fit_x - glyph_width >= 0. {
                                     fit_x -= glyph_width;
                                     word_range_width += glyph_width;
@@ -795,8 +991,14 @@ impl ShapeLine {
                                         word_range_width,
                                         number_of_blanks,
                                     );
-                                    visual_lines.push(current_visual_line);
-                                    current_visual_line = VisualLine::default();
+                                    line_width = push_visual_line(
+                                        &mut visual_lines,
+                                        &mut current_visual_line,
+                                        &mut boxes,
+                                        &mut line_box,
+                                        self,
+                                        font_size,
+                                    );
 
                                     number_of_blanks = 0;
                                     fit_x = line_width - glyph_width;
@@ -812,8 +1014,7 @@ impl ShapeLine {
                                     // Current word causing a wrap is not whitespace, so we ignore the
                                     // previous word if it's a whitespace
                                     if previous_word.blank {
-                                        trailing_space_width =
-                                            Some(previous_word.x_advance * font_size);
+                                        trailing_space_width = Some(previous_word.width(font_size));
                                         number_of_blanks = number_of_blanks.saturating_sub(1);
                                     }
                                 }
@@ -837,8 +1038,14 @@ impl ShapeLine {
                                     number_of_blanks,
                                 );
                             }
-                            visual_lines.push(current_visual_line);
-                            current_visual_line = VisualLine::default();
+                            line_width = push_visual_line(
+                                &mut visual_lines,
+                                &mut current_visual_line,
+                                &mut boxes,
+                                &mut line_box,
+                                self,
+                                font_size,
+                            );
                             number_of_blanks = 0;
 
                             if word.blank {
@@ -874,9 +1081,19 @@ impl ShapeLine {
             if visual_line.ranges.is_empty() {
                 continue;
             }
+            let LineExtents {
+                max_ascent,
+                max_descent,
+                max_line_gap,
+            } = self.visual_line_extents(visual_line, font_size);
+
             let new_order = self.reorder(&visual_line.ranges);
             let mut glyphs = Vec::with_capacity(1);
-            x = start_x;
+            let LineBox {
+                x: box_x,
+                w: line_width,
+            } = boxes[index];
+            x = if self.rtl { box_x + line_width } else { box_x };
             y = 0.;
             let alignment_correction = match (align, self.rtl) {
                 (Align::Left, true) => line_width - visual_line.w,
@@ -911,7 +1128,7 @@ impl ShapeLine {
                                 [*starting_glyph..*ending_glyph]
                                 .iter()
                             {
-                                let x_advance = font_size * glyph.x_advance;
+                                let x_advance = glyph.width(font_size);
                                 let y_advance = font_size * glyph.y_advance;
                                 x -= x_advance;
                                 if word_blank && align == Align::Justified {
@@ -942,7 +1159,7 @@ impl ShapeLine {
 
                                     let word_blank = word.blank;
                                     for glyph in &word.glyphs[g1..g2] {
-                                        let x_advance = font_size * glyph.x_advance;
+                                        let x_advance = glyph.width(font_size);
                                         let y_advance = font_size * glyph.y_advance;
                                         x -= x_advance;
                                         if word_blank && align == Align::Justified {
@@ -986,7 +1203,7 @@ impl ShapeLine {
                                 [*starting_glyph..*ending_glyph]
                                 .iter()
                             {
-                                let x_advance = font_size * glyph.x_advance;
+                                let x_advance = glyph.width(font_size);
                                 let y_advance = font_size * glyph.y_advance;
                                 if word_blank && align == Align::Justified {
                                     glyphs.push(glyph.layout(
@@ -1017,7 +1234,7 @@ impl ShapeLine {
 
                                     let word_blank = word.blank;
                                     for glyph in &word.glyphs[g1..g2] {
-                                        let x_advance = font_size * glyph.x_advance;
+                                        let x_advance = glyph.width(font_size);
                                         let y_advance = font_size * glyph.y_advance;
                                         if word_blank && align == Align::Justified {
                                             glyphs.push(glyph.layout(
@@ -1046,7 +1263,14 @@ impl ShapeLine {
             let mut glyphs_swap = Vec::new();
             mem::swap(&mut glyphs, &mut glyphs_swap);
             layout_lines.push(LayoutLine {
-                w: if self.rtl { start_x - x } else { x },
+                w: if self.rtl {
+                    box_x + line_width - x
+                } else {
+                    x - box_x
+                },
+                max_ascent,
+                max_descent,
+                max_line_gap,
                 glyphs: glyphs_swap,
             });
             push_line = false;
@@ -1055,6 +1279,9 @@ impl ShapeLine {
         if push_line {
             layout_lines.push(LayoutLine {
                 w: 0.0,
+                max_ascent: 0.0,
+                max_descent: 0.0,
+                max_line_gap: 0.0,
                 glyphs: Default::default(),
             });
         }
@@ -1062,3 +1289,75 @@ impl ShapeLine {
         layout_lines
     }
 }
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_inline_object() {
+    use crate::Attrs;
+
+    let mut font_system = FontSystem::new();
+    let attrs = Attrs::new();
+    let object = InlineObject::new(7, 50.0, 12.0, 4.0);
+    let text = "a \u{FFFC} b";
+    let mut attrs_list = AttrsList::new(attrs);
+    attrs_list.add_span(2..5, attrs.object(object));
+    let shape = ShapeLine::new(&mut font_system, text, &attrs_list);
+
+    let layout = shape.layout(16.0, 1000.0, Wrap::Word, None);
+    assert_eq!(layout.len(), 1);
+    let glyph = layout[0]
+        .glyphs
+        .iter()
+        .find(|glyph| glyph.object_opt.is_some())
+        .expect("no object glyph");
+    assert_eq!((glyph.start, glyph.end), (2, 5));
+    assert_eq!(glyph.w, 50.0);
+    assert_eq!(glyph.object_opt, Some(object));
+
+    // The object wraps like a word
+    let layout = shape.layout(16.0, 40.0, Wrap::Word, None);
+    assert_eq!(layout.len(), 3);
+    assert!(layout[1]
+        .glyphs
+        .iter()
+        .any(|glyph| glyph.object_opt.is_some()));
+}
+
+#[test]
+#[ignore = "needs a system font family without bold or italic faces"]
+fn test_synthesis() {
+    use crate::{Attrs, Family, Style, Weight};
+
+    let mut font_system = FontSystem::new();
+    // A family without bold or italic faces
+    let family = font_system
+        .db()
+        .faces()
+        .filter_map(|face| face.families.first().map(|(name, _)| name.clone()))
+        .find(|name| {
+            font_system.db().faces().all(|face| {
+                face.families.first().map(|(n, _)| n) != Some(name)
+                    || (face.weight.0 < 600 && face.style == Style::Normal)
+            })
+        });
+    let family = family.expect("no family without bold or italic faces");
+
+    let regular = Attrs::new().family(Family::Name(&family));
+    let shape = |font_system: &mut FontSystem, attrs: Attrs| {
+        let attrs_list = AttrsList::new(attrs);
+        let shape = ShapeLine::new(font_system, "a", &attrs_list);
+        let mut layout = shape.layout(16.0, 1000.0, Wrap::Word, None);
+        layout.remove(0).glyphs.remove(0)
+    };
+    let glyph = shape(&mut font_system, regular);
+    assert_eq!(glyph.synthesis, Synthesis::new());
+
+    let bold = shape(
+        &mut font_system,
+        regular.weight(Weight::BOLD).style(Style::Italic),
+    );
+    assert_eq!(bold.cache_key.font_id, glyph.cache_key.font_id);
+    assert!(bold.synthesis.bold && bold.synthesis.italic);
+    assert_eq!(bold.cache_key.synthesis, bold.synthesis);
+    assert!(bold.w > glyph.w);
+}
@@ -8,17 +8,211 @@ use alloc::vec::Vec;
 use std::collections::HashMap as Map;
 use swash::scale::{image::Content, ScaleContext};
 use swash::scale::{Render, Source, StrikeWith};
-use swash::zeno::{Format, Vector};
+use swash::zeno::{Angle, Format, Transform, Vector};
 
-use crate::{CacheKey, Color, FontSystem};
+use crate::{CacheKey, Color, FontSystem, Synthesis};
 
 pub use swash::scale::image::{Content as SwashContent, Image as SwashImage};
 pub use swash::zeno::{Command, Placement};
 
+/// Format that glyph outlines are rasterized with
+///
+/// Subpixel formats render a mask with the coverage of the red, green and blue channels, in that
+/// order, followed by an unused byte for each pixel. Color glyphs are always rendered in color.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum SubpixelFormat {
+    /// Grayscale antialiasing, with one byte of coverage for each pixel
+    #[default]
+    None,
+    /// Subpixel antialiasing for displays with red, green and blue subpixels from left to right
+    Rgb,
+    /// Subpixel antialiasing for displays with blue, green and red subpixels from left to right
+    Bgr,
+}
+
+impl SubpixelFormat {
+    fn zeno_format(self) -> Format {
+        match self {
+            Self::None => Format::Alpha,
+            Self::Rgb => Format::Subpixel,
+            // Sample red on the right and blue on the left, keeping the channel order
+            Self::Bgr => Format::CustomSubpixel([0.3, 0.0, -0.3]),
+        }
+    }
+}
+
+/// Order of the sources that glyph images are rendered from, using the first source that a font
+/// has for a glyph
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum GlyphSources {
+    /// Color outlines, then color bitmaps, then outlines
+    #[default]
+    ColorOutlineFirst,
+    /// Color bitmaps, then color outlines, then outlines
+    ColorBitmapFirst,
+    /// Only outlines, so color glyphs are rendered as masks if they have outlines
+    OutlineOnly,
+}
+
+impl GlyphSources {
+    fn sources(self) -> &'static [Source] {
+        // Color outlines use the first palette, and color bitmaps use the best fitting strike
+        match self {
+            Self::ColorOutlineFirst => &[
+                Source::ColorOutline(0),
+                Source::ColorBitmap(StrikeWith::BestFit),
+                Source::Outline,
+            ],
+            Self::ColorBitmapFirst => &[
+                Source::ColorBitmap(StrikeWith::BestFit),
+                Source::ColorOutline(0),
+                Source::Outline,
+            ],
+            Self::OutlineOnly => &[Source::Outline],
+        }
+    }
+}
+
+/// Options for rasterizing glyphs with a [`SwashCache`]
+///
+/// The options are part of the key of cached images, so images rasterized with other options
+/// are not reused.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct RenderOptions {
+    /// Hint glyph outlines to the pixel grid
+    pub hinting: bool,
+    /// Embolden glyphs with [`crate::Synthesis::bold`] in their cache key
+    ///
+    /// This only affects rasterization. The advances of those glyphs are widened by
+    /// [`crate::Synthesis::EMBOLDEN_STRENGTH`] when they are shaped either way, so turning it off
+    /// does not change the layout, and leaves the glyphs a little further apart.
+    pub synthetic_bold: bool,
+    /// Skew glyphs with [`crate::Synthesis::italic`] in their cache key
+    pub synthetic_italic: bool,
+    // The gamma and contrast are kept as bits, so that options can be used as keys
+    gamma_bits: u32,
+    contrast_bits: u32,
+    /// Order of the sources of glyph images
+    pub sources: GlyphSources,
+}
+
+impl RenderOptions {
+    /// Create new options with hinting, synthetic styles, no gamma or contrast adjustment, and
+    /// color glyphs from color outlines first
+    pub const fn new() -> Self {
+        Self {
+            hinting: true,
+            synthetic_bold: true,
+            synthetic_italic: true,
+            gamma_bits: 0x3F80_0000, // 1.0
+            contrast_bits: 0,        // 0.0
+            sources: GlyphSources::ColorOutlineFirst,
+        }
+    }
+
+    /// Set [`Self::hinting`]
+    pub fn hinting(mut self, hinting: bool) -> Self {
+        self.hinting = hinting;
+        self
+    }
+
+    /// Set [`Self::synthetic_bold`]
+    pub fn synthetic_bold(mut self, synthetic_bold: bool) -> Self {
+        self.synthetic_bold = synthetic_bold;
+        self
+    }
+
+    /// Set [`Self::synthetic_italic`]
+    pub fn synthetic_italic(mut self, synthetic_italic: bool) -> Self {
+        self.synthetic_italic = synthetic_italic;
+        self
+    }
+
+    /// Get the gamma of the coverage of masks, see [`Self::gamma`]
+    pub fn get_gamma(&self) -> f32 {
+        f32::from_bits(self.gamma_bits)
+    }
+
+    /// Get the contrast of the coverage of masks, see [`Self::contrast`]
+    pub fn get_contrast(&self) -> f32 {
+        f32::from_bits(self.contrast_bits)
+    }
+
+    /// Set the gamma of the coverage of masks
+    ///
+    /// Coverage is raised to the power of `1 / gamma`, so a gamma above 1.0 makes text heavier.
+    pub fn gamma(mut self, gamma: f32) -> Self {
+        self.gamma_bits = gamma.to_bits();
+        self
+    }
+
+    /// Set the contrast of the coverage of masks
+    ///
+    /// A contrast between 0.0 and 1.0 makes partially covered pixels darker, and is applied
+    /// before gamma.
+    pub fn contrast(mut self, contrast: f32) -> Self {
+        self.contrast_bits = contrast.to_bits();
+        self
+    }
+
+    /// Set [`Self::sources`]
+    pub fn sources(mut self, sources: GlyphSources) -> Self {
+        self.sources = sources;
+        self
+    }
+
+    /// Get the strength of emboldening and the skew that synthesize the styles in the synthesis of
+    /// `cache_key`
+    fn synthesis(&self, cache_key: CacheKey) -> (f32, Option<Transform>) {
+        let embolden = if cache_key.synthesis.bold && self.synthetic_bold {
+            f32::from_bits(cache_key.font_size_bits) * Synthesis::EMBOLDEN_STRENGTH
+        } else {
+            0.0
+        };
+        let transform = if cache_key.synthesis.italic && self.synthetic_italic {
+            Some(Transform::skew(
+                Angle::from_degrees(Synthesis::SKEW_DEGREES),
+                Angle::from_degrees(0.0),
+            ))
+        } else {
+            None
+        };
+        (embolden, transform)
+    }
+
+    /// Adjust the coverage of a mask with the gamma and contrast
+    fn adjust(&self, data: &mut [u8]) {
+        let gamma = self.get_gamma();
+        let contrast = self.get_contrast();
+        if gamma == 1.0 && contrast == 0.0 {
+            return;
+        }
+
+        let mut table = [0; 256];
+        for (i, value) in table.iter_mut().enumerate() {
+            let coverage = i as f32 / 255.0;
+            let coverage = coverage + contrast * coverage * (1.0 - coverage);
+            let coverage = libm::powf(coverage.clamp(0.0, 1.0), 1.0 / gamma);
+            *value = libm::roundf(coverage * 255.0) as u8;
+        }
+        for byte in data.iter_mut() {
+            *byte = table[*byte as usize];
+        }
+    }
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 fn swash_image(
     font_system: &mut FontSystem,
     context: &mut ScaleContext,
     cache_key: CacheKey,
+    format: SubpixelFormat,
+    options: RenderOptions,
 ) -> Option<SwashImage> {
     let font = match font_system.get_font(cache_key.font_id) {
         Some(some) => some,
@@ -28,38 +222,44 @@ fn swash_image(
         }
     };
 
+    let font_size = f32::from_bits(cache_key.font_size_bits);
+
     // Build the scaler
     let mut scaler = context
         .builder(font.as_swash())
-        .size(f32::from_bits(cache_key.font_size_bits))
-        .hint(true)
+        .size(font_size)
+        .hint(options.hinting)
         .build();
 
     // Compute the fractional offset-- you'll likely want to quantize this
     // in a real renderer
     let offset = Vector::new(cache_key.x_bin.as_float(), cache_key.y_bin.as_float());
 
+    // Synthesize missing styles
+    let (embolden, transform) = options.synthesis(cache_key);
+
     // Select our source order
-    Render::new(&[
-        // Color outline with the first palette
-        Source::ColorOutline(0),
-        // Color bitmap with best fit selection mode
-        Source::ColorBitmap(StrikeWith::BestFit),
-        // Standard scalable outline
-        Source::Outline,
-    ])
-    // Select a subpixel format
-    .format(Format::Alpha)
-    // Apply the fractional offset
-    .offset(offset)
-    // Render the image
-    .render(&mut scaler, cache_key.glyph_id)
+    let mut image = Render::new(options.sources.sources())
+        // Select a subpixel format
+        .format(format.zeno_format())
+        // Apply the fractional offset
+        .offset(offset)
+        .embolden(embolden)
+        .transform(transform)
+        // Render the image
+        .render(&mut scaler, cache_key.glyph_id)?;
+
+    if image.content != Content::Color {
+        options.adjust(&mut image.data);
+    }
+    Some(image)
 }
 
 fn swash_outline_commands(
     font_system: &mut FontSystem,
     context: &mut ScaleContext,
     cache_key: CacheKey,
+    options: RenderOptions,
 ) -> Option<Vec<swash::zeno::Command>> {
     use swash::zeno::PathData as _;
 
@@ -78,10 +278,19 @@ fn swash_outline_commands(
         .build();
 
     // Scale the outline
-    let outline = scaler
+    let mut outline = scaler
         .scale_outline(cache_key.glyph_id)
         .or_else(|| scaler.scale_color_outline(cache_key.glyph_id))?;
 
+    // Synthesize missing styles like rasterized images
+    let (embolden, transform_opt) = options.synthesis(cache_key);
+    if embolden != 0.0 {
+        outline.embolden(embolden, embolden);
+    }
+    if let Some(transform) = transform_opt {
+        outline.transform(&transform);
+    }
+
     // Get the path information of the outline
     let path = outline.path();
 
@@ -92,8 +301,13 @@ fn swash_outline_commands(
 /// Cache for rasterizing with the swash scaler
 pub struct SwashCache {
     context: ScaleContext,
-    pub image_cache: Map<CacheKey, Option<SwashImage>>,
-    pub outline_command_cache: Map<CacheKey, Option<Vec<swash::zeno::Command>>>,
+    format: SubpixelFormat,
+    options: RenderOptions,
+    /// Images for each cache key and the format and options they were rasterized with
+    pub image_cache: Map<(CacheKey, SubpixelFormat, RenderOptions), Option<SwashImage>>,
+    /// Outline commands for each cache key and the options they were made with, which include
+    /// the synthesis of missing styles
+    pub outline_command_cache: Map<(CacheKey, RenderOptions), Option<Vec<swash::zeno::Command>>>,
 }
 
 impl SwashCache {
@@ -101,29 +315,67 @@ impl SwashCache {
     pub fn new() -> Self {
         Self {
             context: ScaleContext::new(),
+            format: SubpixelFormat::None,
+            options: RenderOptions::new(),
             image_cache: Map::new(),
             outline_command_cache: Map::new(),
         }
     }
 
+    /// Get the format that glyph outlines are rasterized with
+    pub fn format(&self) -> SubpixelFormat {
+        self.format
+    }
+
+    /// Set the format that glyph outlines are rasterized with
+    ///
+    /// Images rasterized with other formats stay in the cache.
+    pub fn set_format(&mut self, format: SubpixelFormat) {
+        self.format = format;
+    }
+
+    /// Get the options that glyphs are rasterized with
+    pub fn options(&self) -> RenderOptions {
+        self.options
+    }
+
+    /// Set the options that glyphs are rasterized with
+    ///
+    /// Images rasterized with other options stay in the cache.
+    pub fn set_options(&mut self, options: RenderOptions) {
+        self.options = options;
+    }
+
     /// Create a swash Image from a cache key, without caching results
     pub fn get_image_uncached(
         &mut self,
         font_system: &mut FontSystem,
         cache_key: CacheKey,
     ) -> Option<SwashImage> {
-        swash_image(font_system, &mut self.context, cache_key)
+        swash_image(
+            font_system,
+            &mut self.context,
+            cache_key,
+            self.format,
+            self.options,
+        )
     }
 
     /// Create a swash Image from a cache key, caching results
+    ///
+    /// The image has [`SwashContent::SubpixelMask`] content if it was rasterized with a subpixel
+    /// [`SubpixelFormat`], see [`Self::set_format`].
     pub fn get_image(
         &mut self,
         font_system: &mut FontSystem,
         cache_key: CacheKey,
     ) -> &Option<SwashImage> {
+        let (format, options) = (self.format, self.options);
         self.image_cache
-            .entry(cache_key)
-            .or_insert_with(|| swash_image(font_system, &mut self.context, cache_key))
+            .entry((cache_key, format, options))
+            .or_insert_with(|| {
+                swash_image(font_system, &mut self.context, cache_key, format, options)
+            })
     }
 
     pub fn get_outline_commands(
@@ -131,19 +383,41 @@ impl SwashCache {
         font_system: &mut FontSystem,
         cache_key: CacheKey,
     ) -> Option<&[swash::zeno::Command]> {
+        let options = self.options;
         self.outline_command_cache
-            .entry(cache_key)
-            .or_insert_with(|| swash_outline_commands(font_system, &mut self.context, cache_key))
+            .entry((cache_key, options))
+            .or_insert_with(|| {
+                swash_outline_commands(font_system, &mut self.context, cache_key, options)
+            })
             .as_deref()
     }
 
     /// Enumerate pixels in an Image, use `with_image` for better performance
+    ///
+    /// Subpixel masks are drawn with the average coverage of their channels, use
+    /// [`Self::with_subpixels`] to draw them with the coverage of each channel.
     pub fn with_pixels<F: FnMut(i32, i32, Color)>(
         &mut self,
         font_system: &mut FontSystem,
         cache_key: CacheKey,
         base: Color,
         mut f: F,
+    ) {
+        self.with_subpixels(font_system, cache_key, base, |x, y, color, _| {
+            f(x, y, color);
+        });
+    }
+
+    /// Enumerate pixels in an Image with the coverage of their red, green and blue channels
+    ///
+    /// For subpixel masks, the color is `base` with the average coverage as its alpha. For other
+    /// images, each channel has the same coverage as the alpha of the color.
+    pub fn with_subpixels<F: FnMut(i32, i32, Color, [u8; 3])>(
+        &mut self,
+        font_system: &mut FontSystem,
+        cache_key: CacheKey,
+        base: Color,
+        mut f: F,
     ) {
         if let Some(image) = self.get_image(font_system, cache_key) {
             let x = image.placement.left;
@@ -155,10 +429,12 @@ impl SwashCache {
                     for off_y in 0..image.placement.height as i32 {
                         for off_x in 0..image.placement.width as i32 {
                             //TODO: blend base alpha?
+                            let alpha = image.data[i];
                             f(
                                 x + off_x,
                                 y + off_y,
-                                Color(((image.data[i] as u32) << 24) | base.0 & 0xFF_FF_FF),
+                                Color(((alpha as u32) << 24) | base.0 & 0xFF_FF_FF),
+                                [alpha; 3],
                             );
                             i += 1;
                         }
@@ -178,15 +454,124 @@ impl SwashCache {
                                     image.data[i + 2],
                                     image.data[i + 3],
                                 ),
+                                [image.data[i + 3]; 3],
                             );
                             i += 4;
                         }
                     }
                 }
                 Content::SubpixelMask => {
-                    log::warn!("TODO: SubpixelMask");
+                    let mut i = 0;
+                    for off_y in 0..image.placement.height as i32 {
+                        for off_x in 0..image.placement.width as i32 {
+                            let coverage = [image.data[i], image.data[i + 1], image.data[i + 2]];
+                            let alpha = coverage.iter().map(|&c| c as u32).sum::<u32>() / 3;
+                            f(
+                                x + off_x,
+                                y + off_y,
+                                Color((alpha << 24) | base.0 & 0xFF_FF_FF),
+                                coverage,
+                            );
+                            i += 4;
+                        }
+                    }
                 }
             }
         }
     }
 }
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_subpixel_format() {
+    use crate::{Attrs, Buffer, Metrics};
+
+    let mut font_system = FontSystem::new();
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(14.0, 20.0));
+    buffer.set_size(&mut font_system, 100.0, 100.0);
+    buffer.set_text(&mut font_system, "W", Attrs::new());
+    let run = buffer.layout_runs().next().expect("no layout runs");
+    let cache_key = run.glyphs[0].cache_key;
+
+    let mut cache = SwashCache::new();
+    let mut alpha_pixels = 0;
+    cache.with_subpixels(
+        &mut font_system,
+        cache_key,
+        Color::rgb(0, 0, 0),
+        |_, _, _, c| {
+            assert!(c[0] == c[1] && c[1] == c[2]);
+            alpha_pixels += 1;
+        },
+    );
+    assert!(alpha_pixels > 0);
+
+    for format in [SubpixelFormat::Rgb, SubpixelFormat::Bgr] {
+        cache.set_format(format);
+        let image = cache
+            .get_image(&mut font_system, cache_key)
+            .as_ref()
+            .expect("no image");
+        assert_eq!(image.content, SwashContent::SubpixelMask);
+        let mut colored = false;
+        cache.with_subpixels(
+            &mut font_system,
+            cache_key,
+            Color::rgb(0, 0, 0),
+            |_, _, _, c| {
+                colored |= c[0] != c[2];
+            },
+        );
+        assert!(colored);
+    }
+    assert_eq!(cache.image_cache.len(), 3);
+}
+
+#[test]
+#[ignore = "needs system fonts"]
+fn test_render_options() {
+    use crate::{Attrs, Buffer, Metrics};
+
+    let mut font_system = FontSystem::new();
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(14.0, 20.0));
+    buffer.set_size(&mut font_system, 100.0, 100.0);
+    buffer.set_text(&mut font_system, "W", Attrs::new());
+    let run = buffer.layout_runs().next().expect("no layout runs");
+    let cache_key = run.glyphs[0].cache_key;
+    let mut bold_key = cache_key;
+    bold_key.synthesis.bold = true;
+
+    let mut cache = SwashCache::new();
+    let mut coverage = |cache: &mut SwashCache, cache_key| {
+        let image = cache
+            .get_image(&mut font_system, cache_key)
+            .as_ref()
+            .expect("no image");
+        image.data.iter().map(|&c| c as u32).sum::<u32>()
+    };
+    let regular = coverage(&mut cache, cache_key);
+    assert!(coverage(&mut cache, bold_key) > regular);
+    cache.set_options(RenderOptions::new().gamma(2.2));
+    assert!(coverage(&mut cache, cache_key) > regular);
+
+    // Synthesis can be turned off
+    cache.set_options(RenderOptions::new().synthetic_bold(false));
+    assert_eq!(coverage(&mut cache, bold_key), regular);
+    assert_eq!(cache.image_cache.len(), 4);
+    assert_eq!(cache.options().get_gamma(), 1.0);
+
+    // Outlines are synthesized like images
+    let regular = cache
+        .get_outline_commands(&mut font_system, cache_key)
+        .expect("no outline")
+        .to_vec();
+    assert_eq!(
+        cache.get_outline_commands(&mut font_system, bold_key),
+        Some(regular.as_slice())
+    );
+    cache.set_options(RenderOptions::new());
+    assert_ne!(
+        cache.get_outline_commands(&mut font_system, bold_key),
+        Some(regular.as_slice())
+    );
+}